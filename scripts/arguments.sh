@@ -0,0 +1,3 @@
+#!/usr/bin/env oursh
+echo $0 $# $1 $2
+false
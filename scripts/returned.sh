@@ -0,0 +1,3 @@
+echo returned
+return 3
+echo unreachable
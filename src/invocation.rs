@@ -2,25 +2,97 @@
 //!
 //!
 //!
-use std::ffi::CString;
+use std::{
+    ffi::CString,
+    io::Write,
+    path::{Path, PathBuf},
+};
 use crate::program::{
     Runtime,
-    posix::builtin::{self, Builtin},
+    posix::{expand_string, builtin::{self, Builtin}},
 };
 
+/// The Docopt usage string of the shell.
+pub const USAGE: &str = "
+The oursh utility is a command language interpreter that shall execute commands
+read from a command line string, the standard input, or a specified file.
+
+Usage:
+    oursh -c [options] <command_string> [<command_name> [<arguments>...]]
+    oursh -s [options] [<arguments>...]
+    oursh    [options] [<command_file> [<arguments>...]]
+
+By default our will read commands from the command_file operand. If there are no
+operands and the -c option is not specified, the -s option shall be assumed.
+
+Options:
+    -c --command    Read commands from the command_string operand.
+    -s              Read commands from the standard input.
+    -i              Specify that the shell is interactive.
+    --login         Act as if invoked as a login shell.
+    -h --help       Show this screen.
+    -v --verbose    Print extra information.
+    -a --ast        Print program ASTs.
+    -# --alternate  Use alternate program syntax.
+    --posix         Refuse the extensions of the language which aren't POSIX.
+    --init-file     Override the default profile.
+    --rcfile        and RC file locations for startup.
+    --noprofile     Don't load and profile code on launch.
+    --norc          Don't run $ENV or ~/.ourshrc in interactive shells.
+
+    --debug
+    --debugger
+    --dump-po-strings
+    --dump-strings
+    --noediting
+    --restricted
+    --version
+
+TODO: Read set [-abCefhmnuvx] [-o option] for a complete list of arguments.
+";
+
 /// Sourcing profile startup scripts
 ///
-/// For now we just load `.oursh_profile`.
-// TODO: Use the builtin `source` command when it's written.
+/// For now we just load `.oursh_profile`, when it exists.
 pub fn source_profile(runtime: &mut Runtime) {
     if let Some(mut path) = dirs::home_dir() {
         path.push(".oursh_profile");
-        let argv = vec![
-            CString::new("source".to_string()).unwrap(),
-            CString::new(path.to_str().unwrap()).expect("valid path string"),
-        ];
-        if let Err(e) = builtin::Dot.run(argv, runtime) {
-            eprintln!("failed to source profile: {:?}", e);
-        }
+        source(&path, runtime);
+    }
+}
+
+/// Sourcing the startup script of interactive shells
+///
+/// The script is named by `$ENV`, after parameter expansion, or is
+/// `~/.ourshrc` by default. A missing script is skipped.
+pub fn source_rc(runtime: &mut Runtime) {
+    let path = match runtime.variables.get("ENV").map(String::from) {
+        Some(env) => match expand_string(&env, runtime) {
+            Ok(path) => PathBuf::from(path),
+            Err(e) => {
+                let _ = writeln!(runtime.io.stderr(), "oursh: {}", e);
+                return;
+            },
+        },
+        None => match dirs::home_dir() {
+            Some(home) => home.join(".ourshrc"),
+            None => return,
+        },
+    };
+    source(&path, runtime);
+}
+
+// Run the script with the `.` builtin when it exists. Errors are reported,
+// but the shell keeps going.
+fn source(path: &Path, runtime: &mut Runtime) {
+    if !path.is_file() {
+        return;
+    }
+    let argv = vec![
+        CString::new(".").unwrap(),
+        CString::new(path.to_string_lossy().as_bytes()).expect("valid path string"),
+    ];
+    if let Err(e) = builtin::Dot.run(argv, runtime) {
+        eprintln!("failed to source {}: {:?}", path.display(), e);
     }
 }
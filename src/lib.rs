@@ -62,7 +62,7 @@
 //! [documentation]: https://nixpulvis.com/oursh/oursh
 //! [rustup]: https://github.com/rust-lang-nursery/rustup.rs
 //! [posix-ref]: http://pubs.opengroup.org/onlinepubs/9699919799/
-#![feature(box_patterns)]
+#![cfg_attr(test, feature(box_patterns))]
 
 extern crate nix;
 extern crate pwd;
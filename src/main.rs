@@ -10,63 +10,27 @@ use std::{
     env,
     process::{Termination, ExitCode},
     fs::File,
-    io::{self, Read},
-    cell::RefCell,
-    rc::Rc,
+    io::{self, BufReader},
+    mem::ManuallyDrop,
+    os::unix::io::FromRawFd,
+};
+use nix::{
+    sys::wait::WaitStatus,
+    unistd::Pid,
 };
-use nix::sys::wait::WaitStatus;
 use docopt::{Docopt, Value};
 use termion::is_tty;
 use oursh::{
     VERSION,
     repl,
-    invocation::source_profile,
+    invocation::{source_profile, source_rc, USAGE},
     program::{parse_and_run, Runtime, Result, Error},
-    process::{Jobs, IO},
+    process::exit_code,
 };
 
 #[cfg(feature = "history")]
 use oursh::repl::history::History;
 
-// Write the Docopt usage string.
-const USAGE: &str = "
-The oursh utility is a command language interpreter that shall execute commands
-read from a command line string, the standard input, or a specified file.
-
-Usage:
-    oursh    [options] [<command_file> [<arguments>...]]
-    oursh -s [options] [<arguments>...]
-    oursh -c [options] <command_string> [<command_name> [<arguments>...]]
-
-By default our will read commands from the command_file operand. If there are no
-operands and the -c option is not specified, the -s option shall be assumed.
-
-Options:
-    -c              Read commands from the command_string operand.
-    -s              Read commands from the standard input.
-    -i              Specify that the shell is interactive.
-    --login         Act as if invoked as a login shell.
-    -h --help       Show this screen.
-    -v --verbose    Print extra information.
-    -a --ast        Print program ASTs.
-    -# --alternate  Use alternate program syntax.
-    --posix         Run using the (strict) POSIX language by default.
-    --init-file     Override the default profile.
-    --rcfile        and RC file locations for startup.
-    --noprofile     Don't load and profile code on launch.
-    --norc
-
-    --debug
-    --debugger
-    --dump-po-strings
-    --dump-strings
-    --noediting
-    --restricted
-    --version
-
-TODO: Read set [-abCefhmnuvx] [-o option] for a complete list of arguments.
-";
-
 // Our shell, for the greater good. Ready and waiting.
 // TODO: Replace program::Result
 //
@@ -76,29 +40,30 @@ fn main() -> MainResult {
     // TODO: From sh docs:
     //     "with an extension for support of a
     //      leading  <plus-sign> ('+') as noted below."
-    let mut args = Docopt::new(USAGE)
+    let args = Docopt::new(USAGE)
                       .and_then(|d|
                           d.version(Some(VERSION.into()))
                            .argv(env::args().into_iter())
                            .parse())
                       .unwrap_or_else(|e| e.exit());
 
-    // Elementary job management.
-    let mut jobs: Jobs = Rc::new(RefCell::new(vec![]));
-
-    // Default inputs and outputs.
-    let mut io = IO::default();
-
-    #[cfg(feature = "history")]
-    let mut history = History::load();
-    let mut runtime = Runtime {
-        io,
-        jobs: &mut jobs,
-        args: &args,
-        background: false,
-        #[cfg(feature = "history")]
-        history: &mut history,
+    // The shell's state, with default inputs and outputs. A command string
+    // or script is given its name and arguments.
+    let name = if args.get_bool("--command") {
+        args.get_str("<command_name>")
+    } else {
+        args.get_str("<command_file>")
     };
+    // Like `login` starting `-oursh`, a leading `-` makes a login shell.
+    let login = env::args().next().is_some_and(|arg0| arg0.starts_with('-'));
+    let mut builder = Runtime::builder().args(args.clone()).embedded(false).login(login);
+    if !name.is_empty() {
+        builder = builder.name(name);
+    }
+    let arguments = args.get_vec("<arguments>");
+    let mut runtime = builder.positionals(arguments.into_iter().map(String::from).collect())
+                             .build()
+                             .expect("error creating runtime");
 
     // Run the profile before anything else.
     // TODO:
@@ -109,19 +74,32 @@ fn main() -> MainResult {
         source_profile(&mut runtime);
     }
 
-    if let Some(Value::Plain(Some(ref c))) = args.find("<command_string>") {
-        MainResult(parse_and_run(c, &mut runtime))
-    } else if let Some(Value::Plain(Some(ref filename))) = args.find("<command_file>") {
-        let mut file = File::open(filename)
-            .unwrap_or_else(|_| panic!("error opening file: {}", filename));
+    // Interactive shells also run their startup script.
+    if runtime.interactive && !args.get_bool("--norc") {
+        source_rc(&mut runtime);
+    }
 
-        // Fill a string buffer from the file.
-        let mut text = String::new();
-        file.read_to_string(&mut text)
-            .expect("error reading file");
+    // Only interactive shells keep a history, which may be configured by
+    // the profile.
+    #[cfg(feature = "history")]
+    if runtime.interactive {
+        runtime.history = History::load(&runtime.variables);
+    }
 
-        // Run the program.
-        MainResult(parse_and_run(&text, &mut runtime))
+    let result = if let Some(Value::Plain(Some(ref c))) = args.find("<command_string>") {
+        MainResult(parse_and_run(c, &mut runtime))
+    } else if let Some(Value::Plain(Some(ref filename))) = args.find("<command_file>") {
+        let file = match File::open(filename) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("oursh: {}: {}", filename, e);
+                return MainResult(Ok(WaitStatus::Exited(Pid::this(), 127)));
+            },
+        };
+
+        // Run the program a command at a time. A leading `#!` line is for
+        // the kernel, and skipped like any other comment.
+        MainResult(runtime.run_reader(BufReader::new(file)))
     } else {
         // Standard input file descriptor (0), used for user input from the
         // user of the shell.
@@ -138,33 +116,42 @@ fn main() -> MainResult {
             // to the user of the shell.
             let stdout = io::stdout();
 
-            // Trap SIGINT.
-            ctrlc::set_handler(move || println!()).unwrap();
+            // Trap SIGINT, so interrupting a foreground program (or the
+            // prompt) just starts a new line instead of exiting the shell.
+            ctrlc::set_handler(repl::interrupt).unwrap();
 
-            let result = repl::start(stdin, stdout, &mut io, &mut jobs, &mut args);
+            let result = repl::start(stdin, stdout, &mut runtime);
             MainResult(result)
         } else {
-            // Fill a string buffer from STDIN.
-            let mut text = String::new();
-            stdin.lock().read_to_string(&mut text).unwrap();
-
-            // Run the program.
-            MainResult(parse_and_run(&text, &mut runtime))
+            // Run the program a command at a time, reading a byte at a time
+            // so the rest of the input is left for commands like `read`.
+            let input = ManuallyDrop::new(unsafe { File::from_raw_fd(0) });
+            MainResult(runtime.run_reader(BufReader::with_capacity(1, &*input)))
         }
-    }
+    };
+
+    // Run the `EXIT` trap and save the history, as the `exit` builtin does.
+    let code = runtime.shutdown(result.code());
+    MainResult(Ok(WaitStatus::Exited(Pid::this(), code)))
 }
 
 #[derive(Debug)]
 struct MainResult(Result<WaitStatus>);
-impl Termination for MainResult {
-    fn report(self) -> ExitCode {
+impl MainResult {
+    fn code(&self) -> i32 {
         match self.0 {
-            Ok(WaitStatus::Exited(_pid, code)) => ExitCode::from(code as u8),
-            Ok(WaitStatus::Signaled(_pid, _signal, _coredump)) => ExitCode::from(128),
-            Ok(_) => ExitCode::from(0),  // TODO: Is this even remotely correct?
-            Err(Error::Read) => ExitCode::from(1),
-            Err(Error::Parse) => ExitCode::from(2),
-            Err(Error::Runtime) => ExitCode::from(127),
+            Ok(status) => exit_code(status),
+            Err(Error::Read) => 1,
+            Err(Error::Parse(_)) => 2,
+            Err(Error::Runtime) => 127,
+            Err(Error::Exit(code)) |
+            Err(Error::Return(code)) => code,
+            Err(Error::Timeout) => 124,
         }
     }
 }
+impl Termination for MainResult {
+    fn report(self) -> ExitCode {
+        ExitCode::from(self.code() as u8)
+    }
+}
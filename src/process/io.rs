@@ -1,26 +1,119 @@
-use std::os::unix::io::RawFd;
+use std::{
+    io::{self, Write},
+    fs::File,
+    process::Stdio,
+    os::unix::io::{RawFd, FromRawFd},
+};
 use nix::{
-    unistd::{dup2, close},
+    fcntl::{fcntl, FcntlArg, FdFlag},
+    unistd::{dup, dup2, close, write},
 };
 
 /// File descriptors for use in processes and threads
-#[derive(Debug, Copy, Clone)]
-pub struct IO(pub [RawFd; 3]);
+///
+/// Along with the standard ones, other file descriptors may be redirected,
+/// like after `3>file`, which are listed as `(n, fd)` in the order they were
+/// redirected, with a negative one for those which are closed.
+#[derive(Debug, Clone)]
+pub struct IO(pub [RawFd; 3], pub Vec<(RawFd, RawFd)>);
 
 impl IO {
+    /// Replace the file descriptors of this process with these, closing
+    /// any which are negative, like after `2>&-`.
+    ///
+    /// One of them may be the source of another, like after `2>&1`, so
+    /// they're all copied out of the way before any is replaced.
     pub fn dup(&self) -> Result<(), nix::Error> {
-        if self.0[0] != 0 {
-            dup2(self.0[0], 0)?;
-            close(self.0[0])?;
+        let targets = self.targets();
+        let above = targets.iter().map(|&(n, _)| n + 1).max().unwrap_or(0).max(3);
+        let mut copies = vec![];
+        for &(n, fd) in &targets {
+            if fd >= 0 && fd != n {
+                copies.push((n, Some(fcntl(fd, FcntlArg::F_DUPFD(above))?)));
+            } else {
+                copies.push((n, None));
+            }
+        }
+        for (&(n, fd), (_, copy)) in targets.iter().zip(copies) {
+            match copy {
+                Some(copy) => {
+                    dup2(copy, n)?;
+                    close(copy)?;
+                },
+                None if fd < 0 => { let _ = close(n); },
+                // A file the shell opened as this very file descriptor would
+                // still be closed when a program is run.
+                None if n > 2 => { fcntl(n, FcntlArg::F_SETFD(FdFlag::empty()))?; },
+                None => {},
+            }
         }
-        if self.0[1] != 1 {
-            dup2(self.0[1], 1)?;
-            close(self.0[1])?;
+        // The originals are no longer needed, though one may be the source
+        // of more than one of them.
+        for (i, &(_, fd)) in targets.iter().enumerate() {
+            if fd > 2 && !targets.iter().any(|&(n, _)| n == fd) &&
+               !targets[..i].iter().any(|&(_, other)| other == fd) {
+                close(fd)?;
+            }
         }
-        if self.0[2] != 2 {
-            dup2(self.0[2], 2)?;
-            close(self.0[2])?;
+        Ok(())
+    }
+
+    /// The file descriptor `n` is redirected to, if it is, negative when
+    /// it's closed.
+    pub fn get(&self, n: RawFd) -> Option<RawFd> {
+        match n {
+            0..=2 => Some(self.0[n as usize]),
+            _ => self.1.iter().rev().find(|&&(m, _)| m == n).map(|&(_, fd)| fd),
         }
+    }
+
+    /// Redirect the file descriptor `n` to `fd`, or close it when `fd` is
+    /// negative.
+    pub fn set(&mut self, n: RawFd, fd: RawFd) {
+        match n {
+            0..=2 => self.0[n as usize] = fd,
+            _ => {
+                self.1.retain(|&(m, _)| m != n);
+                self.1.push((n, fd));
+            },
+        }
+    }
+
+    // Each file descriptor along with what it's redirected to, standard
+    // ones first.
+    fn targets(&self) -> Vec<(RawFd, RawFd)> {
+        self.0.iter().enumerate().map(|(n, &fd)| (n as RawFd, fd))
+            .chain(self.1.iter().copied())
+            .collect()
+    }
+
+    /// A writer for the standard output, used by builtins.
+    pub fn stdout(&self) -> Writer {
+        Writer(self.0[1])
+    }
+
+    /// A writer for the standard error, used by builtins.
+    pub fn stderr(&self) -> Writer {
+        Writer(self.0[2])
+    }
+
+    /// A copy of the `n`th file descriptor, for a `std::process::Command`.
+    pub fn stdio(&self, n: usize) -> io::Result<Stdio> {
+        let fd = dup(self.0[n])?;
+        Ok(Stdio::from(unsafe { File::from_raw_fd(fd) }))
+    }
+}
+
+/// Writes directly to a file descriptor, which is left open when dropped.
+#[derive(Debug)]
+pub struct Writer(RawFd);
+
+impl Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(write(self.0, buf)?)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
         Ok(())
     }
 }
@@ -28,6 +121,6 @@ impl IO {
 impl Default for IO {
     fn default() -> Self {
         // [stdin, stdout, stderr]
-        IO([0, 1, 2])
+        IO([0, 1, 2], vec![])
     }
 }
@@ -2,8 +2,11 @@ use std::{
     rc::Rc,
     cell::RefCell,
 };
-use nix::sys::wait::WaitStatus;
-use crate::process::{Wait, ProcessGroup};
+use nix::{
+    sys::wait::WaitStatus,
+    unistd::Pid,
+};
+use crate::process::{Wait, ProcessGroup, signal_message};
 
 /// Shared job handling structure
 ///
@@ -11,34 +14,89 @@ use crate::process::{Wait, ProcessGroup};
 // TODO: Make into slightly better struct.
 pub type Jobs = Rc<RefCell<Vec<(String, ProcessGroup)>>>;
 
-/// Enumerate the given jobs, pruning exited, signaled or otherwise errored process groups
-pub fn retain_alive(jobs: &mut Jobs) {
-    jobs.borrow_mut().retain_mut(|job| {
-        let id = job.0.clone();
-        let body = job.1.leader().body();
-        match job.1.leader().status() {
-            Ok(WaitStatus::StillAlive) => {
-                true
-            },
-            Ok(WaitStatus::Exited(pid, code)) => {
-                println!("[{}]+\tExit({})\t{}\t{}", id, code, pid, body);
-                false
-            },
-            Ok(WaitStatus::Signaled(pid, signal, _)) => {
-                println!("[{}]+\t{}\t{}\t{}", id, signal, pid, body);
-                false
-            },
-            Ok(_) => {
-                println!("unhandled");
-                true
-            },
-            Err(e) => {
-                if nix::errno::Errno::ECHILD != e {
-                    println!("err: {:?}", e);
-                }
+/// Check on the jobs without blocking, removing the finished ones.
+///
+/// A notice is returned for each job which has finished or stopped since
+/// the last check, like `[1]+  Done\tsleep 1`. Stopped jobs are kept.
+///
+/// ```
+/// use std::{thread, time::Duration};
+/// use oursh::{process::jobs, program::Runtime};
+///
+/// let mut runtime = Runtime::builder().build().unwrap();
+/// runtime.run_str("sleep 0 & sh -c 'exit 3' &").unwrap();
+/// let mut notices = vec![];
+/// while notices.len() < 2 {
+///     thread::sleep(Duration::from_millis(10));
+///     notices.extend(jobs::reap(&runtime.jobs));
+/// }
+/// notices.sort();
+/// assert_eq!(vec!["[1]+  Done\tsleep 0", "[2]+  Exit 3\tsh -c exit 3"], notices);
+/// assert!(runtime.jobs.borrow().is_empty());
+/// ```
+pub fn reap(jobs: &Jobs) -> Vec<String> {
+    reap_with(jobs, |_, _, _| {})
+}
 
-                false
-            }
+/// Check on the jobs like [`reap`], also calling `changed` with the id, the
+/// pid of the leader, and the new status of each job whose state changed.
+///
+/// Continued jobs are only seen by `changed`, there's no notice for them.
+pub fn reap_with(jobs: &Jobs, changed: impl FnMut(&str, Pid, WaitStatus)) -> Vec<String> {
+    let changed = update(jobs, changed);
+    let mut notices = vec![];
+    jobs.borrow_mut().retain(|(id, job)| {
+        let state = job.state();
+        let finished = is_finished(state);
+        if finished || changed.contains(id) && matches!(state, WaitStatus::Stopped(..)) {
+            notices.push(format!("[{}]+  {}\t{}", id, describe(state), job.leader().body()));
         }
+        !finished
     });
+    notices
+}
+
+/// Check on the jobs without blocking, recording the new state of each one
+/// which changed and calling `changed` with its id, the pid of its leader
+/// and its new status. The ids of the jobs which changed are returned.
+///
+/// Finished jobs are kept, until they're reaped.
+pub fn update(jobs: &Jobs, mut changed: impl FnMut(&str, Pid, WaitStatus)) -> Vec<String> {
+    let mut ids = vec![];
+    for (id, job) in jobs.borrow_mut().iter_mut() {
+        // A finished job's process is gone, there's nothing more to find.
+        if is_finished(job.state()) {
+            continue;
+        }
+        let status = match job.leader().status() {
+            Ok(WaitStatus::StillAlive) => continue,
+            Ok(status) => status,
+            // The job isn't a child of this process, like in a subshell, so
+            // the last known state is all there is.
+            Err(_) => continue,
+        };
+        job.set_state(status);
+        changed(id, job.leader().pid(), status);
+        ids.push(id.clone());
+    }
+    ids
+}
+
+/// The state of a job with the status, as listed by `jobs`, like `Running`,
+/// `Stopped`, `Done` or `Exit 3`.
+pub fn describe(status: WaitStatus) -> String {
+    match status {
+        WaitStatus::Exited(_, 0) => "Done".into(),
+        WaitStatus::Exited(_, code) => format!("Exit {}", code),
+        WaitStatus::Signaled(_, signal, _) => {
+            signal_message(signal).unwrap_or_else(|| signal.as_str()).into()
+        },
+        WaitStatus::Stopped(..) => "Stopped".into(),
+        _ => "Running".into(),
+    }
+}
+
+/// Has a job with the status finished, either exiting or being killed.
+pub fn is_finished(status: WaitStatus) -> bool {
+    matches!(status, WaitStatus::Exited(..) | WaitStatus::Signaled(..))
 }
@@ -14,17 +14,23 @@
 
 use std::{
     borrow::Cow,
+    env,
     process::exit,
-    ffi::CString,
+    ffi::{CString, OsStr},
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    path::{Path, PathBuf},
 };
 use nix::{
     errno::Errno,
-    unistd::{self, execvp, getpid, Pid, ForkResult},
-    sys::wait::{waitpid, WaitStatus, WaitPidFlag},
+    unistd::{self, chdir, execve, getpid, setpgid, Pid, ForkResult},
+    sys::{
+        signal::Signal,
+        wait::{waitpid, WaitStatus, WaitPidFlag},
+    },
 };
 
 mod io;
-pub use self::io::IO;
+pub use self::io::{IO, Writer};
 pub mod jobs;
 pub use self::jobs::Jobs;
 mod session;
@@ -37,25 +43,75 @@ mod thread;
 /// The shell's main job is to run commands. Each job has various arguments, and rules about what
 /// things should be done.
 ///
-/// - TODO #4: Redirection example.
+/// A process inherits the shell's working directory, environment and standard files, unless
+/// they're set before it's spawned.
+///
+/// ```
+/// use std::ffi::CString;
+/// use oursh::process::{Process, Wait};
+///
+/// let argv = vec![CString::new("sh").unwrap(), CString::new("-c").unwrap(),
+///                 CString::new("test \"$PWD $GREETING\" = \"/tmp hi\"").unwrap()];
+/// let process = Process::new(argv)
+///     .current_dir("/tmp")
+///     .env(vec![CString::new("PWD=/tmp").unwrap(), CString::new("GREETING=hi").unwrap()])
+///     .spawn()
+///     .unwrap();
+/// assert!(matches!(process.wait(), Ok(nix::sys::wait::WaitStatus::Exited(_, 0))));
+/// ```
+///
 /// - TODO #6: Background example.
-/// - TODO #4: Environment example?
 #[derive(Debug)]
 pub struct Process {
     argv: Vec<CString>,
     pid: Pid,
+    current_dir: Option<PathBuf>,
+    env: Option<Vec<CString>>,
+    io: IO,
+    group: bool,
+    warn_cwd: bool,
 }
 
 impl Process {
     /// Create a new job from the given command.
-    // TODO #4: Return result.
     pub fn new(argv: Vec<CString>) -> Self {
         Process {
             argv,
             pid: getpid(),
+            current_dir: None,
+            env: None,
+            io: IO::default(),
+            group: false,
+            warn_cwd: false,
         }
     }
 
+    /// A process for the already running child, for the job list.
+    pub fn child(argv: Vec<CString>, pid: Pid) -> Self {
+        Process { pid, ..Process::new(argv) }
+    }
+
+    /// Run the command in the given working directory.
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    /// Run the command with exactly this environment, a list of
+    /// `NAME=value` strings, instead of the shell's own.
+    pub fn env(mut self, env: Vec<CString>) -> Self {
+        self.env = Some(env);
+        self
+    }
+
+    /// Run the command with the given (already open) file descriptors as
+    /// its stdin, stdout and stderr, and any others it redirects, which are
+    /// closed in the child.
+    pub fn io(mut self, io: IO) -> Self {
+        self.io = io;
+        self
+    }
+
     pub fn body(&self) -> String {
         self.argv.iter().map(|a| {
             a.to_string_lossy()
@@ -66,42 +122,172 @@ impl Process {
         self.pid
     }
 
-    /// Run a shell job in the background.
-    pub fn fork(argv: Vec<CString>, io: IO) -> Result<Self, nix::Error> {
+    /// Run the command in a new process group which it leads, as background
+    /// jobs are, so it isn't sent the signals of the shell's group, like an
+    /// interrupt from the terminal.
+    pub fn process_group(mut self) -> Self {
+        self.group = true;
+        self
+    }
+
+    /// Warn when the command is found in the current directory because of
+    /// an empty entry in the `$PATH`, which is easy to miss.
+    pub fn warn_cwd(mut self) -> Self {
+        self.warn_cwd = true;
+        self
+    }
+
+    /// Start running the command in a child process, without waiting for
+    /// it.
+    pub fn spawn(self) -> Result<Self, nix::Error> {
         match unsafe { unistd::fork() } {
             Ok(ForkResult::Parent { child }) => {
-                Ok(Process {
-                    argv,
-                    pid: child,
-                })
+                // Also set by the child, whichever runs first.
+                if self.group {
+                    let _ = setpgid(child, child);
+                }
+                Ok(Process { pid: child, ..self })
             },
             Ok(ForkResult::Child) => {
-                let process = Process {
-                    argv,
-                    pid: getpid(),
-                };
-                io.dup()?;
-                if let Err(e) = process.exec() {
-                    match e {
-                        Errno::ENOENT => {
-                            let name = process.argv[0].to_string_lossy();
-                            eprintln!("oursh: {}: command not found", name);
-                            exit(127);
-                        },
-                        _ => exit(128),
+                let process = Process { pid: getpid(), ..self };
+                if process.group {
+                    let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+                }
+                if let Some(ref dir) = process.current_dir {
+                    if let Err(e) = chdir(dir) {
+                        eprintln!("oursh: {}: {}", dir.display(), e.desc());
+                        exit(1);
                     }
-                } else {
-                    unreachable!()
                 }
+                process.io.dup()?;
+                // Like the shell itself, a command which can't be found has
+                // the status 127, and one which can't be run 126.
+                let e = process.exec().expect_err("exec returned");
+                let name = process.argv[0].to_string_lossy();
+                let (message, code) = match e {
+                    Errno::ENOENT if name.contains('/') => ("no such file or directory", 127),
+                    Errno::ENOENT => ("command not found", 127),
+                    Errno::EACCES => ("permission denied", 126),
+                    e => (e.desc(), 126),
+                };
+                eprintln!("oursh: {}: {}", name, message);
+                exit(code)
             },
             Err(e) => Err(e),
         }
     }
 
-    fn exec(&self) -> Result<(), nix::Error> {
-        execvp(&self.argv[0], &self.argv.iter()
-                                        .map(|a| a.as_c_str())
-                                        .collect::<Vec<_>>()[..]).map(|_| ())
+    /// Replace this process with the command, searching the `PATH` of its
+    /// environment when the name doesn't contain a `/`.
+    ///
+    /// Only the environment is applied, the working directory and files
+    /// are the current process's. This only returns if the command
+    /// couldn't be executed.
+    pub fn exec(&self) -> Result<(), nix::Error> {
+        let argv = self.argv.iter().map(|a| a.as_c_str()).collect::<Vec<_>>();
+        let inherited;
+        let env = match self.env {
+            Some(ref env) => env,
+            None => {
+                inherited = env::vars_os().filter_map(|(name, value)| {
+                    let mut pair = name.as_bytes().to_vec();
+                    pair.push(b'=');
+                    pair.extend_from_slice(value.as_bytes());
+                    CString::new(pair).ok()
+                }).collect::<Vec<_>>();
+                &inherited
+            },
+        };
+        let env = env.iter().map(|e| e.as_c_str()).collect::<Vec<_>>();
+        let name = self.argv[0].to_bytes();
+        if name.contains(&b'/') {
+            return execve(&self.argv[0], &argv, &env).map(|_| ());
+        }
+
+        let path = env.iter()
+                      .find_map(|e| e.to_bytes().strip_prefix(b"PATH="))
+                      .unwrap_or(b"/usr/bin:/bin");
+        let mut error = Errno::ENOENT;
+        for entry in path.split(|b| *b == b':') {
+            let file = path_dir(OsStr::from_bytes(entry)).join(OsStr::from_bytes(name));
+            if self.warn_cwd && entry.is_empty() && file.is_file() {
+                eprintln!("oursh: warning: {}: found in the current directory, \
+                           by an empty entry in $PATH", String::from_utf8_lossy(name));
+            }
+            let file = CString::new(file.into_os_string().into_vec())
+                .expect("path with nul byte");
+            match execve(&file, &argv, &env) {
+                // Keep looking, but remember a permission error over a
+                // missing file.
+                Err(Errno::EACCES) => error = Errno::EACCES,
+                Err(_) => {},
+                Ok(_) => unreachable!(),
+            }
+        }
+        Err(error)
+    }
+}
+
+/// The directories of a `$PATH` to search for commands in, in order. An
+/// empty entry, from a leading or trailing `:` or a `::`, is the current
+/// directory.
+///
+/// ```
+/// use std::{ffi::OsStr, path::Path};
+/// use oursh::process::path_dirs;
+///
+/// let dirs = path_dirs(OsStr::new(":/bin::/usr/bin:")).collect::<Vec<_>>();
+/// assert_eq!(vec![".", "/bin", ".", "/usr/bin", "."].into_iter().map(Path::new)
+///                                                    .collect::<Vec<_>>(),
+///            dirs);
+/// ```
+pub fn path_dirs(path: &OsStr) -> impl Iterator<Item = &Path> {
+    path.as_bytes().split(|b| *b == b':').map(|entry| path_dir(OsStr::from_bytes(entry)))
+}
+
+// The directory of an entry of a `$PATH`.
+fn path_dir(entry: &OsStr) -> &Path {
+    if entry.is_empty() {
+        Path::new(".")
+    } else {
+        Path::new(entry)
+    }
+}
+
+/// The exit status of a command, as used by the shell's conditions.
+///
+/// Commands killed by a signal have the status `128` plus the signal's
+/// number. Commands which haven't finished, like those still running in the
+/// background or stopped, succeed, so the shell carries on.
+pub fn exit_code(status: WaitStatus) -> i32 {
+    match status {
+        WaitStatus::Exited(_, code) => code,
+        WaitStatus::Signaled(_, signal, _) => 128 + signal as i32,
+        _ => 0,
+    }
+}
+
+/// The message printed when a command is killed by the signal, if any.
+///
+/// Like other shells, nothing is printed for an interrupt or a broken pipe,
+/// since the cause is obvious.
+pub fn signal_message(signal: Signal) -> Option<&'static str> {
+    match signal {
+        Signal::SIGINT | Signal::SIGPIPE => None,
+        Signal::SIGHUP => Some("Hangup"),
+        Signal::SIGQUIT => Some("Quit"),
+        Signal::SIGILL => Some("Illegal instruction"),
+        Signal::SIGTRAP => Some("Trace/breakpoint trap"),
+        Signal::SIGABRT => Some("Aborted"),
+        Signal::SIGBUS => Some("Bus error"),
+        Signal::SIGFPE => Some("Floating point exception"),
+        Signal::SIGKILL => Some("Killed"),
+        Signal::SIGUSR1 => Some("User defined signal 1"),
+        Signal::SIGSEGV => Some("Segmentation fault"),
+        Signal::SIGUSR2 => Some("User defined signal 2"),
+        Signal::SIGALRM => Some("Alarm clock"),
+        Signal::SIGTERM => Some("Terminated"),
+        _ => Some(signal.as_str()),
     }
 }
 
@@ -116,7 +302,8 @@ impl Wait for Pid {
     }
 
     fn status(&self) -> nix::Result<WaitStatus> {
-        waitpid(Some(*self), Some(WaitPidFlag::WNOHANG))
+        waitpid(Some(*self), Some(WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED |
+                                  WaitPidFlag::WCONTINUED))
     }
 }
 
@@ -142,14 +329,32 @@ impl Wait for Process {
 /// using the system call getpgrp(), or, equivalently, getpgid(0). One finds the process group ID
 /// of process p using getpgid(p).
 #[derive(Debug)]
-pub struct ProcessGroup(pub Process); // TODO: Make a sorted vector of Process?
+pub struct ProcessGroup {
+    leader: Process, // TODO: Make a sorted vector of Process?
+    state: WaitStatus,
+}
 
 impl ProcessGroup {
+    /// A group led by the process, which is taken to be running.
+    pub fn new(leader: Process) -> Self {
+        ProcessGroup { leader, state: WaitStatus::StillAlive }
+    }
+
     pub fn leader(&self) -> &Process {
-        &self.0
+        &self.leader
     }
 
     pub fn leader_mut(&mut self) -> &mut Process {
-        &mut self.0
+        &mut self.leader
+    }
+
+    /// The last known status of the leader, as found when the jobs were
+    /// last checked on, `StillAlive` while it's running.
+    pub fn state(&self) -> WaitStatus {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: WaitStatus) {
+        self.state = state;
     }
 }
@@ -51,13 +51,15 @@ impl super::Run for Command {
             CString::new(a).expect("error reading argument")
         }).collect();
 
+        let job = Process::new(argv).env(runtime.variables.environment())
+                                    .io(runtime.io.clone())
+                                    .spawn()
+                                    .map_err(|_| Error::Runtime)?;
         let status = if runtime.background {
-            let job = Process::fork(argv, runtime.io).map_err(|_| Error::Runtime)?;
             let status = job.status();
-            runtime.jobs.borrow_mut().push(("???".into(), ProcessGroup(job)));
+            runtime.add_job(ProcessGroup::new(job));
             status
         } else {
-            let job = Process::fork(argv, runtime.io).map_err(|_| Error::Runtime)?;
             job.wait()
         };
         match status {
@@ -53,22 +53,24 @@
 //! }
 //! ```
 //!
+//! The languages of oursh itself, `posix` and `modern`, are run by the
+//! shell directly instead of by an interpreter, from either `{#!posix; ...}`
+//! or `{#posix ...}`.
+//!
 //! Strict POSIX compatibility can be enabled by removing this feature alone.
 //!
-//! - TODO #5: Parse sequence of programs from stream.
 //! - TODO #5: Partial parses for readline-ish / syntax highlighting.
 
 use std::{
     result,
     ffi::CString,
-    fmt::Debug,
+    fmt::{self, Debug},
     io::BufRead,
 };
 use nix::{
     unistd::Pid,
     sys::wait::WaitStatus,
 };
-use crate::process::jobs;
 
 /// Convenience type for results with program errors.
 pub type Result<T> = result::Result<T, Error>;
@@ -80,12 +82,42 @@ pub enum Error {
     // TODO: Wrap an io error?
     Read,
     /// An error within the lexer or parser.
-    // TODO: Wrap both our lex::Error and ParseError.
-    Parse,
+    Parse(SyntaxError),
     /// An error encountered during the evaluation of a program.
     // TODO: Propagate status.
     // TODO: Just wrap an Wait/ExitStatus?
     Runtime,
+    /// Not really an error, the `exit` builtin was run by an embedded shell
+    /// with the given status. The rest of the program is skipped, leaving
+    /// the embedding program to decide what to do.
+    Exit(i32),
+    /// Not really an error either, the `return` builtin was run by a
+    /// function or a script run by `.`, with the given status. The rest of
+    /// it is skipped.
+    Return(i32),
+    /// The program was stopped for running longer than the timeout given to
+    /// [`Runtime::run_timeout`].
+    Timeout,
+}
+
+/// A description of invalid syntax, for tools to report however they like.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    /// The byte offsets of the start and end of the invalid text.
+    pub location: (usize, usize),
+    /// The tokens which would have been valid instead, if any are known.
+    pub expected: Vec<String>,
+    /// A message describing the error, like `unexpected token ...`.
+    pub message: String,
+    /// The text ended before the syntax was complete, so more of it could
+    /// still make it valid, like after `if true; then`.
+    pub incomplete: bool,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
 }
 
 pub trait Run {
@@ -110,6 +142,52 @@ pub trait Program: Sized + Debug + Run {
     /// Parse a whole program from the given `reader`.
     fn parse<R: BufRead>(reader: R) -> Result<Self>;
 
+    /// Parse the next complete command from the `reader`, reading only as
+    /// many lines as it takes, or return `None` at the end of the input.
+    ///
+    /// A line may have many commands, and a command may have many lines,
+    /// like an `if` or a quote with a newline in it. Blank and comment lines
+    /// before a command are skipped. Each program can be run before the next
+    /// is parsed, so the input is never read all at once.
+    ///
+    /// ```
+    /// use oursh::program::{Program, PosixProgram};
+    ///
+    /// let mut reader = b"ls; date\n\n# if\nif true\nthen\n  echo 'a\nb'\nfi\n" as &[u8];
+    /// let first = PosixProgram::parse_next(&mut reader).unwrap().unwrap();
+    /// assert_eq!(2, first.commands().len());
+    /// let second = PosixProgram::parse_next(&mut reader).unwrap().unwrap();
+    /// assert_eq!(1, second.commands().len());
+    /// assert!(PosixProgram::parse_next(&mut reader).is_none());
+    /// ```
+    fn parse_next<R: BufRead>(reader: &mut R) -> Option<Result<Self>> {
+        let mut text = String::new();
+        loop {
+            let start = text.len();
+            match reader.read_line(&mut text) {
+                Ok(0) if text.is_empty() => return None,
+                // Whatever is left at the end is parsed as it is, even if
+                // it's incomplete.
+                Ok(0) => return Some(Self::parse(text.as_bytes())),
+                Ok(_) => {},
+                Err(_) => return Some(Err(Error::Read)),
+            }
+
+            let line = text[start..].trim_start();
+            if start == 0 && (line.is_empty() || line.starts_with('#')) {
+                text.clear();
+                continue;
+            }
+            if continues(&text) {
+                continue;
+            }
+            match Self::parse(text.as_bytes()) {
+                Err(Error::Parse(e)) if e.incomplete => {},
+                result => return Some(result),
+            }
+        }
+    }
+
     /// Return a list of all the commands in this program.
     fn commands(&self) -> &[Self::Command];
 }
@@ -124,6 +202,15 @@ impl<P: Program> Run for P {
     }
 }
 
+// Does the text end with a `\` escaping its last newline, continuing the
+// line onto the next.
+fn continues(text: &str) -> bool {
+    match text.strip_suffix('\n') {
+        Some(text) => text.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1,
+        None => false,
+    }
+}
+
 /// A command is a task given by the user as part of a [`Program`](Program).
 ///
 /// Each command is handled by a [`Process`](crate::process::Process), and a
@@ -198,7 +285,7 @@ pub fn parse<P: Program, R: BufRead>(reader: R) -> Result<P> {
 // language semantics are somewhat tricky subjects.
 
 pub mod runtime;
-pub use self::runtime::Runtime;
+pub use self::runtime::{Runtime, Builder, Options, Output, Variables};
 
 pub mod basic;
 pub use self::basic::Program as BasicProgram;
@@ -214,23 +301,21 @@ pub fn parse_and_run(text: &str, runtime: &mut Runtime)
     // Parse with the primary grammar and run each command in order.
     let program = match parse_primary(text.as_bytes()) {
         Ok(program) => program,
+        Err(Error::Parse(e)) => {
+            eprintln!("oursh: {}", e);
+            return Err(Error::Parse(e));
+        },
         Err(e) => {
             eprintln!("{:?}: {:#?}", e, text);
             return Err(e);
-        }
+        },
     };
 
-    #[cfg(feature = "history")]
-    runtime.history.add(text, 1);
-
     // Print the program if the flag is given.
     if runtime.args.get_bool("--ast") {
         eprintln!("{:#?}", program);
     }
 
     // Run it!
-    let result = program.run(runtime);
-    // Check up on the background jobs.
-    jobs::retain_alive(runtime.jobs);
-    result
+    runtime.run(&program)
 }
@@ -0,0 +1,30 @@
+//! Abstract Syntax Tree for the modern language.
+
+/// A program is the result of parsing a sequence of commands.
+#[derive(Debug, Clone)]
+pub struct Program(pub Vec<Command>);
+
+/// A command of the modern language.
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// A command with its arguments, run just like a POSIX simple command.
+    ///
+    /// ```sh
+    /// echo "hello $USER"
+    /// ```
+    Simple(Vec<String>),
+
+    /// Set a variable, which is local to the enclosing block.
+    ///
+    /// ```sh
+    /// let greeting = hello
+    /// ```
+    Let(String, String),
+
+    /// A sequence of commands, with their own scope for `let`.
+    ///
+    /// ```sh
+    /// { let x = 1; echo $x }
+    /// ```
+    Block(Vec<Command>),
+}
@@ -0,0 +1,182 @@
+//! The modern language, a home for the extensions to the POSIX language.
+//!
+//! For now it's a small language of its own, with commands separated by
+//! `;`, blocks of commands in `{ }`, and variables set with `let`, which is
+//! always a keyword. A variable set with `let` only lasts until the end of
+//! its block.
+//!
+//! ```sh
+//! let greeting = hello
+//! { let name = world; echo $greeting $name }
+//! echo $name   # Nothing, `name` is out of scope.
+//! ```
+//!
+//! Commands are run exactly like the simple commands of the POSIX
+//! language, with the same builtins, functions, expansions and jobs.
+//!
+//! ```
+//! use oursh::program::{Program, ModernProgram, Runtime};
+//!
+//! let program = ModernProgram::parse(b"let x = 1; { let x = 2; test $x = 2 }" as &[u8]).unwrap();
+//! assert_eq!(2, program.commands().len());
+//!
+//! let mut runtime = Runtime::builder().build().unwrap();
+//! runtime.run(&program).unwrap();
+//! assert_eq!(Some("1"), runtime.variables.get("x"));
+//! ```
+use std::{
+    io::{BufRead, Write},
+    result,
+};
+use lalrpop_util::ParseError;
+use nix::{
+    unistd::Pid,
+    sys::wait::WaitStatus,
+};
+use crate::program::{
+    Runtime, Result, Error, Run, SyntaxError,
+    posix::{self, expand_assignment, expansion_error},
+};
+
+// Re-exports.
+pub use self::ast::Program;
+pub use self::ast::Command;
+
+/// Parse the text as a modern program without running it.
+pub fn parse_program(text: &str) -> result::Result<Program, SyntaxError> {
+    parse::ProgramParser::new().parse(text).map_err(|e| {
+        let incomplete = matches!(e, ParseError::UnrecognizedEOF { .. });
+        let (location, expected, message) = match e {
+            ParseError::InvalidToken { location } => {
+                ((location, location), vec![],
+                 format!("invalid token found at {}", location))
+            },
+            ParseError::UnrecognizedToken { token: (s, t, e), expected } => {
+                let message = format!("unexpected token {} found at {}-{}, expecting one of: {}",
+                                      t, s, e, expected.join(", "));
+                ((s, e), expected, message)
+            },
+            ParseError::UnrecognizedEOF { location, expected } => {
+                let message = format!("unexpected EOF found at {}, expecting one of: {}",
+                                      location, expected.join(", "));
+                ((location, location), expected, message)
+            },
+            ParseError::ExtraToken { token: (s, t, e) } => {
+                ((s, e), vec![], format!("extra token {} found at {}", t, s))
+            },
+            ParseError::User { error } => ((0, 0), vec![], error.to_string()),
+        };
+        SyntaxError { location, expected, message, incomplete }
+    })
+}
+
+impl super::Program for Program {
+    type Command = Command;
+
+    fn parse<R: BufRead>(mut reader: R) -> Result<Self> {
+        let mut string = String::new();
+        if reader.read_to_string(&mut string).is_err() {
+            return Err(Error::Read);
+        }
+        parse_program(&string).map_err(Error::Parse)
+    }
+
+    fn commands(&self) -> &[Self::Command] {
+        &self.0[..]
+    }
+}
+
+impl super::Command for Command {}
+
+impl Run for Command {
+    fn run(&self, runtime: &mut Runtime) -> Result<WaitStatus> {
+        match self {
+            Command::Simple(words) => {
+                let words = words.iter().map(|w| posix::ast::Word(w.clone())).collect();
+                posix::Command::Simple(vec![], words, vec![]).run(runtime)
+            },
+            Command::Let(name, _) if runtime.variables.is_readonly(name) => {
+                let _ = writeln!(runtime.io.stderr(), "oursh: {}: readonly variable", name);
+                Ok(WaitStatus::Exited(Pid::this(), 1))
+            },
+            Command::Let(name, value) => {
+                match expand_assignment(value, runtime) {
+                    Ok(value) => runtime.variables.set(name, value),
+                    Err(e) => return expansion_error(e, runtime),
+                }
+                Ok(WaitStatus::Exited(Pid::this(), 0))
+            },
+            Command::Block(commands) => {
+                // Remember the variables the block sets, to restore them
+                // after.
+                let saved = commands.iter().filter_map(|command| match command {
+                    Command::Let(name, _) => Some(name),
+                    _ => None,
+                }).map(|name| {
+                    let old = runtime.variables.get(name).map(String::from);
+                    (name, old, runtime.variables.is_exported(name))
+                }).collect::<Vec<_>>();
+
+                let mut result = Ok(WaitStatus::Exited(Pid::this(), 0));
+                for command in commands {
+                    result = command.run(runtime);
+                    if result.is_err() {
+                        break;
+                    }
+                }
+
+                for (name, old, exported) in saved.into_iter().rev() {
+                    match old {
+                        Some(value) => runtime.variables.set(name, value),
+                        None => runtime.variables.unset(name),
+                    }
+                    if exported {
+                        runtime.variables.export(name);
+                    }
+                }
+                result
+            },
+        }
+    }
+}
+
+// The modern AST data structures.
+pub mod ast;
+
+lalrpop_mod!(
+    #[allow(clippy::all)]
+    #[allow(unknown_lints)]
+    /// LALRPOP generated parser module.
+    pub parse, "/program/modern/mod.rs");
+
+#[cfg(test)]
+mod tests {
+    use crate::program::Runtime;
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert!(parse_program("").unwrap().0.is_empty());
+        assert_eq!(2, parse_program("ls -la; echo 'a b' \"$c\"").unwrap().0.len());
+        assert_eq!(2, parse_program("let x = 1; { let y = $x; echo }").unwrap().0.len());
+        assert!(matches!(parse_program("{ ls; ; }").unwrap().0[0],
+                         Command::Block(ref c) if c.len() == 1));
+        assert!(matches!(parse_program("x=1").unwrap().0[0], Command::Simple(_)));
+        // `let` is always a keyword.
+        assert!(parse_program("let x=1").is_err());
+
+        let error = parse_program("let = 1").unwrap_err();
+        assert_eq!((4, 5), error.location);
+        assert!(parse_program("{ ls").is_err());
+    }
+
+    #[test]
+    fn scoped_let() {
+        let mut runtime = Runtime::builder().env_clear().build().unwrap();
+        let program = parse_program("let x = 1; { let x = $x$x; let y = 2; test $x$y = 112 }").unwrap();
+        let status = runtime.run(&program).unwrap();
+        assert!(matches!(status, WaitStatus::Exited(_, 0)));
+        assert_eq!(Some("1"), runtime.variables.get("x"));
+        assert_eq!(None, runtime.variables.get("y"));
+    }
+}
@@ -0,0 +1,435 @@
+//! Arithmetic expressions, like those of the `(( expr ))` command and of
+//! arithmetic expansion, `$(( expr ))`.
+//!
+//! Expressions are written as in C, with signed 64 bit integers for values,
+//! which are decimal, octal with a leading `0`, or hexadecimal with a leading
+//! `0x`. Variables are named without a `$`, and a variable which is unset or
+//! empty is zero.
+//!
+//! ```sh
+//! (( i = i + 1 ))
+//! (( x > 3 && y != 0 ))
+//! (( n = n < 0 ? -n : n, n *= 2 ))
+//! echo $(( (i + 1) % 3 ))
+//! ```
+//!
+//! From lowest to highest precedence the operators are `,`, the assignments
+//! `=` `*=` `/=` `%=` `+=` `-=` `<<=` `>>=` `&=` `^=` `|=`, the conditional
+//! `?:`, `||`, `&&`, `|`, `^`, `&`, `==` `!=`, `<` `<=` `>` `>=`, `<<` `>>`,
+//! `+` `-`, `*` `/` `%`, the unary `!` `~` `+` `-` and prefix `++` `--`, and
+//! finally the postfix `++` `--`. Overflow wraps around.
+use std::fmt;
+use crate::program::Variables;
+
+/// Evaluate the expression, assigning to the variables as it says.
+///
+/// An empty expression is zero. Errors describe a syntax error, division by
+/// zero, or a variable whose value isn't a number.
+pub(crate) fn evaluate(expression: &str, variables: &mut Variables) -> Result<i64, String> {
+    let mut evaluator = Evaluator {
+        tokens: tokenize(expression)?,
+        next: 0,
+        variables,
+        skip: false,
+    };
+    if evaluator.tokens.is_empty() {
+        return Ok(0);
+    }
+    let value = evaluator.expression()?.value;
+    match evaluator.tokens.get(evaluator.next) {
+        Some(token) => Err(format!("syntax error: unexpected `{}'", token)),
+        None => Ok(value),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Operator(operator) => write!(f, "{}", operator),
+        }
+    }
+}
+
+// Longer operators come first, so they're matched before their prefixes.
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=",
+    "&&", "||", "==", "!=", "<=", ">=", "<<", ">>", "++", "--",
+    "*=", "/=", "%=", "+=", "-=", "&=", "^=", "|=",
+    "*", "/", "%", "+", "-", "<", ">", "&", "^", "|", "!", "~", "?", ":", "=",
+    "(", ")", ",",
+];
+
+const ASSIGNMENTS: &[&str] = &[
+    "=", "*=", "/=", "%=", "+=", "-=", "<<=", ">>=", "&=", "^=", "|=",
+];
+
+// The binary operators, from lowest to highest precedence.
+const BINARY: &[&[&str]] = &[
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = expression.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_alphanumeric() || c == '_' {
+            let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                          .unwrap_or(rest.len());
+            let word = &rest[..len];
+            if c.is_ascii_digit() {
+                tokens.push(Token::Number(number(word)?));
+            } else {
+                tokens.push(Token::Name(word.into()));
+            }
+            len
+        } else {
+            match OPERATORS.iter().find(|o| rest.starts_with(*o)) {
+                Some(operator) => {
+                    tokens.push(Token::Operator(operator));
+                    operator.len()
+                },
+                None => return Err(format!("syntax error: invalid character `{}'", c)),
+            }
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+// Parse an integer constant, with an optional sign.
+fn number(text: &str) -> Result<i64, String> {
+    let trimmed = text.trim();
+    let (negative, digits) = match trimmed.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+    };
+    let parsed = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        u64::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse()
+    };
+    match parsed {
+        Ok(n) if negative => Ok((n as i64).wrapping_neg()),
+        Ok(n) => Ok(n as i64),
+        Err(_) => Err(format!("{}: invalid number", text)),
+    }
+}
+
+// The value of a part of an expression, and the variable it is, if it's
+// just a name that can be assigned to.
+struct Operand {
+    value: i64,
+    name: Option<String>,
+}
+
+impl From<i64> for Operand {
+    fn from(value: i64) -> Self {
+        Operand { value, name: None }
+    }
+}
+
+// A recursive descent parser, which evaluates as it goes.
+struct Evaluator<'a> {
+    tokens: Vec<Token>,
+    next: usize,
+    variables: &'a mut Variables,
+    // Is this the side of a `&&`, `||` or `?:` which isn't taken, and so
+    // has no effect.
+    skip: bool,
+}
+
+impl Evaluator<'_> {
+    // expression := assignment (`,` assignment)*
+    fn expression(&mut self) -> Result<Operand, String> {
+        let mut operand = self.assignment()?;
+        while self.operator(&[","]).is_some() {
+            operand = self.assignment()?.value.into();
+        }
+        Ok(operand)
+    }
+
+    // assignment := conditional (ASSIGNMENT assignment)?
+    fn assignment(&mut self) -> Result<Operand, String> {
+        let left = self.conditional()?;
+        let operator = match self.operator(ASSIGNMENTS) {
+            Some(operator) => operator,
+            None => return Ok(left),
+        };
+        let name = left.name.ok_or("attempted assignment to non-variable")?;
+        let right = self.assignment()?.value;
+        let value = match operator {
+            "=" => right,
+            operator => self.apply(&operator[..operator.len() - 1], left.value, right)?,
+        };
+        self.assign(&name, value)?;
+        Ok(value.into())
+    }
+
+    // conditional := binary (`?` expression `:` conditional)?
+    fn conditional(&mut self) -> Result<Operand, String> {
+        let condition = self.binary(0)?;
+        if self.operator(&["?"]).is_none() {
+            return Ok(condition);
+        }
+        let taken = condition.value != 0;
+        let then = self.skipping(!taken, |e| e.expression())?.value;
+        self.expect(":")?;
+        let els = self.skipping(taken, |e| e.conditional())?.value;
+        Ok(if taken { then } else { els }.into())
+    }
+
+    // binary := unary (BINARY unary)*, by the precedence of the operators.
+    fn binary(&mut self, level: usize) -> Result<Operand, String> {
+        if level == BINARY.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(operator) = self.operator(BINARY[level]) {
+            // The right side of `&&` and `||` only counts when the left
+            // doesn't already decide the result.
+            let skip = match operator {
+                "&&" => left.value == 0,
+                "||" => left.value != 0,
+                _ => false,
+            };
+            let right = self.skipping(skip, |e| e.binary(level + 1))?.value;
+            left = self.apply(operator, left.value, right)?.into();
+        }
+        Ok(left)
+    }
+
+    // unary := (`!` | `~` | `+` | `-` | `++` | `--`) unary | postfix
+    fn unary(&mut self) -> Result<Operand, String> {
+        let operator = match self.operator(&["!", "~", "+", "-", "++", "--"]) {
+            Some(operator) => operator,
+            None => return self.postfix(),
+        };
+        let operand = self.unary()?;
+        let value = match operator {
+            "!" => (operand.value == 0) as i64,
+            "~" => !operand.value,
+            "+" => operand.value,
+            "-" => operand.value.wrapping_neg(),
+            _ => {
+                let name = operand.name.ok_or("attempted assignment to non-variable")?;
+                let value = self.increment(operator, operand.value);
+                self.assign(&name, value)?;
+                value
+            },
+        };
+        Ok(value.into())
+    }
+
+    // postfix := primary (`++` | `--`)?
+    fn postfix(&mut self) -> Result<Operand, String> {
+        let operand = self.primary()?;
+        let name = match &operand.name {
+            Some(name) => name.clone(),
+            None => return Ok(operand),
+        };
+        match self.operator(&["++", "--"]) {
+            Some(operator) => {
+                let value = self.increment(operator, operand.value);
+                self.assign(&name, value)?;
+                Ok(operand.value.into())
+            },
+            None => Ok(operand),
+        }
+    }
+
+    // primary := NUMBER | NAME | `(` expression `)`
+    fn primary(&mut self) -> Result<Operand, String> {
+        match self.tokens.get(self.next).cloned() {
+            Some(Token::Number(value)) => {
+                self.next += 1;
+                Ok(value.into())
+            },
+            Some(Token::Name(name)) => {
+                self.next += 1;
+                let value = match self.variables.get(&name) {
+                    Some(value) if !value.trim().is_empty() => number(value)?,
+                    _ => 0,
+                };
+                Ok(Operand { value, name: Some(name) })
+            },
+            Some(Token::Operator("(")) => {
+                self.next += 1;
+                let value = self.expression()?.value;
+                self.expect(")")?;
+                Ok(value.into())
+            },
+            Some(token) => Err(format!("syntax error: unexpected `{}'", token)),
+            None => Err("syntax error: unexpected end of expression".into()),
+        }
+    }
+
+    fn apply(&self, operator: &str, left: i64, right: i64) -> Result<i64, String> {
+        let value = match operator {
+            "||" => (left != 0 || right != 0) as i64,
+            "&&" => (left != 0 && right != 0) as i64,
+            "|" => left | right,
+            "^" => left ^ right,
+            "&" => left & right,
+            "==" => (left == right) as i64,
+            "!=" => (left != right) as i64,
+            "<" => (left < right) as i64,
+            "<=" => (left <= right) as i64,
+            ">" => (left > right) as i64,
+            ">=" => (left >= right) as i64,
+            "<<" => left.wrapping_shl(right as u32),
+            ">>" => left.wrapping_shr(right as u32),
+            "+" => left.wrapping_add(right),
+            "-" => left.wrapping_sub(right),
+            "*" => left.wrapping_mul(right),
+            // Division by zero is only an error when it's evaluated.
+            "/" | "%" if right == 0 && self.skip => 0,
+            "/" | "%" if right == 0 => return Err("division by zero".into()),
+            "/" => left.wrapping_div(right),
+            "%" => left.wrapping_rem(right),
+            _ => unreachable!("unknown operator {}", operator),
+        };
+        Ok(value)
+    }
+
+    fn increment(&self, operator: &str, value: i64) -> i64 {
+        match operator {
+            "++" => value.wrapping_add(1),
+            _ => value.wrapping_sub(1),
+        }
+    }
+
+    fn assign(&mut self, name: &str, value: i64) -> Result<(), String> {
+        if self.skip {
+            return Ok(());
+        }
+        if self.variables.is_readonly(name) {
+            return Err(format!("{}: readonly variable", name));
+        }
+        self.variables.set(name, value.to_string());
+        Ok(())
+    }
+
+    // Evaluate part of the expression, without effect if `skip` is true.
+    fn skipping<F>(&mut self, skip: bool, evaluate: F) -> Result<Operand, String>
+        where F: FnOnce(&mut Self) -> Result<Operand, String>
+    {
+        let outer = self.skip;
+        self.skip = outer || skip;
+        let result = evaluate(self);
+        self.skip = outer;
+        result
+    }
+
+    // Consume the next token if it's one of the operators.
+    fn operator(&mut self, operators: &[&'static str]) -> Option<&'static str> {
+        match self.tokens.get(self.next) {
+            Some(Token::Operator(operator)) if operators.contains(operator) => {
+                self.next += 1;
+                Some(operator)
+            },
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, operator: &'static str) -> Result<(), String> {
+        match self.operator(&[operator]) {
+            Some(_) => Ok(()),
+            None => match self.tokens.get(self.next) {
+                Some(token) => Err(format!("syntax error: unexpected `{}'", token)),
+                None => Err("syntax error: unexpected end of expression".into()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expression: &str) -> Result<i64, String> {
+        evaluate(expression, &mut Variables::default())
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(Ok(0), eval(""));
+        assert_eq!(Ok(42), eval(" 42 "));
+        assert_eq!(Ok(8), eval("010"));
+        assert_eq!(Ok(255), eval("0xff"));
+        assert!(eval("09").is_err());
+        assert!(eval("1a").is_err());
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(Ok(7), eval("1 + 2 * 3"));
+        assert_eq!(Ok(9), eval("(1 + 2) * 3"));
+        assert_eq!(Ok(1), eval("1 + 2 == 3"));
+        assert_eq!(Ok(1), eval("1 < 2 && 2 < 3 || 0"));
+        assert_eq!(Ok(-3), eval("-1 - 2"));
+        assert_eq!(Ok(2), eval("8 >> 1 + 1"));
+        assert_eq!(Ok(1), eval("!0"));
+        assert_eq!(Ok(-1), eval("~0"));
+        assert_eq!(Ok(2), eval("0 ? 1 : 2"));
+        assert_eq!(Ok(3), eval("1, 2, 3"));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(eval("1 / 0").is_err());
+        assert!(eval("1 % 0").is_err());
+        assert!(eval("1 +").is_err());
+        assert!(eval("(1").is_err());
+        assert!(eval("1 2").is_err());
+        assert!(eval("1 = 2").is_err());
+        assert!(eval("$x").is_err());
+        // The side which isn't taken has no errors.
+        assert_eq!(Ok(0), eval("0 && 1 / 0"));
+        assert_eq!(Ok(1), eval("1 ? 1 : 1 / 0"));
+    }
+
+    #[test]
+    fn variables() {
+        let mut variables = Variables::default();
+        variables.set("x", "5");
+        variables.set("empty", "");
+        assert_eq!(Ok(10), evaluate("x * 2 + empty + unset", &mut variables));
+        assert_eq!(Ok(6), evaluate("x = x + 1", &mut variables));
+        assert_eq!(Some("6"), variables.get("x"));
+        assert_eq!(Ok(12), evaluate("x *= 2", &mut variables));
+        assert_eq!(Ok(12), evaluate("x++", &mut variables));
+        assert_eq!(Ok(12), evaluate("--x", &mut variables));
+        assert_eq!(Ok(3), evaluate("a = b = 3", &mut variables));
+        assert_eq!((Some("3"), Some("3")), (variables.get("a"), variables.get("b")));
+        assert_eq!(Ok(0), evaluate("0 && (y = 1)", &mut variables));
+        assert_eq!(None, variables.get("y"));
+
+        variables.set("s", "abc");
+        assert!(evaluate("s + 1", &mut variables).is_err());
+
+        variables.readonly("x");
+        assert!(evaluate("x = 1", &mut variables).is_err());
+        assert!(evaluate("x++", &mut variables).is_err());
+        assert_eq!(Some("12"), variables.get("x"));
+    }
+}
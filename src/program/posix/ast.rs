@@ -1,5 +1,19 @@
 //! Abstract Syntax Tree for the POSIX language.
-use std::os::unix::io::RawFd;
+//!
+//! Each node displays as shell source which parses back into the same
+//! tree, normalized to single spaces around operators and `;` between
+//! commands.
+//!
+//! ```
+//! use oursh::program::posix::parse_program;
+//!
+//! let program = parse_program("FOO=1  ls -la>out&&echo 'done!' ;  true").unwrap();
+//! assert_eq!("FOO=1 ls -la > out && echo 'done!'; true", program.to_string());
+//! ```
+use std::{
+    fmt,
+    os::unix::io::RawFd,
+};
 
 /// A program is the result of parsing a sequence of commands.
 #[derive(Debug, Clone)]
@@ -55,12 +69,62 @@ pub enum Command {
     /// ```
     Or(Box<Command>, Box<Command>),
 
-    /// Run the inner **program** in a sub-shell environment.
+    /// Perform the second command when the first succeeds, otherwise the
+    /// third, if there is one. An `elif` is another `if` in place of the
+    /// third.
     ///
     /// ### Examples
     ///
     /// ```sh
-    /// DATE=(date)
+    /// if [ -d src ]; then cd src; elif [ -d lib ]; then cd lib; else ls; fi
+    /// ```
+    If(Box<Command>, Box<Command>, Option<Box<Command>>),
+
+    /// Perform the second command for as long as the first succeeds. An
+    /// `until` loop is parsed as a `while` loop on the negated condition.
+    ///
+    /// ### Examples
+    ///
+    /// ```sh
+    /// while read line; do echo "> $line"; done
+    /// until [ -e ready ]; do sleep 1; done
+    /// ```
+    While(Box<Command>, Box<Command>),
+
+    /// Perform the command once for each of the words, after they're
+    /// expanded, with the named variable set to each in turn. Without `in`
+    /// and a list of words, the positional parameters are used.
+    ///
+    /// ### Examples
+    ///
+    /// ```sh
+    /// for file in *.rs; do wc -l "$file"; done
+    /// for arg; do echo "$arg"; done
+    /// ```
+    For(String, Option<Vec<Word>>, Box<Command>),
+
+    /// Perform the commands of the first pattern to match the word, after
+    /// they're both expanded. Each pattern is matched like a pathname, and
+    /// is only expanded if the ones before it don't match.
+    ///
+    /// ### Examples
+    ///
+    /// ```sh
+    /// case "$1" in
+    ///     -h|--help) usage ;;
+    ///     *.rs) rustc "$1" ;;
+    ///     *) echo "unknown: $1" ;;
+    /// esac
+    /// ```
+    Case(Word, Vec<(Vec<Word>, Command)>),
+
+    /// Run the inner **program** in a sub-shell environment, a copy of the
+    /// shell, so nothing it changes affects the shell itself.
+    ///
+    /// ### Examples
+    ///
+    /// ```sh
+    /// (cd src && make)
     /// ```
     Subshell(Box<Program>),
 
@@ -84,6 +148,42 @@ pub enum Command {
     /// ```
     Background(Box<Command>),
 
+    /// Run a compound command with its input and output redirected, all
+    /// of its commands together.
+    ///
+    /// ### Examples
+    ///
+    /// ```sh
+    /// { date; ls; } > log 2>&1
+    /// while read line; do echo "$line"; done < file
+    /// ```
+    Redirected(Box<Command>, Vec<Redirect>),
+
+    /// Define a function, to be called like any other command.
+    ///
+    /// ### Examples
+    ///
+    /// ```sh
+    /// greet() { echo "hello $1"; }
+    /// ```
+    Function(String, Box<Command>),
+
+    /// Evaluate an arithmetic expression, succeeding when its value isn't
+    /// zero. Variables assigned in the expression are set in the shell.
+    ///
+    /// ### Examples
+    ///
+    /// ```sh
+    /// if (( x > 3 )); then echo big; fi
+    /// (( i = i + 1 ))
+    /// ```
+    ///
+    /// ### Compatibility
+    ///
+    /// This is **non-POSIX**, it's a common extension of shells like `bash`
+    /// and `ksh`, but convenient enough to have here.
+    Arithmetic(String),
+
     /// Run a program through another parser/interpreter.
     ///
     /// ### Examples
@@ -132,15 +232,22 @@ pub enum Redirect {
         clobber: bool,
         append: bool,
     },
-    // // Here-Document
-    // // [n]<<word
-    // //     here-document
-    // // delimiter (above word)
-    // Here {
-    //     n: RawFd,
-    //     leading: bool,
-    //     string: String,
-    // },
+    // Here-String, which isn't POSIX
+    // [n]<<<word
+    String { n: RawFd, word: String },
+    // Here-Document
+    // [n]<<word
+    //     here-document
+    // delimiter (above word)
+    // [n]<<-word (strip = true, leading tabs removed)
+    Here {
+        n: RawFd,
+        delimiter: String,
+        text: String,
+        // The text isn't expanded when the delimiter is quoted.
+        quoted: bool,
+        strip: bool,
+    },
 }
 
 impl Redirect {
@@ -149,7 +256,8 @@ impl Redirect {
             Redirect::RW { ref mut n, .. } => n,
             Redirect::Read { ref mut n, .. } => n,
             Redirect::Write { ref mut n, .. } => n,
-            // Redirect::Here { ref mut n, .. } => n,
+            Redirect::String { ref mut n, .. } => n,
+            Redirect::Here { ref mut n, .. } => n,
         }
     }
 }
@@ -210,6 +318,173 @@ impl Program {
     }
 }
 
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, command) in self.0.iter().enumerate() {
+            if i > 0 {
+                // Background jobs are already separated by their `&`.
+                match self.0[i - 1] {
+                    Command::Background(_) => write!(f, " ")?,
+                    _ => write!(f, "; ")?,
+                }
+            }
+            write!(f, "{}", command)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Simple(assignments, words, redirects) => {
+                let parts = assignments.iter().map(ToString::to_string)
+                    .chain(words.iter().map(ToString::to_string))
+                    .chain(redirects.iter().map(ToString::to_string))
+                    .collect::<Vec<_>>();
+                write!(f, "{}", parts.join(" "))
+            },
+            Command::Compound(commands) => write!(f, "{{ {} }}", Commands(commands)),
+            Command::Not(command) => write!(f, "! {}", command),
+            Command::And(left, right) => write!(f, "{} && {}", left, right),
+            Command::Or(left, right) => write!(f, "{} || {}", left, right),
+            Command::If(condition, then, els) => {
+                write!(f, "if {} then {}{} fi", Body(condition), Body(then),
+                       Else(els.as_deref()))
+            },
+            Command::While(condition, body) => {
+                let (keyword, condition) = match &**condition {
+                    Command::Not(condition) => ("until", &**condition),
+                    condition => ("while", condition),
+                };
+                write!(f, "{} {} do {} done", keyword, Body(condition), Body(body))
+            },
+            Command::For(name, words, body) => {
+                write!(f, "for {}", name)?;
+                if let Some(words) = words {
+                    write!(f, " in")?;
+                    for word in words {
+                        write!(f, " {}", word)?;
+                    }
+                }
+                write!(f, "; do {} done", Body(body))
+            },
+            Command::Case(word, items) => {
+                write!(f, "case {} in", word)?;
+                for (patterns, body) in items {
+                    let patterns = patterns.iter().map(ToString::to_string)
+                        .collect::<Vec<_>>();
+                    write!(f, " {})", patterns.join(" | "))?;
+                    match body {
+                        Command::Compound(commands) if commands.is_empty() => {},
+                        body => write!(f, " {}", Body(body))?,
+                    }
+                    write!(f, " ;;")?;
+                }
+                write!(f, " esac")
+            },
+            Command::Subshell(program) => write!(f, "({})", program),
+            Command::Pipeline(left, right) => write!(f, "{} | {}", left, right),
+            Command::Background(command) => write!(f, "{} &", command),
+            Command::Redirected(command, redirects) => {
+                write!(f, "{}", command)?;
+                for redirect in redirects {
+                    write!(f, " {}", redirect)?;
+                }
+                Ok(())
+            },
+            Command::Function(name, body) => write!(f, "{}() {}", name, body),
+            Command::Arithmetic(expression) => write!(f, "(({}))", expression),
+            Command::Lang(interpreter, text) => match interpreter {
+                Interpreter::Shebang(interpreter) => write!(f, "{{#!{}; {}}}", interpreter, text),
+                Interpreter::HashLang(language) => write!(f, "{{#{} {}}}", language, text),
+                // The primary language has no syntax of its own.
+                Interpreter::Primary |
+                Interpreter::Alternate => write!(f, "{{# {}}}", text),
+            },
+        }
+    }
+}
+
+// The commands of a compound command, each followed by a `;`.
+struct Commands<'a>(&'a [Command]);
+
+impl fmt::Display for Commands<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let commands = self.0.iter().map(|c| format!("{};", c)).collect::<Vec<_>>();
+        write!(f, "{}", commands.join(" "))
+    }
+}
+
+// The commands of the part of a compound command, like the condition of an
+// `if`, each followed by a `;`.
+struct Body<'a>(&'a Command);
+
+impl fmt::Display for Body<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Command::Compound(commands) => write!(f, "{}", Commands(commands)),
+            command => write!(f, "{};", command),
+        }
+    }
+}
+
+// The rest of an `if` after its body, either `else`, `elif` or nothing.
+struct Else<'a>(Option<&'a Command>);
+
+impl fmt::Display for Else<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(Command::If(condition, then, els)) => {
+                write!(f, " elif {} then {}{}", Body(condition), Body(then),
+                       Else(els.as_deref()))
+            },
+            Some(command) => write!(f, " else {}", Body(command)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for Assignment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.0, self.1)
+    }
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The file descriptor is only written when it isn't the default.
+        let fd = |n: RawFd, default| if n == default { "".into() } else { n.to_string() };
+        match self {
+            Redirect::RW { n, filename } => write!(f, "{}<> {}", fd(*n, 0), filename),
+            Redirect::Read { n, filename, duplicate } => {
+                let op = if *duplicate { "<&" } else { "<" };
+                write!(f, "{}{} {}", fd(*n, 0), op, filename)
+            },
+            Redirect::Write { n, filename, duplicate, clobber, append } => {
+                let op = match (duplicate, clobber, append) {
+                    (true, _, _) => ">&",
+                    (_, true, _) => ">|",
+                    (_, _, true) => ">>",
+                    _ => ">",
+                };
+                write!(f, "{}{} {}", fd(*n, 1), op, filename)
+            },
+            Redirect::String { n, word } => write!(f, "{}<<< {}", fd(*n, 0), word),
+            // The text follows the command's line, so it isn't shown here.
+            Redirect::Here { n, delimiter, strip, .. } => {
+                let op = if *strip { "<<-" } else { "<<" };
+                write!(f, "{}{} {}", fd(*n, 0), op, delimiter)
+            },
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -238,6 +513,31 @@ mod tests {
         assert_eq!(3, parse_program("git s; ls -la; true;").unwrap().0.len());
     }
 
+    #[test]
+    fn separators() {
+        assert_eq!(3, parse_program("a\nb; c").unwrap().0.len());
+        assert_eq!(3, parse_program("a;\nb\n\nc;\n").unwrap().0.len());
+        // Empty commands are skipped.
+        assert_eq!(2, parse_program("a;\n;b").unwrap().0.len());
+        // But a `;;` only ends the clause of a `case`.
+        assert!(parse_program("a;; b").is_err());
+        assert!(parse_program(";").unwrap().0.is_empty());
+        assert_eq!(1, parse_program("\n;a").unwrap().0.len());
+
+        let command = parse_command("{ a\nb; c; }").unwrap();
+        assert_matches!(&command, Command::Compound(c) if c.len() == 3);
+        let command = parse_command("{\n  a\n\n  b;\n}").unwrap();
+        assert_matches!(&command, Command::Compound(c) if c.len() == 2);
+        let command = parse_command("{ a; b;\n; }").unwrap();
+        assert_matches!(&command, Command::Compound(c) if c.len() == 2);
+        assert!(parse_command("{ a;; b; }").is_err());
+        let command = parse_command("{ { a; }; b; }").unwrap();
+        assert_matches!(&command, Command::Compound(c) if c.len() == 2);
+        let command = parse_command("if a\nthen\n  b\nelse c\nfi").unwrap();
+        assert_matches!(&command, Command::If(_, _, Some(_)));
+        assert!(parse_command("{ ; }").is_err());
+    }
+
     fn parse_command<'a>(text: &'a str)
         -> Result<Command, ParseError<usize, Token<'a>, Error>>
     {
@@ -246,6 +546,72 @@ mod tests {
         parser.parse(text, lexer)
     }
 
+    #[test]
+    fn display_round_trip() {
+        let corpus = [
+            "ls -la",
+            "FOO=1 BAR=\"a b\" env",
+            "FOO=1",
+            "export A=1 B",
+            "FOO=1 export -p > out",
+            "echo 'a b' \\$x ${y} \"$z\"",
+            "cat < in > out 2>> err",
+            "read x <<< \"a $b\"",
+            "ls; date &",
+            "sleep 1 & sleep 2 & wait",
+            "true && false || ! true",
+            "cat README.md | wc -l | cat",
+            "{ ls; date; }",
+            "f() { echo \"hello $1\"; }",
+            "if true; then ls; fi",
+            "if true; then ls; else date; fi",
+            "if false; then ls; elif true; then date; else echo; fi",
+            "if true; then ls; fi && echo",
+            "if a; then b; else if c; then d; fi; fi",
+            "if true && false; then ! ls; fi",
+            "while read x; do echo $x; done | cat",
+            "until ! false; do ls; done",
+            "for x in a \"b c\" *.rs; do echo $x; done",
+            "for x; do echo $x; done",
+            "for x in; do ls; done",
+            "case $x in a | \"b c\") echo 1; ;; *.rs) ;; *) ls; date; ;; esac",
+            "case x in esac",
+            "echo a | { cat; } | (cat)",
+            "$(ls; date)",
+            "(cd src; make) && ls",
+            "echo {a,b} <(ls)",
+            "(( i = i + 1 )) && ! ((x > 3))",
+            "{ ls; date; } > out 2>&1 | cat",
+            "(cd src; make) 2> /dev/null",
+            "while read x; do echo $x; done < in",
+            "if true; then ls; fi >> out",
+            "f() { echo hi; } > out",
+            "f() (cd src; make)",
+            "f() if true; then ls; fi 2> /dev/null",
+        ];
+        for text in corpus.iter() {
+            let program = parse_program(text).unwrap();
+            let display = program.to_string();
+            let reparsed = parse_program(&display).unwrap_or_else(|e| {
+                panic!("{:?} displayed as {:?}: {:?}", text, display, e)
+            });
+            assert_eq!(format!("{:?}", program), format!("{:?}", reparsed));
+            assert_eq!(display, reparsed.to_string());
+        }
+
+        assert_eq!("cat < in > out 2>> err",
+                   parse_program("cat <in >out 2>>err").unwrap().to_string());
+        assert_eq!("if a; then b; elif c; then d; fi",
+                   parse_program("if a; then b; elif c; then d; fi").unwrap().to_string());
+        assert_eq!("for x; do echo $x; done",
+                   parse_program("for x\ndo\n  echo $x\ndone").unwrap().to_string());
+        assert_eq!("case $1 in -h | --help) usage; ;; *) exit 1; ;; esac",
+                   parse_program("case $1 in\n  (-h|--help)\n    usage;;\n  *) exit 1\nesac")
+                       .unwrap().to_string());
+        assert_eq!("sleep 1 & ls; date",
+                   parse_program("sleep 1&ls;date").unwrap().to_string());
+    }
+
     #[test]
     fn simple_command() {
         assert!(parse_command("ls").is_ok());
@@ -255,7 +621,9 @@ mod tests {
 
     #[test]
     fn compound_command() {
-        assert!(parse_command("{ls}").is_err());
+        // Without a space or `;`, the braces are part of a word, like `{a,b}`.
+        let command = parse_command("{ls}").unwrap();
+        assert_matches!(&command, Command::Simple(_, w, _) if w[0].0 == "{ls}");
         assert!(parse_command("{ls; date}").is_err());
 
         let text = "{ls;}";
@@ -269,6 +637,10 @@ mod tests {
         let text = "{git s; ls -la; true;}";
         let command = parse_command(text).unwrap();
         assert_matches!(&command, Command::Compound(c) if c.len() == 3);
+
+        // Unlike a subshell, the last command needs a `;` before the `}`.
+        assert!(parse_command("{ ls }").is_err());
+        assert!(parse_command("{ ls; date }").is_err());
     }
 
     #[test]
@@ -300,16 +672,44 @@ mod tests {
         assert!(parse_command("()").is_err());
         assert!(parse_command("$()").is_ok());
 
-        let command = parse_command("$(ls)").unwrap();
+        let command = parse_command("(ls)").unwrap();
         assert_matches!(command, Command::Subshell(_));
 
-        let command = parse_command("$(date;)").unwrap();
+        let command = parse_command("(cd x && make; ls)").unwrap();
+        assert_matches!(command, Command::Subshell(box Program(c)) if c.len() == 2);
+
+        let command = parse_command("(date;)").unwrap();
         assert_matches!(command, Command::Subshell(_));
 
-        let command = parse_command("$(date; ls)").unwrap();
+        // A command substitution is a word, even as the command name.
+        let command = parse_command("$(ls)").unwrap();
+        assert_matches!(&command, Command::Simple(_, w, _) if w[0].0 == "$(ls)");
+
+        let command = parse_command("$(date; ls -la;) x").unwrap();
+        assert_matches!(&command, Command::Simple(_, w, _) if w.len() == 2);
+
+        let command = parse_command("(echo $(date))").unwrap();
         assert_matches!(command, Command::Subshell(_));
+    }
 
-        let command = parse_command("$(date; ls -la;)").unwrap();
+    #[test]
+    fn arithmetic_command() {
+        let command = parse_command("(( x > 3 ))").unwrap();
+        assert_matches!(command, Command::Arithmetic(e) if e == " x > 3 ");
+        let command = parse_command("! ((x)) || ((y))").unwrap();
+        assert_matches!(command, Command::Or(box Command::Not(_), _));
+        // Otherwise they're nested subshells.
+        let command = parse_command("((ls))").unwrap();
+        assert_matches!(command, Command::Arithmetic(_));
+        let command = parse_command("((ls); ls)").unwrap();
         assert_matches!(command, Command::Subshell(_));
     }
+
+    #[test]
+    fn function_command() {
+        assert!(parse_command("f() echo").is_err());
+
+        let command = parse_command("f() { echo; }").unwrap();
+        assert_matches!(command, Command::Function(n, box Command::Compound(_)) if n == "f");
+    }
 }
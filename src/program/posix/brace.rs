@@ -0,0 +1,184 @@
+//! Brace expansion, the first of the word expansions.
+//!
+//! A brace group makes many words from one, without looking at the
+//! filesystem. The group is either a list of comma separated alternatives,
+//! or a range of integers or letters, with an optional step.
+//!
+//! ```sh
+//! echo {a,b}{1,2}     # a1 a2 b1 b2
+//! echo x{1..5}        # x1 x2 x3 x4 x5
+//! echo {a..e..2}      # a c e
+//! echo {1,{a,b}2}     # 1 a2 b2
+//! ```
+//!
+//! Groups which are quoted or escaped, or which have neither a comma nor a
+//! valid range, like `{a}`, are left as they are.
+use std::iter;
+
+/// Expand the brace groups of the (unexpanded) word, returning each of the
+/// resulting words in order.
+pub(crate) fn expand(word: &str) -> Vec<String> {
+    let bare = bare(word);
+    for (n, &(open, c)) in bare.iter().enumerate() {
+        if c != '{' {
+            continue;
+        }
+
+        // Find the matching `}`, and the commas within the group itself.
+        let mut depth = 0;
+        let mut commas = vec![];
+        let mut close = None;
+        for &(i, c) in &bare[n + 1..] {
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => {
+                    close = Some(i);
+                    break;
+                },
+                '}' => depth -= 1,
+                ',' if depth == 0 => commas.push(i),
+                _ => {},
+            }
+        }
+        let close = match close {
+            Some(close) => close,
+            None => continue,
+        };
+
+        let alternatives = if commas.is_empty() {
+            match range(&word[open + 1..close]) {
+                Some(items) => items,
+                None => continue,
+            }
+        } else {
+            let mut start = open + 1;
+            commas.iter().chain(iter::once(&close)).map(|&end| {
+                let alternative = word[start..end].to_string();
+                start = end + 1;
+                alternative
+            }).collect()
+        };
+
+        // Later groups are expanded for each of this group's words.
+        let prefix = &word[..open];
+        let suffixes = expand(&word[close + 1..]);
+        return alternatives.iter().flat_map(|a| expand(a)).flat_map(|a| {
+            suffixes.iter().map(move |s| format!("{}{}{}", prefix, a, s))
+        }).collect();
+    }
+    vec![word.into()]
+}
+
+// The characters of the word which aren't quoted, escaped, or part of a
+// `${..}` parameter, along with their positions.
+fn bare(word: &str) -> Vec<(usize, char)> {
+    let mut bare = vec![];
+    let mut chars = word.char_indices().peekable();
+    let mut quote = None;
+    let mut parameters = 0;
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if q == c => quote = None,
+            (Some('\''), _) => {},
+            (_, '\\') => {
+                chars.next();
+            },
+            (Some(_), _) => {},
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, '$') if matches!(chars.peek(), Some((_, '{'))) => {
+                chars.next();
+                parameters += 1;
+            },
+            (None, '}') if parameters > 0 => parameters -= 1,
+            (None, c) if parameters == 0 => bare.push((i, c)),
+            _ => {},
+        }
+    }
+    bare
+}
+
+// The items of an `x..y` or `x..y..step` range, of integers or letters of
+// the same case. Integers are padded with zeros when either end is.
+fn range(text: &str) -> Option<Vec<String>> {
+    let parts = text.split("..").collect::<Vec<_>>();
+    let step = match parts.len() {
+        2 => 1,
+        3 => parts[2].parse::<i64>().ok()?.unsigned_abs().max(1),
+        _ => return None,
+    };
+
+    if let (Ok(x), Ok(y)) = (parts[0].parse::<i64>(), parts[1].parse::<i64>()) {
+        let padded = |s: &str| {
+            let digits = s.trim_start_matches('-');
+            digits.len() > 1 && digits.starts_with('0')
+        };
+        let width = if padded(parts[0]) || padded(parts[1]) {
+            parts[0].len().max(parts[1].len())
+        } else {
+            0
+        };
+        return Some(steps(x, y, step).map(|n| {
+            format!("{:0width$}", n, width = width)
+        }).collect());
+    }
+
+    let letter = |s: &str| match s.as_bytes() {
+        [c] if c.is_ascii_alphabetic() => Some(*c),
+        _ => None,
+    };
+    let (x, y) = (letter(parts[0])?, letter(parts[1])?);
+    if x.is_ascii_lowercase() != y.is_ascii_lowercase() {
+        return None;
+    }
+    Some(steps(x.into(), y.into(), step).map(|c| {
+        char::from(c as u8).to_string()
+    }).collect())
+}
+
+// Count from `x` to `y` by `step`, in either direction.
+fn steps(x: i64, y: i64, step: u64) -> impl Iterator<Item = i64> {
+    let count = x.abs_diff(y) / step;
+    (0..=count).map(move |i| {
+        let offset = (i * step) as i64;
+        if x <= y { x + offset } else { x - offset }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alternatives() {
+        assert_eq!(vec!["a", "b", "c"], expand("{a,b,c}"));
+        assert_eq!(vec!["xay", "xby"], expand("x{a,b}y"));
+        assert_eq!(vec!["a1", "a2", "b1", "b2"], expand("{a,b}{1,2}"));
+        assert_eq!(vec!["1", "a2", "b2"], expand("{1,{a,b}2}"));
+        assert_eq!(vec!["a", ""], expand("{a,}"));
+        assert_eq!(vec!["'a b'", "c"], expand("{'a b',c}"));
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(vec!["1", "2", "3"], expand("{1..3}"));
+        assert_eq!(vec!["3", "2", "1"], expand("{3..1}"));
+        assert_eq!(vec!["0", "4", "8"], expand("{0..10..4}"));
+        assert_eq!(vec!["-1", "0", "1"], expand("{-1..1}"));
+        assert_eq!(vec!["08", "09", "10"], expand("{08..10}"));
+        assert_eq!(vec!["a", "c", "e"], expand("{a..e..2}"));
+        assert_eq!(vec!["x1y", "x2y"], expand("x{1..2}y"));
+    }
+
+    #[test]
+    fn literal() {
+        assert_eq!(vec!["{a}"], expand("{a}"));
+        assert_eq!(vec!["{}"], expand("{}"));
+        assert_eq!(vec!["{a..Z}"], expand("{a..Z}"));
+        assert_eq!(vec!["{1..b}"], expand("{1..b}"));
+        assert_eq!(vec!["'{a,b}'"], expand("'{a,b}'"));
+        assert_eq!(vec!["\\{a,b}"], expand("\\{a,b}"));
+        assert_eq!(vec!["${a}"], expand("${a}"));
+        assert_eq!(vec!["{a}1", "{a}2"], expand("{a}{1,2}"));
+        assert_eq!(vec!["{ab", "{ac"], expand("{a{b,c}"));
+    }
+}
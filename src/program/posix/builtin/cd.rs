@@ -1,49 +1,156 @@
 use std::{
-    env::{self, set_var},
+    env,
+    io::Write,
     ffi::CString,
+    path::{Component, Path, PathBuf},
 };
 use nix::{
+    errno::Errno,
     unistd::{chdir, Pid},
     sys::wait::WaitStatus,
 };
 use crate::{
     program::posix::builtin::Builtin,
-    program::{Result, Error, Runtime},
+    program::{Result, Runtime},
 };
 
 /// Change directory (`cd`) builtin.
+///
+/// - `cd` changes to `$HOME`.
+/// - `cd -` changes to `$OLDPWD`, printing the new directory.
+/// - `cd dir` changes to `dir`, which is searched for in the directories of
+///   `$CDPATH` unless it starts with `/`, `.` or `..`.
+///
+/// Each change sets `$OLDPWD` to the previous directory and `$PWD` to the
+/// new one. By default, or with `-L`, paths are logical, so `$PWD` keeps any
+/// symlinks and `..` removes the previous component of the path. With `-P`
+/// symlinks are resolved, and `$PWD` is the physical directory.
 pub struct Cd;
 
 impl Builtin for Cd {
-    fn run(self, argv: Vec<CString>, _: &mut Runtime) -> Result<WaitStatus> {
-        match argv.len() {
-            0 => {
-                panic!("command name not passed in argv[0]");
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        if argv.is_empty() {
+            panic!("command name not passed in argv[0]");
+        }
+
+        let mut physical = false;
+        let mut args = &argv[1..];
+        while let Some(arg) = args.first() {
+            match arg.to_bytes() {
+                b"-L" => physical = false,
+                b"-P" => physical = true,
+                b"--" => {
+                    args = &args[1..];
+                    break;
+                },
+                _ => break,
+            }
+            args = &args[1..];
+        }
+
+        let (dst, print) = match args.len() {
+            0 => match runtime.variables.get("HOME") {
+                Some(home) => (PathBuf::from(home), false),
+                None => return fail(runtime, "HOME not set"),
             },
             1 => {
-                let home = match env::var("HOME") {
-                    Ok(path) => path,
-                    Err(_) => return Err(Error::Runtime),
-                };
-                let dst = home.as_str();
-                chdir(dst).map(|_| {
-                    set_var("PWD", &dst);
-                    WaitStatus::Exited(Pid::this(), 0)
-                })
-                          .map_err(|_| Error::Runtime)
+                let arg = args[0].to_string_lossy();
+                if arg == "-" {
+                    match runtime.variables.get("OLDPWD") {
+                        Some(old) => (PathBuf::from(old), true),
+                        None => return fail(runtime, "OLDPWD not set"),
+                    }
+                } else {
+                    search(&arg, runtime)
+                }
             },
-            2 => {
-                let dst = argv[1].to_string_lossy();
-                chdir(dst.as_ref()).map(|_| {
-                        set_var("PWD", dst.as_ref());
-                        WaitStatus::Exited(Pid::this(), 0)
-                    })
-                    .map_err(|_| Error::Runtime)
+            _ => return fail(runtime, "too many arguments"),
+        };
+
+        let old = runtime.variables.get("PWD").filter(|pwd| {
+            pwd.starts_with('/')
+        }).map(String::from).or_else(|| {
+            env::current_dir().ok().map(|d| d.to_string_lossy().into_owned())
+        });
+        let dst = if physical {
+            dst
+        } else {
+            logical(old.as_deref(), &dst)
+        };
+        // Failing leaves the directory, `$PWD` and `$OLDPWD` as they were.
+        if let Err(e) = chdir(&dst) {
+            let reason = match e {
+                Errno::ENOENT => "no such file or directory",
+                Errno::ENOTDIR => "not a directory",
+                Errno::EACCES => "permission denied",
+                e => e.desc(),
+            };
+            let message = format!("{}: {}", dst.display(), reason);
+            return fail(runtime, &message);
+        }
+
+        let pwd = if physical {
+            env::current_dir().unwrap_or(dst)
+        } else {
+            dst
+        };
+        let pwd = pwd.to_string_lossy();
+        if let Some(old) = old {
+            runtime.variables.set("OLDPWD", old);
+        }
+        runtime.variables.set("PWD", pwd.as_ref());
+        if print {
+            let _ = writeln!(runtime.io.stdout(), "{}", pwd);
+        }
+        Ok(WaitStatus::Exited(Pid::this(), 0))
+    }
+}
+
+// The absolute path of the directory from the current one, with `.` and `..`
+// components removed without resolving symlinks.
+fn logical(pwd: Option<&str>, dir: &Path) -> PathBuf {
+    let mut path = match pwd {
+        Some(pwd) if dir.is_relative() => PathBuf::from(pwd),
+        _ => PathBuf::new(),
+    };
+    for component in dir.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                path.pop();
             },
-            _ => {
-                eprintln!("too many arguments");
-                Ok(WaitStatus::Exited(Pid::this(), 1))
+            component => path.push(component),
+        }
+    }
+    path
+}
+
+// Find the directory in `$CDPATH`, returning it and whether it was found
+// there. Paths starting with `/`, `.` or `..` aren't searched for, and the
+// current directory is tried when there's no match.
+fn search(dir: &str, runtime: &Runtime) -> (PathBuf, bool) {
+    let path = Path::new(dir);
+    let relative = !matches!(path.components().next(), Some(c) if {
+        c.as_os_str() == "." || c.as_os_str() == ".." || c.as_os_str() == "/"
+    });
+    if let (true, Some(cdpath)) = (relative, runtime.variables.get("CDPATH")) {
+        for prefix in cdpath.split(':') {
+            let candidate = if prefix.is_empty() {
+                PathBuf::from(".").join(dir)
+            } else {
+                Path::new(prefix).join(dir)
+            };
+            if candidate.is_dir() {
+                // Only a directory found from a named prefix is printed.
+                return (candidate, !prefix.is_empty());
             }
         }
     }
+    (path.into(), false)
+}
+
+// Print the diagnostic, and return the failing status.
+fn fail(runtime: &mut Runtime, message: &str) -> Result<WaitStatus> {
+    let _ = writeln!(runtime.io.stderr(), "oursh: cd: {}", message);
+    Ok(WaitStatus::Exited(Pid::this(), 1))
 }
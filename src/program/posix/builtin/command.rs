@@ -1,18 +1,65 @@
-use std::ffi::CString;
-use nix::sys::wait::WaitStatus;
+use std::{
+    io::Write,
+    ffi::CString,
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+};
+use nix::{
+    unistd::Pid,
+    sys::wait::WaitStatus,
+};
 use crate::{
-    program::posix::builtin::Builtin,
-    program::{Result, Runtime, parse_and_run},
+    process::path_dirs,
+    program::posix::builtin::{self, Builtin},
+    program::{Result, Runtime},
 };
 
-/// Command builtin, I have no idea why you'd want this honestly.
+/// Command builtin, `command [-v] name [argument...]`.
+///
+/// - `command name` runs the command.
+/// - `command -v name...` prints how each command would be found, as its
+///   name for a function or builtin, or the path of an executable in the
+///   `$PATH`. The status is 1 if any of them aren't found.
 pub struct Command;
 
 impl Builtin for Command {
     fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        if argv.get(1).map(|a| a.to_bytes() == b"-v").unwrap_or(false) {
+            let mut status = 0;
+            for name in argv[2..].iter().map(|a| a.to_string_lossy()) {
+                match find(&name, runtime) {
+                    Some(found) => {
+                        let _ = writeln!(runtime.io.stdout(), "{}", found);
+                    },
+                    None => status = 1,
+                }
+            }
+            return Ok(WaitStatus::Exited(Pid::this(), status));
+        }
+
         let text = argv[1..].iter().map(|c| {
             c.to_str().unwrap()
         }).collect::<Vec<_>>().join(" ");
-        parse_and_run(&text, runtime)
+        builtin::run_text(&text, runtime)
     }
 }
+
+// How the named command would be found, if at all.
+fn find(name: &str, runtime: &Runtime) -> Option<String> {
+    if runtime.functions.contains_key(name) || builtin::enabled(name, runtime).is_some() {
+        return Some(name.into());
+    }
+    if name.contains('/') {
+        return is_executable(Path::new(name)).then(|| name.into());
+    }
+    let path = runtime.variables.get("PATH")?;
+    path_dirs(path.as_ref()).map(|dir| dir.join(name))
+                            .find(|path| is_executable(path))
+                            .map(|path| path.to_string_lossy().into())
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+                      .unwrap_or(false)
+}
@@ -0,0 +1,48 @@
+use std::{
+    io::Write,
+    ffi::CString,
+};
+use nix::{
+    unistd::Pid,
+    sys::wait::WaitStatus,
+};
+use crate::{
+    program::posix::builtin::Builtin,
+    program::{Result, Runtime},
+};
+
+/// Disown builtin, removes jobs from the job table.
+///
+/// - `disown` removes the current job.
+/// - `disown id...` removes each job, given by its pid or a job spec like
+///   `%1` (see [`Runtime::job`]).
+/// - `disown -a` removes every job.
+///
+/// Disowned jobs keep running, but can't be waited for, and aren't sent
+/// `SIGHUP` when a login shell exits with the `huponexit` option.
+pub struct Disown;
+
+impl Builtin for Disown {
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        let args = argv.iter().skip(1).map(|a| a.to_string_lossy()).collect::<Vec<_>>();
+        if args.first().map(|a| a == "-a").unwrap_or(false) {
+            runtime.jobs.borrow_mut().clear();
+            return Ok(WaitStatus::Exited(Pid::this(), 0));
+        }
+
+        let specs = if args.is_empty() { vec!["%+".into()] } else { args };
+        let mut code = 0;
+        for spec in specs {
+            match runtime.job(&spec) {
+                Ok(i) => {
+                    runtime.jobs.borrow_mut().remove(i);
+                },
+                Err(message) => {
+                    let _ = writeln!(runtime.io.stderr(), "oursh: disown: {}", message);
+                    code = 1;
+                },
+            }
+        }
+        Ok(WaitStatus::Exited(Pid::this(), code))
+    }
+}
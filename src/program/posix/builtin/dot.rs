@@ -1,51 +1,82 @@
 use std::{
-    io::Read,
-    fs::File,
+    io::Write,
+    fs::{self, File},
     ffi::CString,
+    mem,
+    path::PathBuf,
+    process,
 };
 use nix::{
     unistd::Pid,
     sys::wait::WaitStatus,
 };
 use crate::{
-    program::posix::builtin::Builtin,
-    program::{Result, Runtime, parse_and_run},
+    process::path_dirs,
+    program::posix::builtin::{Builtin, run_text},
+    program::{Result, Error, Runtime},
 };
 
 /// Execute commands from `file` in the current environment
 ///
-/// TODO:
 /// If file does not contain a `/`, the shell shall use the search path
 /// specified by `PATH` to find the directory containing file. Unlike normal
 /// command search, however, the file searched for by the `.` utility need not
 /// be executable. If no readable file is found, a non-interactive shell shall
 /// abort; an interactive shell shall write a diagnostic message to standard
 /// error, but this condition shall not be considered a syntax error.
+///
+/// Any further arguments are the positional parameters while the file runs.
+/// A `return` in the file ends it, with the status it's given.
 pub struct Dot;
 
 impl Builtin for Dot {
     fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
-        match argv.len() {
-            0 => unreachable!(),
-            1 => {
-                eprintln!("filename argument required");
-                Ok(WaitStatus::Exited(Pid::this(), 2))
-            }
-            2 => {
-                let path = argv[1].to_str().unwrap();
-                if let Ok(mut file) = File::open(&path) {
-                    let mut contents = String::new();
-                    if file.read_to_string(&mut contents).is_ok() {
-                        parse_and_run(&contents, runtime)
-                    } else {
-                        Ok(WaitStatus::Exited(Pid::this(), 1))
-                    }
+        if argv.len() < 2 {
+            let _ = writeln!(runtime.io.stderr(), "filename argument required");
+            return Ok(WaitStatus::Exited(Pid::this(), 2));
+        }
+
+        let name = argv[1].to_string_lossy().into_owned();
+        let path = if name.contains('/') {
+            Some(PathBuf::from(&name))
+        } else {
+            search(&name, runtime)
+        };
+        let contents = match path.and_then(|p| fs::read_to_string(p).ok()) {
+            Some(contents) => contents,
+            None => {
+                let _ = writeln!(runtime.io.stderr(), "oursh: .: {}: file not found", name);
+                if runtime.interactive || runtime.embedded {
+                    return Ok(WaitStatus::Exited(Pid::this(), 1));
                 } else {
-                    Ok(WaitStatus::Exited(Pid::this(), 1))
+                    process::exit(1);
                 }
             },
-            _ => unreachable!(),
+        };
 
+        runtime.calls += 1;
+        let result = if argv.len() > 2 {
+            let args = argv[2..].iter().map(|a| {
+                a.to_string_lossy().into_owned()
+            }).collect();
+            let positionals = mem::replace(&mut runtime.positionals, args);
+            let result = run_text(&contents, runtime);
+            runtime.positionals = positionals;
+            result
+        } else {
+            run_text(&contents, runtime)
+        };
+        runtime.calls -= 1;
+        match result {
+            Err(Error::Return(code)) => Ok(WaitStatus::Exited(Pid::this(), code)),
+            result => result,
         }
     }
 }
+
+// Find the first readable file with the given name in the `PATH`.
+fn search(name: &str, runtime: &Runtime) -> Option<PathBuf> {
+    let path = runtime.variables.get("PATH")?;
+    path_dirs(path.as_ref()).map(|dir| dir.join(name))
+                            .find(|path| path.is_file() && File::open(path).is_ok())
+}
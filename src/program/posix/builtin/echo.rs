@@ -0,0 +1,43 @@
+use std::{
+    io::Write,
+    ffi::CString,
+};
+use nix::{
+    unistd::Pid,
+    sys::wait::WaitStatus,
+};
+use crate::{
+    program::posix::builtin::Builtin,
+    program::{Result, Runtime},
+};
+
+/// Echo builtin, writes its arguments separated by spaces.
+///
+/// A leading `-n` leaves off the trailing newline.
+pub struct Echo;
+
+impl Builtin for Echo {
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        let mut args = &argv[1..];
+        let newline = match args.first() {
+            Some(arg) if arg.to_bytes() == b"-n" => {
+                args = &args[1..];
+                false
+            },
+            _ => true,
+        };
+
+        let mut text = args.iter()
+                           .map(|a| a.to_string_lossy())
+                           .collect::<Vec<_>>()
+                           .join(" ");
+        if newline {
+            text.push('\n');
+        }
+        let status = match runtime.io.stdout().write_all(text.as_bytes()) {
+            Ok(()) => 0,
+            Err(_) => 1,
+        };
+        Ok(WaitStatus::Exited(Pid::this(), status))
+    }
+}
@@ -0,0 +1,51 @@
+use std::{
+    io::Write,
+    ffi::CString,
+};
+use nix::{
+    unistd::Pid,
+    sys::wait::WaitStatus,
+};
+use crate::{
+    program::posix::builtin::{self, Builtin},
+    program::{Result, Runtime},
+};
+
+/// Enable builtin, `enable [-n] [name...]`, used to turn builtins off and
+/// on again.
+///
+/// - `enable` prints every builtin, as the command which turns it on or off.
+/// - `enable -n NAME` turns off the builtin `NAME`, so an executable of the
+///   same name in the `$PATH` is run instead.
+/// - `enable NAME` turns the builtin `NAME` back on.
+pub struct Enable;
+
+impl Builtin for Enable {
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        let mut args = argv.iter().skip(1).map(|a| a.to_string_lossy().into_owned()).peekable();
+        let disable = args.next_if(|a| a == "-n").is_some();
+        let names = args.collect::<Vec<_>>();
+
+        if names.is_empty() {
+            for name in builtin::names() {
+                let flag = if runtime.disabled_builtins.contains(name) { "-n " } else { "" };
+                let _ = writeln!(runtime.io.stdout(), "enable {}{}", flag, name);
+            }
+            return Ok(WaitStatus::Exited(Pid::this(), 0));
+        }
+
+        let mut status = 0;
+        for name in names {
+            if builtin::lookup(&name).is_none() {
+                let _ = writeln!(runtime.io.stderr(), "oursh: enable: {}: not a shell builtin",
+                                 name);
+                status = 1;
+            } else if disable {
+                runtime.disabled_builtins.insert(name);
+            } else {
+                runtime.disabled_builtins.remove(&name);
+            }
+        }
+        Ok(WaitStatus::Exited(Pid::this(), status))
+    }
+}
@@ -0,0 +1,70 @@
+use std::{
+    io::Write,
+    process,
+    ffi::CString,
+};
+use nix::{
+    errno::Errno,
+    unistd::{close, dup, dup2, Pid},
+    sys::wait::WaitStatus,
+};
+use crate::{
+    process::Process,
+    program::posix::builtin::Builtin,
+    program::{Result, Error, Runtime},
+};
+
+/// Exec builtin, replaces the shell with a command.
+///
+/// The command keeps the shell's process, along with its redirections. When
+/// the command can't be executed an interactive shell keeps running, while
+/// any other shell exits with status 127, or 126 when the command isn't
+/// executable.
+///
+/// Without a command, `exec`'s redirections are applied to the shell
+/// itself, e.g. `exec > log` sends all further output to `log`.
+pub struct Exec;
+
+impl Builtin for Exec {
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        if argv.len() < 2 {
+            return Ok(WaitStatus::Exited(Pid::this(), 0));
+        }
+
+        // Keep the shell's own files in case the command can't be run.
+        let saved = [dup(0), dup(1), dup(2)];
+        for (n, fd) in runtime.io.0.iter().enumerate() {
+            if *fd as usize != n {
+                let _ = dup2(*fd, n as i32);
+            }
+        }
+        let process = Process::new(argv[1..].to_vec()).env(runtime.variables.environment());
+        let error = process.exec().err();
+        for (n, fd) in saved.iter().enumerate() {
+            if let Ok(fd) = *fd {
+                let _ = dup2(fd, n as i32);
+                let _ = close(fd);
+            }
+        }
+
+        let name = argv[1].to_string_lossy();
+        let code = match error {
+            Some(Errno::ENOENT) | None => {
+                let _ = writeln!(runtime.io.stderr(), "oursh: exec: {}: not found", name);
+                127
+            },
+            Some(e) => {
+                let _ = writeln!(runtime.io.stderr(), "oursh: exec: {}: {}", name, e.desc());
+                126
+            },
+        };
+
+        if runtime.interactive {
+            Ok(WaitStatus::Exited(Pid::this(), code))
+        } else if runtime.embedded {
+            Err(Error::Exit(code))
+        } else {
+            process::exit(code)
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    io::Write,
     process,
     ffi::CString,
 };
@@ -8,38 +9,43 @@ use nix::{
 };
 use crate::{
     program::posix::builtin::Builtin,
-    program::{Result, Runtime},
+    program::{Result, Error, Runtime},
 };
 
 /// Exit builtin, alternative to ctrl-d.
+///
+/// The status is taken modulo 256, so `exit 257` exits with 1 and `exit -1`
+/// with 255, and without one it's the last command's, `$?`. A status which
+/// isn't a number is an error, and exits with 2. Given more than one
+/// argument the shell doesn't exit, the status is 1.
+///
+/// The shell is shut down first, running the `EXIT` trap and saving the
+/// history.
 pub struct Exit;
 
 impl Builtin for Exit {
-    #[allow(unused_variables)]
     fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
-        #[cfg(feature = "history")]
-        if argv.len() == 1 || argv.len() == 2 {
-            runtime.history.save().unwrap();
-        }
-
-        match argv.len() {
-            0 => {
-                panic!("command name not passed in argv[0]");
-            },
-            1 => {
-                process::exit(0)
+        let code = match argv.get(1).map(|a| a.to_string_lossy()) {
+            None => runtime.last_status,
+            Some(arg) => match arg.trim().parse::<i64>() {
+                Ok(_) if argv.len() > 2 => {
+                    let _ = writeln!(runtime.io.stderr(), "oursh: exit: too many arguments");
+                    return Ok(WaitStatus::Exited(Pid::this(), 1));
+                },
+                Ok(code) => (code & 0xff) as i32,
+                Err(_) => {
+                    let _ = writeln!(runtime.io.stderr(),
+                                     "oursh: exit: {}: numeric argument required", arg);
+                    2
+                },
             },
-            2 => {
-                if let Ok(n) = str::parse(argv[1].to_str().unwrap()) {
-                    process::exit(n)
-                } else {
-                    process::exit(2)
-                }
-            },
-            _ => {
-                eprintln!("too many arguments");
-                Ok(WaitStatus::Exited(Pid::this(), 1))
-            }
+        };
+
+        // Embedding programs get the status instead of being exited.
+        if runtime.embedded {
+            Err(Error::Exit(code))
+        } else {
+            process::exit(runtime.shutdown(code))
         }
     }
 }
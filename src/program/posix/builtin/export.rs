@@ -1,5 +1,5 @@
 use std::{
-    env,
+    io::Write,
     ffi::CString,
 };
 use nix::{
@@ -11,26 +11,41 @@ use crate::{
     program::{Result, Runtime},
 };
 
-/// Export builtin, used to set global variables.
+/// Export builtin, used to pass variables to the environment of commands.
+///
+/// - `export` (or `export -p`) prints the exported variables.
+/// - `export NAME` exports the variable `NAME`.
+/// - `export NAME=value` sets and exports the variable `NAME`.
 pub struct Export;
 
 impl Builtin for Export {
-    fn run(self, argv: Vec<CString>, _: &mut Runtime) -> Result<WaitStatus> {
-        match argv.len() {
-            0 => unreachable!(),
-            1 => {
-                // TODO: Print all env vars.
-                unimplemented!();
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        let args = argv[1..].iter().map(|a| {
+            a.to_string_lossy().into_owned()
+        }).collect::<Vec<_>>();
+
+        if args.is_empty() || args == ["-p"] {
+            for (name, value) in runtime.variables.exported() {
+                let _ = writeln!(runtime.io.stdout(), "export {}='{}'",
+                                 name, value.replace('\'', "'\\''"));
+            }
+            return Ok(WaitStatus::Exited(Pid::this(), 0));
+        }
+
+        let mut status = 0;
+        for arg in args {
+            match arg.split_once('=') {
+                Some((name, _)) if runtime.variables.is_readonly(name) => {
+                    let _ = writeln!(runtime.io.stderr(), "oursh: {}: readonly variable", name);
+                    status = 1;
+                },
+                Some((name, value)) => {
+                    runtime.variables.set(name, value);
+                    runtime.variables.export(name);
+                },
+                None => runtime.variables.export(&arg),
             }
-            n => {
-                for assignment in argv[1..n].iter() {
-                    let mut split = assignment.to_str().unwrap().splitn(2, '=');
-                    if let (Some(key), Some(value)) = (split.next(), split.next()) {
-                        env::set_var(key, value);
-                    }
-                }
-                Ok(WaitStatus::Exited(Pid::this(), 0))
-            },
         }
+        Ok(WaitStatus::Exited(Pid::this(), status))
     }
 }
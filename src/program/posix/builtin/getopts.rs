@@ -0,0 +1,138 @@
+use std::{
+    io::Write,
+    ffi::CString,
+};
+use nix::{
+    unistd::Pid,
+    sys::wait::WaitStatus,
+};
+use crate::{
+    program::posix::builtin::Builtin,
+    program::{Result, Runtime},
+};
+
+/// Parse utility options, one per invocation.
+///
+/// `getopts optstring name [arg...]` reads the next option from `arg...`
+/// (or the positional parameters) into the variable `name`, and its
+/// argument into `OPTARG`. The index of the next argument to be processed
+/// is kept in `OPTIND`.
+///
+/// When `optstring` starts with a `:` errors are reported silently. An
+/// invalid option sets `name` to `?`, and a missing option-argument sets
+/// `name` to `:`, with `OPTARG` set to the option character in both cases.
+/// Otherwise a diagnostic is printed, `name` is set to `?` and `OPTARG` is
+/// unset.
+pub struct Getopts;
+
+impl Builtin for Getopts {
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        if argv.len() < 3 {
+            let _ = writeln!(runtime.io.stderr(),
+                             "oursh: getopts: usage: getopts optstring name [arg...]");
+            return Ok(WaitStatus::Exited(Pid::this(), 2));
+        }
+
+        let optstring = argv[1].to_string_lossy().into_owned();
+        let name = argv[2].to_string_lossy().into_owned();
+        let args = if argv.len() > 3 {
+            argv[3..].iter().map(|a| a.to_string_lossy().into_owned()).collect()
+        } else {
+            runtime.positionals.clone()
+        };
+        let (silent, optstring) = match optstring.strip_prefix(':') {
+            Some(optstring) => (true, optstring),
+            None => (false, optstring.as_str()),
+        };
+
+        let mut optind = runtime.variables.get("OPTIND")
+                            .and_then(|i| i.parse::<usize>().ok())
+                            .unwrap_or(1)
+                            .max(1);
+
+        // The position within a group of options like `-ab` only counts
+        // while `OPTIND` is still the argument it was in.
+        let mut offset = match runtime.getopts_offset {
+            (offset, index) if index == optind => offset,
+            _ => 0,
+        };
+
+        // Start reading a new argument, unless we're in the middle of a
+        // group of options.
+        if offset == 0 {
+            match args.get(optind - 1).map(|a| a.as_str()) {
+                Some("--") => {
+                    runtime.variables.set("OPTIND", (optind + 1).to_string());
+                    return Ok(finish(&name, runtime));
+                },
+                Some(a) if a.starts_with('-') && a != "-" => offset = 1,
+                _ => return Ok(finish(&name, runtime)),
+            }
+        }
+
+        // The arguments may have changed since, like with `set --`.
+        let arg = args.get(optind - 1).map(|a| a.chars().collect::<Vec<_>>()).unwrap_or_default();
+        let option = match arg.get(offset) {
+            Some(&option) => option,
+            None => return Ok(finish(&name, runtime)),
+        };
+        offset += 1;
+        let rest = arg[offset..].iter().collect::<String>();
+        if rest.is_empty() {
+            optind += 1;
+            offset = 0;
+        }
+
+        let spec = optstring.find(option)
+                            .filter(|_| option != ':')
+                            .map(|i| optstring[i + option.len_utf8()..].starts_with(':'));
+        let result = match spec {
+            None => {
+                if silent {
+                    runtime.variables.set("OPTARG", option.to_string());
+                } else {
+                    let _ = writeln!(runtime.io.stderr(), "oursh: getopts: illegal option -- {}", option);
+                    runtime.variables.unset("OPTARG");
+                }
+                "?".to_string()
+            },
+            Some(true) if !rest.is_empty() => {
+                runtime.variables.set("OPTARG", rest);
+                optind += 1;
+                offset = 0;
+                option.to_string()
+            },
+            Some(true) => {
+                if let Some(optarg) = args.get(optind - 1) {
+                    runtime.variables.set("OPTARG", optarg.as_str());
+                    optind += 1;
+                    option.to_string()
+                } else if silent {
+                    runtime.variables.set("OPTARG", option.to_string());
+                    ":".to_string()
+                } else {
+                    let _ = writeln!(runtime.io.stderr(),
+                                     "oursh: getopts: option requires an argument -- {}", option);
+                    runtime.variables.unset("OPTARG");
+                    "?".to_string()
+                }
+            },
+            Some(false) => {
+                runtime.variables.unset("OPTARG");
+                option.to_string()
+            },
+        };
+
+        runtime.variables.set(&name, result);
+        runtime.variables.set("OPTIND", optind.to_string());
+        runtime.getopts_offset = (offset, optind);
+        Ok(WaitStatus::Exited(Pid::this(), 0))
+    }
+}
+
+// There are no more options to be read.
+fn finish(name: &str, runtime: &mut Runtime) -> WaitStatus {
+    runtime.getopts_offset = (0, 0);
+    runtime.variables.set(name, "?");
+    WaitStatus::Exited(Pid::this(), 1)
+}
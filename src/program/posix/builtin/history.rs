@@ -0,0 +1,37 @@
+use std::{
+    io::Write,
+    ffi::CString,
+};
+use nix::{
+    unistd::Pid,
+    sys::wait::WaitStatus,
+};
+use crate::{
+    program::posix::builtin::Builtin,
+    program::{Result, Runtime},
+};
+
+/// History builtin, used to view and clear the command history.
+///
+/// - `history` prints the numbered history, oldest first.
+/// - `history -c` clears the history.
+pub struct History;
+
+impl Builtin for History {
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        match argv.get(1).map(|a| a.to_string_lossy()).as_deref() {
+            None => {
+                for (i, text) in runtime.history.entries().iter().enumerate() {
+                    let _ = writeln!(runtime.io.stdout(), "{:5}  {}", i + 1, text);
+                }
+            },
+            Some("-c") => runtime.history.clear(),
+            Some(arg) => {
+                let _ = writeln!(runtime.io.stderr(), "oursh: history: {}: invalid option", arg);
+                let _ = writeln!(runtime.io.stderr(), "oursh: history: usage: history [-c]");
+                return Ok(WaitStatus::Exited(Pid::this(), 2));
+            },
+        }
+        Ok(WaitStatus::Exited(Pid::this(), 0))
+    }
+}
@@ -1,22 +1,101 @@
-use std::ffi::CString;
+use std::{
+    io::Write,
+    ffi::CString,
+};
 use nix::{
     unistd::Pid,
     sys::wait::WaitStatus,
 };
 use crate::{
+    process::jobs::{describe, is_finished},
     program::posix::builtin::Builtin,
     program::{Result, Runtime},
 };
 
-/// Background job information.
+/// Background job information, `jobs [-l | -p] [id...]`.
+///
+/// Each job is listed like `[1]+\t1234\t\tsleep 10`, with its id, a `+` for
+/// the current job or a `-` for the previous one (see [`Runtime::job`]), the
+/// pid of its leader, and its command. Only the jobs given by their pid or a
+/// job spec like `%1` are listed, if any are given.
+///
+/// - `-l` lists the process group id and the state of each job instead,
+///   like `[1]+ 1234 Running\tsleep 10`.
+/// - `-p` lists only the process group ids, as for `kill $(jobs -p)`.
+///
+/// Jobs which have finished are listed once, and then removed.
 pub struct Jobs;
 
 impl Builtin for Jobs {
-    fn run(self, _: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
-        for (id, job) in runtime.jobs.borrow().iter() {
-            println!("[{}]\t{}\t\t{}",
-                     id, job.leader().pid(), job.leader().body());
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        let mut args = argv.iter().skip(1).map(|a| a.to_string_lossy().into_owned()).peekable();
+        let (mut long, mut pids) = (false, false);
+        while let Some(flags) = args.next_if(|a| a.starts_with('-') && a != "-") {
+            if flags == "--" {
+                break;
+            }
+            for flag in flags.chars().skip(1) {
+                match flag {
+                    'l' => long = true,
+                    'p' => pids = true,
+                    _ => {
+                        let _ = writeln!(runtime.io.stderr(),
+                                         "oursh: jobs: -{}: invalid option", flag);
+                        let _ = writeln!(runtime.io.stderr(),
+                                         "oursh: jobs: usage: jobs [-l | -p] [id...]");
+                        return Ok(WaitStatus::Exited(Pid::this(), 2));
+                    },
+                }
+            }
+        }
+
+        runtime.update_jobs();
+        let mut code = 0;
+        let count = runtime.jobs.borrow().len();
+        let mut listed = vec![];
+        for spec in args {
+            match runtime.job(&spec) {
+                Ok(i) => listed.push(i),
+                Err(message) => {
+                    let _ = writeln!(runtime.io.stderr(), "oursh: jobs: {}", message);
+                    code = 1;
+                },
+            }
+        }
+        if code == 0 && listed.is_empty() {
+            listed = (0..count).collect();
+        }
+
+        let jobs = runtime.jobs.borrow();
+        for &i in &listed {
+            let (id, job) = &jobs[i];
+            let pid = job.leader().pid();
+            let mark = match count - i {
+                1 => '+',
+                2 => '-',
+                _ => ' ',
+            };
+            let _ = if pids {
+                writeln!(runtime.io.stdout(), "{}", pid)
+            } else if long {
+                writeln!(runtime.io.stdout(), "[{}]{} {} {}\t{}",
+                         id, mark, pid, describe(job.state()), job.leader().body())
+            } else {
+                writeln!(runtime.io.stdout(), "[{}]{}\t{}\t\t{}",
+                         id, mark, pid, job.leader().body())
+            };
+        }
+        drop(jobs);
+
+        // The finished jobs have been seen now.
+        listed.sort_unstable();
+        listed.dedup();
+        let mut jobs = runtime.jobs.borrow_mut();
+        for &i in listed.iter().rev() {
+            if is_finished(jobs[i].1.state()) {
+                jobs.remove(i);
+            }
         }
-        Ok(WaitStatus::Exited(Pid::this(), 0))
+        Ok(WaitStatus::Exited(Pid::this(), code))
     }
 }
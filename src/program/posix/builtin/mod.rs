@@ -3,9 +3,16 @@
 //!
 //! These commands take precedence over any executables with the same name
 //! in the `$PATH`.
-use std::ffi::CString;
-use nix::sys::wait::WaitStatus;
-use crate::program::{Result, Runtime};
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    sync::OnceLock,
+};
+use nix::{
+    unistd::Pid,
+    sys::wait::WaitStatus,
+};
+use crate::program::{Result, Error, Runtime, parse_and_run};
 
 /// A builtin is a custom shell command, often changing the state of the
 /// shell in some way.
@@ -15,19 +22,134 @@ pub trait Builtin {
     fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus>;
 }
 
+/// Runs a builtin with its arguments, the first of which is its name.
+pub type BuiltinFn = fn(Vec<CString>, &mut Runtime) -> Result<WaitStatus>;
+
+// All the builtins by name, which is the one place they're registered.
+const BUILTINS: &[(&str, BuiltinFn)] = &[
+    (".",       |argv, runtime| Dot.run(argv, runtime)),
+    (":",       |argv, runtime| Status(0).run(argv, runtime)),
+    ("[",       |argv, runtime| Test.run(argv, runtime)),
+    ("builtin", |argv, runtime| ShellBuiltin.run(argv, runtime)),
+    ("cd",      |argv, runtime| Cd.run(argv, runtime)),
+    ("command", |argv, runtime| Command.run(argv, runtime)),
+    ("disown",  |argv, runtime| Disown.run(argv, runtime)),
+    ("echo",    |argv, runtime| Echo.run(argv, runtime)),
+    ("enable",  |argv, runtime| Enable.run(argv, runtime)),
+    ("exec",    |argv, runtime| Exec.run(argv, runtime)),
+    ("exit",    |argv, runtime| Exit.run(argv, runtime)),
+    ("export",  |argv, runtime| Export.run(argv, runtime)),
+    ("false",   |argv, runtime| Status(1).run(argv, runtime)),
+    ("getopts", |argv, runtime| Getopts.run(argv, runtime)),
+    #[cfg(feature = "history")]
+    ("history", |argv, runtime| History.run(argv, runtime)),
+    ("jobs",    |argv, runtime| Jobs.run(argv, runtime)),
+    ("printf",  |argv, runtime| Printf.run(argv, runtime)),
+    ("pwd",     |argv, runtime| Pwd.run(argv, runtime)),
+    ("read",    |argv, runtime| Read.run(argv, runtime)),
+    ("readonly", |argv, runtime| Readonly.run(argv, runtime)),
+    ("return",  |argv, runtime| Return.run(argv, runtime)),
+    ("set",     |argv, runtime| Set.run(argv, runtime)),
+    ("shift",   |argv, runtime| Shift.run(argv, runtime)),
+    ("test",    |argv, runtime| Test.run(argv, runtime)),
+    ("times",   |argv, runtime| Times.run(argv, runtime)),
+    ("trap",    |argv, runtime| Trap.run(argv, runtime)),
+    ("true",    |argv, runtime| Status(0).run(argv, runtime)),
+    ("ulimit",  |argv, runtime| Ulimit.run(argv, runtime)),
+    ("umask",   |argv, runtime| Umask.run(argv, runtime)),
+    ("unset",   |argv, runtime| Unset.run(argv, runtime)),
+    ("wait",    |argv, runtime| Wait.run(argv, runtime)),
+];
+
+/// Find the builtin with the given name, if there is one.
+///
+/// This is how the shell decides what's a builtin, both to run it and to
+/// describe it, as `command -v` does.
+pub fn lookup(name: &str) -> Option<BuiltinFn> {
+    static TABLE: OnceLock<HashMap<&str, BuiltinFn>> = OnceLock::new();
+    TABLE.get_or_init(|| BUILTINS.iter().copied().collect()).get(name).copied()
+}
+
+/// Find the builtin with the given name, unless it's been turned off with
+/// `enable -n`.
+pub fn enabled(name: &str, runtime: &Runtime) -> Option<BuiltinFn> {
+    lookup(name).filter(|_| !runtime.disabled_builtins.contains(name))
+}
+
+/// The names of all the builtins, in order.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    BUILTINS.iter().map(|(name, _)| *name)
+}
+
+/// Parse and run the text in the shell itself, as `.` and `command` do.
+///
+/// A syntax error in the text ends a shell which isn't interactive, as it
+/// would in a script, otherwise the status is 2.
+pub(crate) fn run_text(text: &str, runtime: &mut Runtime) -> Result<WaitStatus> {
+    match parse_and_run(text, runtime) {
+        Err(Error::Parse(_)) if runtime.interactive || runtime.embedded => {
+            Ok(WaitStatus::Exited(Pid::this(), 2))
+        },
+        result => result,
+    }
+}
+
 mod cd;
 pub use self::cd::Cd;
 mod command;
 pub use self::command::Command;
+mod disown;
+pub use self::disown::Disown;
 mod dot;
 pub use self::dot::Dot;
+mod echo;
+pub use self::echo::Echo;
+mod enable;
+pub use self::enable::Enable;
+mod exec;
+pub use self::exec::Exec;
 mod exit;
 pub use self::exit::Exit;
 mod export;
 pub use self::export::Export;
+mod getopts;
+pub use self::getopts::Getopts;
+#[cfg(feature = "history")]
+mod history;
+#[cfg(feature = "history")]
+pub use self::history::History;
 mod jobs;
 pub use self::jobs::Jobs;
+mod printf;
+pub use self::printf::Printf;
+mod pwd;
+pub use self::pwd::Pwd;
+mod read;
+pub use self::read::Read;
+mod readonly;
+pub use self::readonly::Readonly;
 mod r#return;
 pub use self::r#return::Return;
+mod set;
+pub use self::set::Set;
+mod shift;
+pub use self::shift::Shift;
+mod shell_builtin;
+pub use self::shell_builtin::ShellBuiltin;
+mod status;
+pub use self::status::Status;
+mod test;
+pub use self::test::Test;
+mod times;
+pub use self::times::Times;
+mod trap;
+pub use self::trap::Trap;
+pub(crate) use self::trap::{install, pending};
+mod ulimit;
+pub use self::ulimit::Ulimit;
+mod umask;
+pub use self::umask::Umask;
+mod unset;
+pub use self::unset::Unset;
 mod wait;
 pub use self::wait::Wait;
@@ -0,0 +1,262 @@
+use std::{
+    io::Write,
+    ffi::CString,
+    iter::Peekable,
+    str::Chars,
+};
+use nix::{
+    unistd::Pid,
+    sys::wait::WaitStatus,
+};
+use crate::{
+    program::posix::builtin::Builtin,
+    program::{Result, Runtime},
+};
+
+/// Formatted output builtin, `printf FORMAT [ARGUMENT...]`.
+///
+/// The format is written with its `\` escapes like `\n` and `\t`
+/// interpreted, and each conversion replaced by the next argument.
+///
+/// - `%s` is a string, and `%c` its first character.
+/// - `%d` and `%i` are decimal integers, `%u` unsigned, `%o` octal, and `%x`
+///   or `%X` hexadecimal.
+/// - `%%` is a `%`.
+///
+/// Conversions take the flags `-`, `+`, ` `, `0` and `#`, a field width,
+/// and a precision, as in `%-8.3s`. The format is reused while there are
+/// more arguments than conversions, and missing arguments are empty, or
+/// zero.
+pub struct Printf;
+
+impl Builtin for Printf {
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        let args = argv.iter().skip(1).map(|a| a.to_string_lossy()).collect::<Vec<_>>();
+        let (format, args) = match args.split_first() {
+            Some((format, args)) => (format, args),
+            None => {
+                let _ = writeln!(runtime.io.stderr(),
+                                 "oursh: printf: usage: printf format [arguments]");
+                return Ok(WaitStatus::Exited(Pid::this(), 2));
+            },
+        };
+
+        let mut printer = Printer {
+            args: args.iter().map(|a| a.as_ref()).collect(),
+            next: 0,
+            output: vec![],
+            errors: vec![],
+        };
+        loop {
+            let next = printer.next;
+            match printer.print(format) {
+                // Reuse the format only while it takes more arguments.
+                Ok(true) if printer.next > next && printer.next < printer.args.len() => {},
+                Ok(_) => break,
+                Err(error) => {
+                    printer.errors.push(error);
+                    break;
+                },
+            }
+        }
+
+        let _ = runtime.io.stdout().write_all(&printer.output);
+        for error in &printer.errors {
+            let _ = writeln!(runtime.io.stderr(), "oursh: printf: {}", error);
+        }
+        let status = if printer.errors.is_empty() { 0 } else { 1 };
+        Ok(WaitStatus::Exited(Pid::this(), status))
+    }
+}
+
+struct Printer<'a> {
+    args: Vec<&'a str>,
+    // The index of the next argument to convert.
+    next: usize,
+    output: Vec<u8>,
+    // Invalid numbers, which are reported after the output.
+    errors: Vec<String>,
+}
+
+impl<'a> Printer<'a> {
+    // Print the format once, returning false if a `\c` ended the output.
+    fn print(&mut self, format: &str) -> std::result::Result<bool, String> {
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => if !escape(&mut chars, &mut self.output) {
+                    return Ok(false);
+                },
+                '%' => self.convert(&mut chars)?,
+                c => push(&mut self.output, c),
+            }
+        }
+        Ok(true)
+    }
+
+    // Print the conversion following a `%`.
+    fn convert(&mut self, chars: &mut Peekable<Chars>) -> std::result::Result<(), String> {
+        let mut spec = String::from("%");
+        let mut flags = String::new();
+        while let Some(&c) = chars.peek() {
+            if !"-+ 0#".contains(c) {
+                break;
+            }
+            flags.push(c);
+            chars.next();
+        }
+        spec += &flags;
+        let width = digits(chars, &mut spec);
+        let precision = if chars.peek() == Some(&'.') {
+            spec.push('.');
+            chars.next();
+            Some(digits(chars, &mut spec))
+        } else {
+            None
+        };
+
+        let conversion = chars.next();
+        let text = match conversion {
+            Some('%') => "%".into(),
+            Some('s') => {
+                let arg = self.arg();
+                match precision {
+                    Some(p) => arg.chars().take(p).collect(),
+                    None => arg.into(),
+                }
+            },
+            Some('c') => self.arg().chars().take(1).collect(),
+            Some(c @ ('d' | 'i' | 'u' | 'o' | 'x' | 'X')) => {
+                let n = self.number();
+                let (sign, digits) = match c {
+                    'd' | 'i' if n < 0 => ("-", n.unsigned_abs().to_string()),
+                    'd' | 'i' if flags.contains('+') => ("+", n.to_string()),
+                    'd' | 'i' if flags.contains(' ') => (" ", n.to_string()),
+                    'd' | 'i' | 'u' => ("", (n as u64).to_string()),
+                    'o' if flags.contains('#') && n != 0 => ("0", format!("{:o}", n)),
+                    'o' => ("", format!("{:o}", n)),
+                    'x' if flags.contains('#') && n != 0 => ("0x", format!("{:x}", n)),
+                    'x' => ("", format!("{:x}", n)),
+                    'X' if flags.contains('#') && n != 0 => ("0X", format!("{:X}", n)),
+                    _ => ("", format!("{:X}", n)),
+                };
+                let digits = match precision {
+                    Some(p) => format!("{:0>p$}", digits, p = p),
+                    None => digits,
+                };
+                // Zeros go between the sign and the digits.
+                if flags.contains('0') && !flags.contains('-') && precision.is_none() {
+                    let p = width.saturating_sub(sign.len());
+                    format!("{}{:0>p$}", sign, digits, p = p)
+                } else {
+                    format!("{}{}", sign, digits)
+                }
+            },
+            Some(c) => return Err(format!("{}{}: invalid directive", spec, c)),
+            None => return Err(format!("{}: missing format character", spec)),
+        };
+
+        let padding = width.saturating_sub(text.chars().count());
+        if flags.contains('-') {
+            self.output.extend(text.as_bytes());
+            self.output.extend(" ".repeat(padding).as_bytes());
+        } else {
+            self.output.extend(" ".repeat(padding).as_bytes());
+            self.output.extend(text.as_bytes());
+        }
+        Ok(())
+    }
+
+    // The next argument, or an empty string once they've run out.
+    fn arg(&mut self) -> &'a str {
+        let arg = self.args.get(self.next).copied().unwrap_or("");
+        self.next += 1;
+        arg
+    }
+
+    // The next argument as an integer, which is decimal, octal with a
+    // leading `0`, hexadecimal with a leading `0x`, or the character code
+    // of whatever follows a leading quote.
+    fn number(&mut self) -> i64 {
+        let arg = self.arg();
+        if let Some(rest) = arg.strip_prefix('\'').or_else(|| arg.strip_prefix('"')) {
+            return rest.chars().next().map(|c| c as i64).unwrap_or(0);
+        }
+
+        let text = arg.trim();
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(text) => (true, text),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let parsed = if arg.is_empty() {
+            Ok(0)
+        } else if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            i64::from_str_radix(hex, 16)
+        } else if text.len() > 1 && text.starts_with('0') {
+            i64::from_str_radix(&text[1..], 8)
+        } else {
+            text.parse()
+        };
+        match parsed {
+            Ok(n) if negative => -n,
+            Ok(n) => n,
+            Err(_) => {
+                self.errors.push(format!("{}: invalid number", arg));
+                0
+            },
+        }
+    }
+}
+
+// Read the digits of a width or precision, also adding them to the spec.
+fn digits(chars: &mut Peekable<Chars>, spec: &mut String) -> usize {
+    let mut n = 0;
+    while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+        spec.push(chars.next().unwrap());
+        n = n * 10 + d as usize;
+    }
+    n
+}
+
+// Print the escaped character following a `\`, returning false for `\c`,
+// which ends the output.
+fn escape(chars: &mut Peekable<Chars>, output: &mut Vec<u8>) -> bool {
+    let c = match chars.next() {
+        Some('a') => '\x07',
+        Some('b') => '\x08',
+        Some('c') => return false,
+        Some('f') => '\x0c',
+        Some('n') => '\n',
+        Some('r') => '\r',
+        Some('t') => '\t',
+        Some('v') => '\x0b',
+        Some(c @ ('\\' | '"' | '\'')) => c,
+        Some(c @ '0'..='7') => {
+            // Up to three octal digits make a single byte.
+            let mut byte = c.to_digit(8).unwrap();
+            for _ in 0..2 {
+                match chars.peek().and_then(|c| c.to_digit(8)) {
+                    Some(d) => {
+                        byte = byte * 8 + d;
+                        chars.next();
+                    },
+                    None => break,
+                }
+            }
+            output.push(byte as u8);
+            return true;
+        },
+        Some(c) => {
+            output.push(b'\\');
+            c
+        },
+        None => '\\',
+    };
+    push(output, c);
+    true
+}
+
+fn push(output: &mut Vec<u8>, c: char) {
+    let mut buffer = [0; 4];
+    output.extend(c.encode_utf8(&mut buffer).as_bytes());
+}
@@ -0,0 +1,46 @@
+use std::{
+    env,
+    fs,
+    io::Write,
+    ffi::CString,
+};
+use nix::{
+    unistd::Pid,
+    sys::wait::WaitStatus,
+};
+use crate::{
+    program::posix::builtin::Builtin,
+    program::{Result, Runtime},
+};
+
+/// Print working directory (`pwd`) builtin.
+///
+/// Prints `$PWD` when it names the working directory, so paths through
+/// symlinks are kept. With `-P` the physical directory is printed instead.
+pub struct Pwd;
+
+impl Builtin for Pwd {
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        let physical = argv[1..].iter().fold(false, |physical, arg| {
+            match arg.to_bytes() {
+                b"-P" => true,
+                b"-L" => false,
+                _ => physical,
+            }
+        });
+        let current = match env::current_dir() {
+            Ok(dir) => dir,
+            Err(e) => {
+                let _ = writeln!(runtime.io.stderr(), "oursh: pwd: {}", e);
+                return Ok(WaitStatus::Exited(Pid::this(), 1));
+            },
+        };
+        let pwd = runtime.variables.get("PWD").filter(|pwd| {
+            !physical && pwd.starts_with('/') &&
+                fs::canonicalize(pwd).map(|p| p == current).unwrap_or(false)
+        }).map(String::from);
+        let dir = pwd.unwrap_or_else(|| current.to_string_lossy().into_owned());
+        let _ = writeln!(runtime.io.stdout(), "{}", dir);
+        Ok(WaitStatus::Exited(Pid::this(), 0))
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    io::Write,
+    ffi::CString,
+};
+use nix::{
+    unistd::{self, Pid},
+    sys::wait::WaitStatus,
+};
+use crate::{
+    program::posix::builtin::Builtin,
+    program::posix::{field::{self, Splitter}, lex::is_name},
+    program::{Result, Runtime},
+};
+
+/// Read a line from stdin into variables, `read [-r] name...`.
+///
+/// The line is split into fields at the characters of `$IFS`, as for the
+/// words of a command, and each field is assigned to the next name. The
+/// last name gets the rest of the line, and names without a field are set
+/// to the empty string.
+///
+/// Unless `-r` is given a `\` escapes the next character, which is then
+/// never a delimiter, and a `\` at the end of a line continues it onto the
+/// next.
+///
+/// The status is 1 when the end of the input is reached first.
+pub struct Read;
+
+impl Builtin for Read {
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        let mut args = argv.iter().skip(1).map(|a| a.to_string_lossy().into_owned()).peekable();
+        let raw = args.next_if(|a| a == "-r").is_some();
+        args.next_if(|a| a == "--");
+        let names = args.collect::<Vec<_>>();
+        if names.is_empty() {
+            let _ = writeln!(runtime.io.stderr(), "oursh: read: usage: read [-r] name...");
+            return Ok(WaitStatus::Exited(Pid::this(), 2));
+        }
+        if let Some(name) = names.iter().find(|n| !is_name(n)) {
+            let _ = writeln!(runtime.io.stderr(), "oursh: read: `{}': not a valid identifier",
+                             name);
+            return Ok(WaitStatus::Exited(Pid::this(), 2));
+        }
+        if let Some(name) = names.iter().find(|n| runtime.variables.is_readonly(n)) {
+            let _ = writeln!(runtime.io.stderr(), "oursh: {}: readonly variable", name);
+            return Ok(WaitStatus::Exited(Pid::this(), 1));
+        }
+
+        // Read a byte at a time, so nothing after the line is consumed.
+        let mut line = vec![];
+        let mut escaped = vec![];
+        let mut escape = false;
+        let mut complete = false;
+        let mut byte = [0];
+        while let Ok(1) = unistd::read(runtime.io.0[0], &mut byte) {
+            match byte[0] {
+                b'\n' if escape => escape = false,
+                b'\n' => {
+                    complete = true;
+                    break;
+                },
+                b'\\' if !raw && !escape => escape = true,
+                b => {
+                    line.push(b);
+                    escaped.push(escape);
+                    escape = false;
+                },
+            }
+        }
+
+        let ifs = field::ifs(runtime).to_owned();
+        let mut splitter = Splitter::new(&ifs).limit(names.len());
+        let line = String::from_utf8_lossy(&line);
+        for (i, c) in line.char_indices() {
+            if escaped.get(i).copied().unwrap_or(false) {
+                splitter.push(c);
+            } else {
+                splitter.split(c.encode_utf8(&mut [0; 4]));
+            }
+        }
+        let mut fields = splitter.finish().into_iter();
+        for name in &names {
+            runtime.variables.set(name, fields.next().unwrap_or_default());
+        }
+
+        let status = if complete { 0 } else { 1 };
+        Ok(WaitStatus::Exited(Pid::this(), status))
+    }
+}
@@ -0,0 +1,67 @@
+use std::{
+    io::Write,
+    ffi::CString,
+};
+use nix::{
+    unistd::Pid,
+    sys::wait::WaitStatus,
+};
+use crate::{
+    program::posix::builtin::Builtin,
+    program::posix::lex::is_name,
+    program::{Result, Runtime},
+};
+
+/// Readonly builtin, used to keep variables from being changed.
+///
+/// - `readonly` (or `readonly -p`) prints the readonly variables.
+/// - `readonly NAME` marks the variable `NAME` readonly.
+/// - `readonly NAME=value` sets the variable `NAME` and marks it readonly.
+///
+/// Once a variable is readonly it can't be assigned or unset for the rest
+/// of the shell's life, and trying to is an error.
+pub struct Readonly;
+
+impl Builtin for Readonly {
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        let args = argv[1..].iter().map(|a| {
+            a.to_string_lossy().into_owned()
+        }).collect::<Vec<_>>();
+
+        if args.is_empty() || args == ["-p"] {
+            let variables = &runtime.variables;
+            for name in variables.readonly_names() {
+                let _ = match variables.get(name) {
+                    Some(value) => writeln!(runtime.io.stdout(), "readonly {}='{}'",
+                                            name, value.replace('\'', "'\\''")),
+                    None => writeln!(runtime.io.stdout(), "readonly {}", name),
+                };
+            }
+            return Ok(WaitStatus::Exited(Pid::this(), 0));
+        }
+
+        let mut status = 0;
+        for arg in args {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (arg.as_str(), None),
+            };
+            if !is_name(name) {
+                let _ = writeln!(runtime.io.stderr(),
+                                 "oursh: readonly: `{}': not a valid identifier", arg);
+                status = 1;
+                continue;
+            }
+            if let Some(value) = value {
+                if runtime.variables.is_readonly(name) {
+                    let _ = writeln!(runtime.io.stderr(), "oursh: {}: readonly variable", name);
+                    status = 1;
+                    continue;
+                }
+                runtime.variables.set(name, value);
+            }
+            runtime.variables.readonly(name);
+        }
+        Ok(WaitStatus::Exited(Pid::this(), status))
+    }
+}
@@ -1,18 +1,47 @@
-use std::ffi::CString;
+use std::{
+    io::Write,
+    ffi::CString,
+};
 use nix::{
     unistd::Pid,
     sys::wait::WaitStatus,
 };
 use crate::{
     program::posix::builtin::Builtin,
-    program::{Result, Runtime},
+    program::{Result, Error, Runtime},
 };
 
-/// Noop builtin, same idea as `true`.
-pub struct Return(pub i32);
+/// Return from a function, or a script run by `.`, `return [n]`.
+///
+/// The status is taken modulo 256, like for `exit`, and is the last
+/// command's, `$?`, without one.
+/// A status which isn't a number is an error, and returns 2. Outside of a
+/// function or script, or given more than one argument, nothing is
+/// returned from and the status is 1.
+pub struct Return;
 
 impl Builtin for Return {
-    fn run(self, _: Vec<CString>, _: &mut Runtime) -> Result<WaitStatus> {
-        Ok(WaitStatus::Exited(Pid::this(), self.0))
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        if runtime.calls == 0 {
+            let _ = writeln!(runtime.io.stderr(),
+                             "oursh: return: can only return from a function or sourced script");
+            return Ok(WaitStatus::Exited(Pid::this(), 1));
+        }
+        let code = match argv.get(1).map(|a| a.to_string_lossy()) {
+            None => runtime.last_status,
+            Some(arg) => match arg.trim().parse::<i64>() {
+                Ok(_) if argv.len() > 2 => {
+                    let _ = writeln!(runtime.io.stderr(), "oursh: return: too many arguments");
+                    return Ok(WaitStatus::Exited(Pid::this(), 1));
+                },
+                Ok(code) => (code & 0xff) as i32,
+                Err(_) => {
+                    let _ = writeln!(runtime.io.stderr(),
+                                     "oursh: return: {}: numeric argument required", arg);
+                    2
+                },
+            },
+        };
+        Err(Error::Return(code))
     }
 }
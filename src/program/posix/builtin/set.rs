@@ -0,0 +1,112 @@
+use std::{
+    io::Write,
+    ffi::CString,
+};
+use nix::{
+    unistd::Pid,
+    sys::wait::WaitStatus,
+};
+use crate::{
+    program::posix::builtin::Builtin,
+    program::{Result, Runtime, Options},
+};
+
+/// Set builtin, used to view variables, set positional parameters and set
+/// options.
+///
+/// - `set` prints the shell variables in a form suitable for re-input.
+/// - `set --` clears the positional parameters.
+/// - `set -- a b` (or `set a b`) sets the positional parameters to `a b`.
+/// - `set -o NAME` turns on an option, and `set +o NAME` turns it off. See
+///   [`Options`](crate::program::Options) for the options.
+/// - `set -C` turns on the option with that flag, and `set +C` turns it off.
+///   Several flags can be given together, like `set -Cu`.
+/// - `set -o` prints the options, and `set +o` prints them as the commands
+///   which restore them.
+pub struct Set;
+
+impl Builtin for Set {
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        if argv.len() == 1 {
+            for (key, value) in runtime.variables.iter() {
+                let _ = writeln!(runtime.io.stdout(), "{}={}", key, quote(value));
+            }
+            return Ok(WaitStatus::Exited(Pid::this(), 0));
+        }
+
+        let args = argv[1..].iter().map(|a| {
+            a.to_string_lossy().into_owned()
+        }).collect::<Vec<_>>();
+
+        let mut args = &args[..];
+        while let Some(arg) = args.first() {
+            match arg.as_str() {
+                "--" => {
+                    runtime.positionals = args[1..].to_vec();
+                    break;
+                },
+                flag @ ("-o" | "+o") => {
+                    let on = flag == "-o";
+                    match args.get(1) {
+                        Some(name) => {
+                            match runtime.options.get_mut(name) {
+                                Some(option) => *option = on,
+                                None => {
+                                    let _ = writeln!(runtime.io.stderr(),
+                                                     "oursh: set: {}: invalid option name",
+                                                     name);
+                                    return Ok(WaitStatus::Exited(Pid::this(), 2));
+                                },
+                            }
+                            args = &args[2..];
+                        },
+                        None => {
+                            for name in Options::NAMES {
+                                let value = runtime.options.get(name).unwrap_or(false);
+                                let _ = if on {
+                                    let value = if value { "on" } else { "off" };
+                                    writeln!(runtime.io.stdout(), "{:<15} {}", name, value)
+                                } else {
+                                    let flag = if value { '-' } else { '+' };
+                                    writeln!(runtime.io.stdout(), "set {}o {}", flag, name)
+                                };
+                            }
+                            args = &args[1..];
+                        },
+                    }
+                },
+                a if a.len() > 1 && (a.starts_with('-') || a.starts_with('+')) &&
+                     a[1..].chars().all(|c| Options::FLAGS.iter().any(|f| f.0 == c)) => {
+                    let on = a.starts_with('-');
+                    for c in a[1..].chars() {
+                        let name = Options::FLAGS.iter().find(|f| f.0 == c).unwrap().1;
+                        if let Some(option) = runtime.options.get_mut(name) {
+                            *option = on;
+                        }
+                    }
+                    args = &args[1..];
+                },
+                a if a.starts_with('-') || a.starts_with('+') => {
+                    let _ = writeln!(runtime.io.stderr(), "oursh: set: {}: invalid option", a);
+                    return Ok(WaitStatus::Exited(Pid::this(), 2));
+                },
+                _ => {
+                    runtime.positionals = args.to_vec();
+                    break;
+                },
+            }
+        }
+        Ok(WaitStatus::Exited(Pid::this(), 0))
+    }
+}
+
+// Single quote the value unless it's made up of only safe characters.
+fn quote(value: &str) -> String {
+    if !value.is_empty() && value.chars().all(|c| {
+        c.is_ascii_alphanumeric() || "_-+=./:,@%".contains(c)
+    }) {
+        value.into()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
@@ -0,0 +1,36 @@
+use std::{
+    io::Write,
+    ffi::CString,
+};
+use nix::{
+    unistd::Pid,
+    sys::wait::WaitStatus,
+};
+use crate::{
+    program::posix::builtin::{self, Builtin},
+    program::{Result, Runtime},
+};
+
+/// Builtin builtin, `builtin name [argument...]`.
+///
+/// Runs the builtin `name`, even when a function of the same name would be
+/// found first. It's an error if there's no such builtin, or it's been
+/// turned off with `enable -n`.
+pub struct ShellBuiltin;
+
+impl Builtin for ShellBuiltin {
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        let name = match argv.get(1) {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => return Ok(WaitStatus::Exited(Pid::this(), 0)),
+        };
+        match builtin::enabled(&name, runtime) {
+            Some(builtin) => builtin(argv[1..].to_vec(), runtime),
+            None => {
+                let _ = writeln!(runtime.io.stderr(), "oursh: builtin: {}: not a shell builtin",
+                                 name);
+                Ok(WaitStatus::Exited(Pid::this(), 1))
+            },
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::{
+    io::Write,
+    ffi::CString,
+};
+use nix::{
+    unistd::Pid,
+    sys::wait::WaitStatus,
+};
+use crate::{
+    program::posix::builtin::Builtin,
+    program::{Result, Runtime},
+};
+
+/// Shift builtin, drops the first `n` positional parameters (1 by default)
+/// and renumbers the rest.
+///
+/// Shifting more parameters than there are is an error, and leaves them
+/// unchanged.
+pub struct Shift;
+
+impl Builtin for Shift {
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        let n = match argv.len() {
+            0 => panic!("command name not passed in argv[0]"),
+            1 => 1,
+            2 => {
+                let arg = argv[1].to_string_lossy();
+                match arg.parse::<usize>() {
+                    Ok(n) => n,
+                    Err(_) => {
+                        let _ = writeln!(runtime.io.stderr(),
+                                         "oursh: shift: {}: numeric argument required", arg);
+                        return Ok(WaitStatus::Exited(Pid::this(), 2));
+                    },
+                }
+            },
+            _ => {
+                let _ = writeln!(runtime.io.stderr(), "oursh: shift: too many arguments");
+                return Ok(WaitStatus::Exited(Pid::this(), 2));
+            },
+        };
+
+        if n > runtime.positionals.len() {
+            let _ = writeln!(runtime.io.stderr(), "oursh: shift: {}: shift count out of range", n);
+            return Ok(WaitStatus::Exited(Pid::this(), 1));
+        }
+        runtime.positionals.drain(..n);
+        Ok(WaitStatus::Exited(Pid::this(), 0))
+    }
+}
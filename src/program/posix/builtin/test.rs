@@ -0,0 +1,217 @@
+use std::{
+    io::Write,
+    ffi::CString,
+    fs::{self, Metadata},
+    os::unix::fs::{FileTypeExt, MetadataExt},
+};
+use nix::{
+    unistd::{access, isatty, AccessFlags, Pid},
+    sys::wait::WaitStatus,
+};
+use crate::{
+    program::posix::builtin::Builtin,
+    program::{Result, Runtime},
+};
+
+/// Conditional expression builtin, `test` and `[`.
+///
+/// The status is 0 when the expression is true, 1 when it's false, and 2
+/// when it's invalid, like `[ "$1" -eq 1 ]` when `$1` isn't an integer.
+///
+/// - `-n STRING` and `-z STRING` test for a non-empty or empty string.
+/// - `-e`, `-f`, `-d`, `-h`/`-L`, `-p`, `-S`, `-b` and `-c` test that a file
+///   exists, and which type it is.
+/// - `-r`, `-w`, `-x`, `-s`, `-u` and `-g` test a file's permissions, size
+///   and set-user/group-ID bits.
+/// - `-t FD` tests that a file descriptor is a terminal.
+/// - `=`, `!=`, `<` and `>` compare strings.
+/// - `-eq`, `-ne`, `-lt`, `-le`, `-gt` and `-ge` compare integers.
+/// - `-nt`, `-ot` and `-ef` compare files' modification times and identity.
+/// - `! EXPR`, `EXPR -a EXPR`, `EXPR -o EXPR` and `( EXPR )` combine them.
+pub struct Test;
+
+impl Builtin for Test {
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        let name = argv[0].to_string_lossy().into_owned();
+        let args = argv.iter().skip(1).map(|a| a.to_string_lossy()).collect::<Vec<_>>();
+        let mut args = args.iter().map(|a| a.as_ref()).collect::<Vec<_>>();
+        if name == "[" && args.pop() != Some("]") {
+            let _ = writeln!(runtime.io.stderr(), "oursh: [: missing `]'");
+            return Ok(WaitStatus::Exited(Pid::this(), 2));
+        }
+
+        let code = match evaluate(&args) {
+            Ok(true) => 0,
+            Ok(false) => 1,
+            Err(message) => {
+                let _ = writeln!(runtime.io.stderr(), "oursh: {}: {}", name, message);
+                2
+            },
+        };
+        Ok(WaitStatus::Exited(Pid::this(), code))
+    }
+}
+
+// Evaluate the arguments, first by their number as POSIX specifies, so
+// that operands which look like operators are still treated as operands,
+// then as a general expression.
+fn evaluate(args: &[&str]) -> std::result::Result<bool, String> {
+    match args {
+        [] => Ok(false),
+        [a] => Ok(!a.is_empty()),
+        ["!", a] => Ok(a.is_empty()),
+        [op, a] => unary(op, a),
+        [a, op, b] if is_binary(op) => binary(a, op, b),
+        ["!", ..] if args.len() <= 4 => evaluate(&args[1..]).map(|b| !b),
+        ["(", a, ")"] => Ok(!a.is_empty()),
+        ["(", a, b, ")"] => evaluate(&[*a, *b]),
+        _ => {
+            let mut parser = Parser { args, position: 0 };
+            let result = parser.or()?;
+            match args.get(parser.position) {
+                Some(arg) => Err(format!("{}: binary operator expected", arg)),
+                None => Ok(result),
+            }
+        },
+    }
+}
+
+// A recursive descent parser of the operators, from loosest to tightest
+// binding: `-o`, `-a`, `!`, then parentheses and the primaries.
+struct Parser<'a> {
+    args: &'a [&'a str],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn or(&mut self) -> std::result::Result<bool, String> {
+        let mut result = self.and()?;
+        while self.next_if("-o") {
+            result |= self.and()?;
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> std::result::Result<bool, String> {
+        let mut result = self.not()?;
+        while self.next_if("-a") {
+            result &= self.not()?;
+        }
+        Ok(result)
+    }
+
+    fn not(&mut self) -> std::result::Result<bool, String> {
+        if self.next_if("!") {
+            self.not().map(|b| !b)
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> std::result::Result<bool, String> {
+        let rest = &self.args[self.position..];
+        match rest {
+            [] => Err("argument expected".into()),
+            ["(", ..] => {
+                self.position += 1;
+                let result = self.or()?;
+                if self.next_if(")") {
+                    Ok(result)
+                } else {
+                    Err("missing `)'".into())
+                }
+            },
+            [a, op, b, ..] if is_binary(op) => {
+                self.position += 3;
+                binary(a, op, b)
+            },
+            [op, a, ..] if is_unary(op) => {
+                self.position += 2;
+                unary(op, a)
+            },
+            [a, ..] => {
+                self.position += 1;
+                Ok(!a.is_empty())
+            },
+        }
+    }
+
+    fn next_if(&mut self, arg: &str) -> bool {
+        let found = self.args.get(self.position) == Some(&arg);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+}
+
+fn is_unary(op: &str) -> bool {
+    matches!(op, "-b" | "-c" | "-d" | "-e" | "-f" | "-g" | "-h" | "-L" | "-n" |
+                 "-p" | "-r" | "-s" | "-S" | "-t" | "-u" | "-w" | "-x" | "-z")
+}
+
+fn is_binary(op: &str) -> bool {
+    matches!(op, "=" | "==" | "!=" | "<" | ">" | "-eq" | "-ne" | "-lt" | "-le" |
+                 "-gt" | "-ge" | "-nt" | "-ot" | "-ef")
+}
+
+fn unary(op: &str, a: &str) -> std::result::Result<bool, String> {
+    let metadata = || fs::metadata(a).ok();
+    let is = |test: fn(&Metadata) -> bool| metadata().map(|m| test(&m)).unwrap_or(false);
+    Ok(match op {
+        "-n" => !a.is_empty(),
+        "-z" => a.is_empty(),
+        "-e" => metadata().is_some(),
+        "-f" => is(|m| m.is_file()),
+        "-d" => is(|m| m.is_dir()),
+        "-b" => is(|m| m.file_type().is_block_device()),
+        "-c" => is(|m| m.file_type().is_char_device()),
+        "-p" => is(|m| m.file_type().is_fifo()),
+        "-S" => is(|m| m.file_type().is_socket()),
+        "-h" | "-L" => fs::symlink_metadata(a).map(|m| m.file_type().is_symlink())
+                                              .unwrap_or(false),
+        "-s" => is(|m| m.len() > 0),
+        "-u" => is(|m| m.mode() & 0o4000 != 0),
+        "-g" => is(|m| m.mode() & 0o2000 != 0),
+        "-r" => access(a, AccessFlags::R_OK).is_ok(),
+        "-w" => access(a, AccessFlags::W_OK).is_ok(),
+        "-x" => access(a, AccessFlags::X_OK).is_ok(),
+        "-t" => isatty(integer(a)? as i32).unwrap_or(false),
+        _ => return Err(format!("{}: unary operator expected", op)),
+    })
+}
+
+fn binary(a: &str, op: &str, b: &str) -> std::result::Result<bool, String> {
+    let modified = |path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    Ok(match op {
+        "=" | "==" => a == b,
+        "!=" => a != b,
+        "<" => a < b,
+        ">" => a > b,
+        "-eq" => integer(a)? == integer(b)?,
+        "-ne" => integer(a)? != integer(b)?,
+        "-lt" => integer(a)? < integer(b)?,
+        "-le" => integer(a)? <= integer(b)?,
+        "-gt" => integer(a)? > integer(b)?,
+        "-ge" => integer(a)? >= integer(b)?,
+        "-nt" => match (modified(a), modified(b)) {
+            (Some(a), Some(b)) => a > b,
+            (a, b) => a.is_some() && b.is_none(),
+        },
+        "-ot" => match (modified(a), modified(b)) {
+            (Some(a), Some(b)) => a < b,
+            (a, b) => a.is_none() && b.is_some(),
+        },
+        "-ef" => match (fs::metadata(a), fs::metadata(b)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        },
+        _ => return Err(format!("{}: binary operator expected", op)),
+    })
+}
+
+// Parse an operand of an integer comparison, surrounding blanks allowed.
+fn integer(text: &str) -> std::result::Result<i64, String> {
+    text.trim().parse().map_err(|_| format!("{}: integer expression expected", text))
+}
+
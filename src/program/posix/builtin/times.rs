@@ -0,0 +1,46 @@
+use std::{
+    io::Write,
+    ffi::CString,
+    mem::MaybeUninit,
+};
+use nix::{
+    libc,
+    unistd::Pid,
+    sys::wait::WaitStatus,
+};
+use crate::{
+    program::posix::builtin::Builtin,
+    program::{Result, Runtime},
+};
+
+/// Times builtin, prints the user and system CPU time of the shell, and then
+/// of its children, like `0m0.010000s 0m0.003000s`.
+pub struct Times;
+
+impl Builtin for Times {
+    fn run(self, _: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        for who in [libc::RUSAGE_SELF, libc::RUSAGE_CHILDREN] {
+            let (user, system) = usage(who);
+            let _ = writeln!(runtime.io.stdout(), "{} {}", format(user), format(system));
+        }
+        Ok(WaitStatus::Exited(Pid::this(), 0))
+    }
+}
+
+// The user and system time used, in seconds.
+fn usage(who: libc::c_int) -> (f64, f64) {
+    let mut usage = MaybeUninit::<libc::rusage>::uninit();
+    // Only an invalid `who` fails.
+    let usage = unsafe {
+        libc::getrusage(who, usage.as_mut_ptr());
+        usage.assume_init()
+    };
+    let seconds = |t: libc::timeval| t.tv_sec as f64 + t.tv_usec as f64 / 1e6;
+    (seconds(usage.ru_utime), seconds(usage.ru_stime))
+}
+
+// Format the time in minutes and seconds.
+fn format(seconds: f64) -> String {
+    let minutes = (seconds / 60.0).floor();
+    format!("{}m{:.6}s", minutes, seconds - minutes * 60.0)
+}
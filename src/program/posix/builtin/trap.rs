@@ -0,0 +1,160 @@
+use std::{
+    io::Write,
+    cell::RefCell,
+    collections::BTreeMap,
+    convert::TryFrom,
+    ffi::CString,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use nix::{
+    libc::c_int,
+    unistd::Pid,
+    sys::{
+        signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal},
+        wait::WaitStatus,
+    },
+};
+use crate::{
+    program::posix::builtin::Builtin,
+    program::{Result, Runtime},
+};
+
+/// Trap builtin, used to set the action taken on a condition.
+///
+/// - `trap` prints the current traps in a form suitable for re-input.
+/// - `trap action condition...` runs `action` when a condition occurs.
+/// - `trap - condition...` resets the conditions to their defaults.
+///
+/// Conditions are `EXIT` (or `0`), and signal names or numbers. With the
+/// `modern` feature `DEBUG` is also a condition, run before each simple
+/// command, as is `ERR`, run after a command fails outside of a condition.
+///
+/// A signal with an empty action is ignored, and the action of any other is
+/// run between commands once the signal has arrived.
+pub struct Trap;
+
+impl Builtin for Trap {
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        let args = argv[1..].iter().map(|a| {
+            a.to_string_lossy().into_owned()
+        }).collect::<Vec<_>>();
+
+        if args.is_empty() {
+            for (condition, action) in runtime.traps.iter() {
+                let _ = writeln!(runtime.io.stdout(), "trap -- '{}' {}",
+                                 action.replace('\'', "'\\''"), condition);
+            }
+            return Ok(WaitStatus::Exited(Pid::this(), 0));
+        }
+
+        let (action, conditions) = match args[0].as_str() {
+            "--" => (args.get(1), args.get(2..).unwrap_or(&[])),
+            _ => (args.first(), &args[1..]),
+        };
+        // A lone condition resets it, like `trap - condition`.
+        let (action, conditions) = match action {
+            Some(a) if conditions.is_empty() && condition(a).is_some() => {
+                (None, &args[args.len() - 1..])
+            },
+            Some(a) if a == "-" => (None, conditions),
+            a => (a, conditions),
+        };
+
+        let mut status = 0;
+        for name in conditions {
+            match condition(name) {
+                Some(condition) => {
+                    if install(&condition, action.map(|a| a.as_str())).is_err() {
+                        let _ = writeln!(runtime.io.stderr(), "oursh: trap: {}: can't be trapped", name);
+                        status = 1;
+                        continue;
+                    }
+                    match action {
+                        Some(action) => {
+                            runtime.traps.insert(condition, action.clone());
+                        },
+                        None => {
+                            runtime.traps.remove(&condition);
+                        },
+                    }
+                },
+                None => {
+                    let _ = writeln!(runtime.io.stderr(), "oursh: trap: {}: invalid signal specification", name);
+                    status = 1;
+                },
+            }
+        }
+        Ok(WaitStatus::Exited(Pid::this(), status))
+    }
+}
+
+// Normalize the name of a trap condition, if it's valid.
+fn condition(name: &str) -> Option<String> {
+    match name {
+        "0" | "EXIT" => return Some("EXIT".into()),
+        #[cfg(feature = "modern")]
+        "DEBUG" | "ERR" => return Some(name.into()),
+        _ => {},
+    }
+
+    let signal = match name.parse::<i32>() {
+        Ok(n) => Signal::try_from(n).ok()?,
+        Err(_) if name.starts_with("SIG") => Signal::from_str(name).ok()?,
+        Err(_) => Signal::from_str(&format!("SIG{}", name)).ok()?,
+    };
+    Some(signal.as_str()[3..].into())
+}
+
+// The signals which have arrived since their traps were last run.
+static PENDING: [AtomicBool; 64] = [const { AtomicBool::new(false) }; 64];
+
+thread_local! {
+    // How the signals were handled before they were first trapped.
+    static ORIGINAL: RefCell<BTreeMap<c_int, SigAction>> = const {
+        RefCell::new(BTreeMap::new())
+    };
+}
+
+extern "C" fn caught(signal: c_int) {
+    if let Some(pending) = PENDING.get(signal as usize) {
+        pending.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Handle the signal of a trap condition, if it is one, for its new action.
+///
+/// An empty action ignores the signal, any other catches it to be run by
+/// [`pending`], and no action gives the signal back its original handling.
+pub(crate) fn install(condition: &str, action: Option<&str>) -> nix::Result<()> {
+    let signal = match Signal::from_str(&format!("SIG{}", condition)) {
+        Ok(signal) => signal,
+        Err(_) => return Ok(()),
+    };
+    let handler = match action {
+        Some("") => SigHandler::SigIgn,
+        Some(_) => SigHandler::Handler(caught),
+        None => {
+            if let Some(original) = ORIGINAL.with(|o| o.borrow_mut().remove(&(signal as c_int))) {
+                unsafe { sigaction(signal, &original)?; }
+            }
+            return Ok(());
+        },
+    };
+    let handling = SigAction::new(handler, SaFlags::SA_RESTART, SigSet::empty());
+    let original = unsafe { sigaction(signal, &handling)? };
+    ORIGINAL.with(|o| {
+        o.borrow_mut().entry(signal as c_int).or_insert(original);
+    });
+    Ok(())
+}
+
+/// The conditions of the caught signals which have arrived since this was
+/// last called.
+pub(crate) fn pending() -> Vec<String> {
+    PENDING.iter().enumerate().filter(|(_, pending)| {
+        pending.swap(false, Ordering::SeqCst)
+    }).filter_map(|(signal, _)| {
+        Signal::try_from(signal as i32).ok()
+    }).map(|signal| signal.as_str()[3..].into()).collect()
+}
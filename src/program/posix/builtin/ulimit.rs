@@ -0,0 +1,167 @@
+use std::{
+    io::Write,
+    ffi::CString,
+    mem::MaybeUninit,
+};
+use nix::{
+    errno::Errno,
+    libc,
+    unistd::Pid,
+    sys::wait::WaitStatus,
+};
+use crate::{
+    program::posix::builtin::Builtin,
+    program::{Result, Runtime},
+};
+
+/// Resource limits builtin, `ulimit [-H|-S] [-a|-cdflmnstuv] [limit]`.
+///
+/// - `ulimit -n` prints the limit on open files, and `ulimit -n 64` sets
+///   it. The file size limit `-f` is used when no resource is given.
+/// - `ulimit -a` prints all the limits.
+/// - `-H` and `-S` choose the hard or soft limit. Both are set when
+///   neither is given, and the soft limit is printed.
+///
+/// A limit is a number, or `unlimited`. Sizes are in kilobytes, except for
+/// the 512 byte blocks of `-c` and `-f`, and the CPU time `-t` is in
+/// seconds.
+///
+/// The limits are the process's own, so they apply to every command run
+/// after they're set.
+pub struct Ulimit;
+
+// The type of the resource constants, which differs between platforms.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+type Resource = libc::c_int;
+
+// The option, description, unit, size of the unit in bytes, and resource of
+// each limit.
+const LIMITS: &[(char, &str, &str, libc::rlim_t, Resource)] = &[
+    ('c', "core file size", "blocks", 512, libc::RLIMIT_CORE),
+    ('d', "data seg size", "kbytes", 1024, libc::RLIMIT_DATA),
+    ('f', "file size", "blocks", 512, libc::RLIMIT_FSIZE),
+    ('l', "max locked memory", "kbytes", 1024, libc::RLIMIT_MEMLOCK),
+    ('m', "max memory size", "kbytes", 1024, libc::RLIMIT_RSS),
+    ('n', "open files", "", 1, libc::RLIMIT_NOFILE),
+    ('s', "stack size", "kbytes", 1024, libc::RLIMIT_STACK),
+    ('t', "cpu time", "seconds", 1, libc::RLIMIT_CPU),
+    ('u', "max user processes", "", 1, libc::RLIMIT_NPROC),
+    ('v', "virtual memory", "kbytes", 1024, libc::RLIMIT_AS),
+];
+
+impl Builtin for Ulimit {
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        let (mut hard, mut soft) = (false, false);
+        let mut limits = vec![];
+        let mut value = None;
+        for arg in argv.iter().skip(1).map(|a| a.to_string_lossy()) {
+            let options = match arg.strip_prefix('-') {
+                Some(options) if !options.is_empty() && value.is_none() => options,
+                _ if value.is_none() => {
+                    value = Some(arg.into_owned());
+                    continue;
+                },
+                _ => {
+                    let _ = writeln!(runtime.io.stderr(), "oursh: ulimit: too many arguments");
+                    return Ok(WaitStatus::Exited(Pid::this(), 2));
+                },
+            };
+            for option in options.chars() {
+                match option {
+                    'H' => hard = true,
+                    'S' => soft = true,
+                    'a' => limits.extend(LIMITS),
+                    _ => match LIMITS.iter().find(|l| l.0 == option) {
+                        Some(limit) => limits.push(limit),
+                        None => {
+                            let _ = writeln!(runtime.io.stderr(),
+                                             "oursh: ulimit: -{}: invalid option", option);
+                            let _ = writeln!(runtime.io.stderr(),
+                                             "oursh: ulimit: usage: ulimit [-H|-S] \
+                                              [-a|-cdflmnstuv] [limit]");
+                            return Ok(WaitStatus::Exited(Pid::this(), 2));
+                        },
+                    },
+                }
+            }
+        }
+        if limits.is_empty() {
+            limits.extend(LIMITS.iter().find(|l| l.0 == 'f'));
+        }
+
+        let value = match value {
+            None => {
+                for &&(option, description, unit, size, resource) in &limits {
+                    let (current, maximum) = get(resource);
+                    let limit = if hard && !soft { maximum } else { current };
+                    let limit = if limit == libc::RLIM_INFINITY {
+                        "unlimited".into()
+                    } else {
+                        (limit / size).to_string()
+                    };
+                    // Several limits are labelled.
+                    if limits.len() > 1 {
+                        let label = match unit {
+                            "" => format!("(-{})", option),
+                            unit => format!("({}, -{})", unit, option),
+                        };
+                        let _ = writeln!(runtime.io.stdout(), "{:<20} {:<16} {}",
+                                         description, label, limit);
+                    } else {
+                        let _ = writeln!(runtime.io.stdout(), "{}", limit);
+                    }
+                }
+                return Ok(WaitStatus::Exited(Pid::this(), 0));
+            },
+            Some(value) => value,
+        };
+
+        for &&(_, description, _, size, resource) in &limits {
+            let limit = match value.as_str() {
+                "unlimited" => Some(libc::RLIM_INFINITY),
+                value => value.parse::<libc::rlim_t>().ok().and_then(|n| n.checked_mul(size)),
+            };
+            let limit = match limit {
+                Some(limit) => limit,
+                None => {
+                    let _ = writeln!(runtime.io.stderr(), "oursh: ulimit: {}: invalid number",
+                                     value);
+                    return Ok(WaitStatus::Exited(Pid::this(), 1));
+                },
+            };
+
+            // Without `-H` or `-S`, both limits are set.
+            let (current, maximum) = get(resource);
+            let (current, maximum) = match (hard, soft) {
+                (true, false) => (current.min(limit), limit),
+                (false, true) => (limit, maximum),
+                _ => (limit, limit),
+            };
+            if let Err(e) = set(resource, current, maximum) {
+                let _ = writeln!(runtime.io.stderr(),
+                                 "oursh: ulimit: {}: cannot modify limit: {}",
+                                 description, e.desc());
+                return Ok(WaitStatus::Exited(Pid::this(), 1));
+            }
+        }
+        Ok(WaitStatus::Exited(Pid::this(), 0))
+    }
+}
+
+// The soft and hard limits of the resource.
+fn get(resource: Resource) -> (libc::rlim_t, libc::rlim_t) {
+    let mut limit = MaybeUninit::<libc::rlimit>::uninit();
+    // Only an invalid resource fails.
+    let limit = unsafe {
+        libc::getrlimit(resource, limit.as_mut_ptr());
+        limit.assume_init()
+    };
+    (limit.rlim_cur, limit.rlim_max)
+}
+
+fn set(resource: Resource, current: libc::rlim_t, maximum: libc::rlim_t) -> nix::Result<()> {
+    let limit = libc::rlimit { rlim_cur: current, rlim_max: maximum };
+    Errno::result(unsafe { libc::setrlimit(resource, &limit) }).map(drop)
+}
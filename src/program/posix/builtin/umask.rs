@@ -0,0 +1,133 @@
+use std::{
+    io::Write,
+    ffi::CString,
+};
+use nix::{
+    unistd::Pid,
+    sys::{stat::{self, Mode}, wait::WaitStatus},
+};
+use crate::{
+    program::posix::builtin::Builtin,
+    program::{Result, Runtime},
+};
+
+/// File creation mask builtin.
+///
+/// - `umask` prints the mask in octal, like `0022`.
+/// - `umask -S` prints the permissions the mask allows, like
+///   `u=rwx,g=rx,o=rx`.
+/// - `umask 022` sets the mask in octal.
+/// - `umask u=rwx,g=rx,o=` sets the mask to deny the permissions which
+///   aren't given, and `+` or `-` add or remove permissions instead.
+///
+/// The mask is the process's own, so it applies to the files of later
+/// redirections as well as those of the commands which are run.
+pub struct Umask;
+
+impl Builtin for Umask {
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        let mut args = argv.iter().skip(1).map(|a| a.to_string_lossy());
+        let mut symbolic = false;
+        let mut arg = args.next();
+        if arg.as_deref() == Some("-S") {
+            symbolic = true;
+            arg = args.next();
+        }
+
+        match arg {
+            None => {
+                let mask = current();
+                if symbolic {
+                    let _ = writeln!(runtime.io.stdout(), "{}", format(mask));
+                } else {
+                    let _ = writeln!(runtime.io.stdout(), "{:04o}", mask);
+                }
+            },
+            Some(arg) => {
+                match parse(&arg, current()) {
+                    Some(mask) => {
+                        stat::umask(Mode::from_bits_truncate(mask));
+                    },
+                    None => {
+                        let _ = writeln!(runtime.io.stderr(),
+                                         "oursh: umask: {}: invalid mask", arg);
+                        return Ok(WaitStatus::Exited(Pid::this(), 1));
+                    },
+                }
+            },
+        }
+        Ok(WaitStatus::Exited(Pid::this(), 0))
+    }
+}
+
+/// The current file creation mask of the process.
+pub fn current() -> u32 {
+    // The mask can only be read by setting it, so put it right back.
+    let mask = stat::umask(Mode::empty());
+    stat::umask(mask);
+    mask.bits() as u32
+}
+
+// Parse an octal or symbolic mask, where the symbolic mask changes the
+// current one.
+fn parse(text: &str, mask: u32) -> Option<u32> {
+    if text.starts_with(|c: char| c.is_ascii_digit()) {
+        return u32::from_str_radix(text, 8).ok().filter(|m| *m <= 0o777);
+    }
+
+    // Symbolic modes are the permissions to allow, the complement of the
+    // mask.
+    let mut allowed = !mask & 0o777;
+    for clause in text.split(',') {
+        let op = clause.find(|c| "=+-".contains(c))?;
+        let (who, rest) = clause.split_at(op);
+        let mut who = who.chars().try_fold(0, |who, c| match c {
+            'u' => Some(who | 0o700),
+            'g' => Some(who | 0o070),
+            'o' => Some(who | 0o007),
+            'a' => Some(who | 0o777),
+            _ => None,
+        })?;
+        if who == 0 {
+            who = 0o777;
+        }
+
+        // Each clause may have many actions, like `u+r-w`.
+        let mut chars = rest.chars().peekable();
+        while let Some(op) = chars.next() {
+            let mut perms = 0;
+            while let Some(c) = chars.peek() {
+                perms |= match c {
+                    'r' => 0o444,
+                    'w' => 0o222,
+                    'x' => 0o111,
+                    '=' | '+' | '-' => break,
+                    _ => return None,
+                };
+                chars.next();
+            }
+            match op {
+                '=' => allowed = (allowed & !who) | (perms & who),
+                '+' => allowed |= perms & who,
+                '-' => allowed &= !(perms & who),
+                _ => return None,
+            }
+        }
+    }
+    Some(!allowed & 0o777)
+}
+
+// Format the permissions allowed by the mask symbolically.
+fn format(mask: u32) -> String {
+    let allowed = !mask;
+    ["u", "g", "o"].iter().enumerate().map(|(i, who)| {
+        let bits = allowed >> (6 - i * 3);
+        let mut perms = who.to_string() + "=";
+        for (bit, c) in [(0o4, 'r'), (0o2, 'w'), (0o1, 'x')] {
+            if bits & bit != 0 {
+                perms.push(c);
+            }
+        }
+        perms
+    }).collect::<Vec<_>>().join(",")
+}
@@ -0,0 +1,56 @@
+use std::{
+    io::Write,
+    ffi::CString,
+};
+use nix::{
+    unistd::Pid,
+    sys::wait::WaitStatus,
+};
+use crate::{
+    program::posix::builtin::Builtin,
+    program::{Result, Runtime},
+};
+
+/// Unset builtin, `unset [-v|-f] name...`.
+///
+/// - `unset NAME` (or `unset -v NAME`) removes the variable `NAME`, from
+///   the environment too if it was exported.
+/// - `unset -f NAME` removes the function `NAME`.
+///
+/// Unsetting something which isn't set is fine, but readonly variables
+/// can't be unset, and make the status 1.
+pub struct Unset;
+
+impl Builtin for Unset {
+    fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
+        let mut args = argv.iter().skip(1).map(|a| a.to_string_lossy().into_owned()).peekable();
+        let functions = match args.peek().map(|a| a.as_str()) {
+            Some("-f") => true,
+            Some("-v") => false,
+            Some(option) if option.starts_with('-') && option != "--" && option != "-" => {
+                let _ = writeln!(runtime.io.stderr(), "oursh: unset: {}: invalid option",
+                                 option);
+                let _ = writeln!(runtime.io.stderr(),
+                                 "oursh: unset: usage: unset [-v|-f] name...");
+                return Ok(WaitStatus::Exited(Pid::this(), 2));
+            },
+            _ => false,
+        };
+        args.next_if(|a| a == "-f" || a == "-v");
+        args.next_if(|a| a == "--");
+
+        let mut status = 0;
+        for name in args {
+            if functions {
+                runtime.functions.remove(&name);
+            } else if runtime.variables.is_readonly(&name) {
+                let _ = writeln!(runtime.io.stderr(),
+                                 "oursh: unset: {}: cannot unset: readonly variable", name);
+                status = 1;
+            } else {
+                runtime.variables.unset(&name);
+            }
+        }
+        Ok(WaitStatus::Exited(Pid::this(), status))
+    }
+}
@@ -1,42 +1,120 @@
-use std::ffi::CString;
+use std::{
+    io::Write,
+    ffi::CString,
+};
 use nix::{
     unistd::Pid,
-    sys::wait::WaitStatus,
+    sys::{
+        signal::{sigprocmask, SigSet, SigmaskHow, Signal},
+        wait::WaitStatus,
+    },
 };
 use crate::{
     program::posix::builtin::Builtin,
-    program::{Result, Error, Runtime},
-    process::Wait as WaitTrait,
+    program::{Result, Runtime},
+    process::{Wait as WaitTrait, exit_code, jobs::is_finished},
 };
 
-/// Wait builtin, used to block for all background jobs.
+/// Wait builtin, used to block for background jobs.
+///
+/// - `wait` waits for every job, returning 0.
+/// - `wait id...` waits for each job, given by its pid or a job spec like
+///   `%1` (see [`Runtime::job`]), returning the status of the last one. An
+///   unknown job has the status 127.
+/// - `wait -n` waits for whichever job finishes first, returning its
+///   status. Without any jobs the status is 127.
+///
+/// Jobs which have been waited for are removed from the job table.
 pub struct Wait;
 
 impl Builtin for Wait {
     fn run(self, argv: Vec<CString>, runtime: &mut Runtime) -> Result<WaitStatus> {
-        match argv.len() {
-            0 => unreachable!(),
-            1 => {
-                let mut last = Ok(WaitStatus::Exited(Pid::this(), 0));
-                for job in runtime.jobs.borrow().iter() {
-                    last = job.1.leader().wait().map_err(|_| Error::Runtime)
+        if argv.len() == 1 {
+            let jobs = runtime.jobs.borrow_mut().drain(..).collect::<Vec<_>>();
+            for (id, job) in jobs {
+                if let Ok(status) = job.leader().wait() {
+                    runtime.job_changed(&id, job.leader().pid(), status);
                 }
-                last
             }
-            n => {
-                let mut last = Ok(WaitStatus::Exited(Pid::this(), 0));
-                for i in 2..=n {
-                    let pid: i32 = argv[i-1].to_string_lossy().parse().unwrap();
-                    if let Some((_id, pg)) = runtime.jobs.borrow().iter().find(|(_, pg)| {
-                        pid == pg.leader().pid().as_raw()
-                    }) {
-                        last = pg.leader().wait().map_err(|_| Error::Runtime)
+            return Ok(WaitStatus::Exited(Pid::this(), 0));
+        }
+
+        if argv[1].to_bytes() == b"-n" {
+            return Ok(WaitStatus::Exited(Pid::this(), next(runtime)));
+        }
+
+        let mut last = 0;
+        for arg in &argv[1..] {
+            let arg = arg.to_string_lossy();
+            last = match runtime.job(&arg) {
+                Ok(i) => {
+                    let (id, job) = runtime.jobs.borrow_mut().remove(i);
+                    // The job may already have been reaped, or be known to
+                    // have finished, e.g. by `jobs`.
+                    if is_finished(job.state()) {
+                        exit_code(job.state())
                     } else {
-                        eprintln!("oursh: wait: pid {} is not a child of this shell", pid);
+                        match job.leader().wait() {
+                            Ok(status) => {
+                                runtime.job_changed(&id, job.leader().pid(), status);
+                                exit_code(status)
+                            },
+                            Err(_) => 0,
+                        }
                     }
-                }
-                last
-            },
+                },
+                Err(message) => {
+                    let _ = writeln!(runtime.io.stderr(), "oursh: wait: {}", message);
+                    127
+                },
+            };
         }
+        Ok(WaitStatus::Exited(Pid::this(), last))
     }
 }
+
+// Wait for the next job to finish, removing it and returning its status.
+fn next(runtime: &mut Runtime) -> i32 {
+    // SIGCHLD is held back from checking on the jobs until it's waited for,
+    // so a job which finishes in between isn't missed.
+    let mut children = SigSet::empty();
+    children.add(Signal::SIGCHLD);
+    let mut mask = SigSet::empty();
+    let _ = sigprocmask(SigmaskHow::SIG_BLOCK, Some(&children), Some(&mut mask));
+    let code = loop {
+        // Only the jobs are checked on, any other children are left for
+        // whatever is waiting on them.
+        let mut waiting = false;
+        let mut changes = vec![];
+        for (id, job) in runtime.jobs.borrow_mut().iter_mut() {
+            if is_finished(job.state()) {
+                continue;
+            }
+            match job.leader().status() {
+                Ok(WaitStatus::StillAlive) => waiting = true,
+                Ok(status) => {
+                    job.set_state(status);
+                    changes.push((id.clone(), job.leader().pid(), status));
+                    waiting |= !is_finished(status);
+                },
+                // The job isn't a child of this process, so there's no
+                // waiting for it.
+                Err(_) => {},
+            }
+        }
+        for (id, pid, status) in changes {
+            runtime.job_changed(&id, pid, status);
+        }
+
+        let i = runtime.jobs.borrow().iter().position(|(_, job)| is_finished(job.state()));
+        if let Some(i) = i {
+            let (_, job) = runtime.jobs.borrow_mut().remove(i);
+            break exit_code(job.state());
+        }
+        if !waiting || children.wait().is_err() {
+            break 127;
+        }
+    };
+    let _ = sigprocmask(SigmaskHow::SIG_SETMASK, Some(&mask), None);
+    code
+}
@@ -0,0 +1,868 @@
+//! Word expansion, which turns a word as it was written into its fields.
+//!
+//! The expansions are done in the order POSIX gives them: tilde expansion of
+//! a leading `~`, parameter expansion of `$NAME`, `${NAME}` and the special
+//! parameters, field splitting of the results of unquoted expansions,
+//! pathname expansion of unquoted patterns, and finally quote removal.
+//! Command substitution of `$(..)` and `` `..` ``, and arithmetic expansion
+//! of `$((..))`, are done along with parameter expansion.
+//!
+//! Single quotes preserve everything literally, while double quotes still
+//! allow parameter expansion, command substitution, arithmetic expansion and
+//! `\` escapes of `$`, `` ` ``, `"`, `\` and newlines.
+//!
+//! Expanding a word only changes the runtime by assigning a default, like
+//! `${x:=5}`, or by the status of a command substitution, so it's the same
+//! for the words of a command, and for anything else which needs their
+//! fields.
+use std::{
+    fs,
+    iter::Peekable,
+    mem,
+    result,
+    str::Chars,
+};
+use dirs::home_dir;
+use crate::program::{
+    Error,
+    Runtime,
+    parse_primary,
+    posix::{
+        arithmetic,
+        field::{self, Field, Splitter},
+        lex,
+        pattern::Pattern,
+        substitution::capture,
+    },
+};
+
+/// Expand a word into its fields.
+///
+/// Brace expansion, which makes many words from one, has already been
+/// done for the words of a command. The results of unquoted expansions are
+/// split at the characters of the current `$IFS`, and a field with an
+/// unquoted `*`, `?` or `[` is replaced by the pathnames it matches, in
+/// order, if there are any.
+///
+/// A word which expands to nothing at all, like an unquoted `$EMPTY`, has
+/// no fields.
+///
+/// With the `nounset` option, expanding a parameter which isn't set is an
+/// error, with a message saying which. `$@` and `$*` are fine without any
+/// positional parameters, as are the forms of `${...}` with a modifier,
+/// though not `${#...}`.
+///
+/// ```
+/// use oursh::program::{Runtime, posix::expand_word};
+///
+/// let mut runtime = Runtime::builder().env("X", " a  b").build().unwrap();
+/// assert_eq!(vec!["a", "b!"], expand_word("$X'!'", &mut runtime).unwrap());
+/// assert_eq!(vec![" a  b!"], expand_word("\"$X\"'!'", &mut runtime).unwrap());
+/// ```
+pub fn expand_word(word: &str, runtime: &mut Runtime) -> result::Result<Vec<String>, String> {
+    let ifs = field::ifs(runtime).to_string();
+    let fields = expand(word, false, Splitter::new(&ifs), runtime)?;
+    Ok(fields.into_iter().flat_map(|field| {
+        let paths = field.pattern.map(|pattern| pathnames(&pattern)).unwrap_or_default();
+        if paths.is_empty() {
+            vec![field.text]
+        } else {
+            paths
+        }
+    }).collect())
+}
+
+/// Expand each of the words into their fields, like [`expand_word`], all
+/// together in order.
+pub fn expand_words<'a>(words: impl IntoIterator<Item = &'a str>, runtime: &mut Runtime)
+    -> result::Result<Vec<String>, String>
+{
+    let mut fields = vec![];
+    for word in words {
+        fields.extend(expand_word(word, runtime)?);
+    }
+    Ok(fields)
+}
+
+/// Expand a word into a single value, like [`expand_word`], but without
+/// field splitting or pathname expansion, like the filename of a
+/// redirection.
+pub(crate) fn expand_string(word: &str, runtime: &mut Runtime) -> result::Result<String, String> {
+    expand(word, false, Splitter::new(""), runtime).map(|mut fields| {
+        fields.pop().map(|field| field.text).unwrap_or_default()
+    })
+}
+
+/// Expand the value of a variable assignment, like [`expand_string`], but
+/// also expanding a `~` after each unquoted `:`, as in `PATH=~/bin:~/sbin`.
+pub(crate) fn expand_assignment(value: &str, runtime: &mut Runtime)
+    -> result::Result<String, String>
+{
+    expand(value, true, Splitter::new(""), runtime).map(|mut fields| {
+        fields.pop().map(|field| field.text).unwrap_or_default()
+    })
+}
+
+/// Expand a word into a pattern, like a pattern of a `case`, without field
+/// splitting or pathname expansion. Only its unquoted `*`, `?` and `[` are
+/// special, including those from unquoted expansions.
+pub(crate) fn expand_pattern(word: &str, runtime: &mut Runtime)
+    -> result::Result<Pattern, String>
+{
+    let field = expand(word, false, Splitter::new(""), runtime)?.pop();
+    Ok(match field {
+        Some(Field { pattern: Some(pattern), .. }) => Pattern::new(&pattern),
+        Some(Field { text, .. }) => Pattern::new(&escape(&text)),
+        None => Pattern::new(""),
+    })
+}
+
+/// Expand the text of a here-document, like a word in double quotes,
+/// except that a `"` is just a `"`, and only `$`, `` ` ``, `\` and newlines
+/// can be escaped.
+pub(crate) fn expand_here(text: &str, runtime: &mut Runtime)
+    -> result::Result<String, String>
+{
+    let mut fields = Splitter::new("");
+    fields.start();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.peek() {
+                Some('\n') => { chars.next(); },
+                Some(&c) if "$`\\".contains(c) => {
+                    chars.next();
+                    fields.push(c);
+                },
+                _ => fields.push('\\'),
+            },
+            '$' => expand_parameter(&mut chars, true, &mut fields, runtime)?,
+            '`' => substitute(&backquoted(&mut chars, false), true, &mut fields, runtime)?,
+            c => fields.push(c),
+        }
+    }
+    // Like in `"$@"`, each positional parameter would be its own field.
+    let fields = fields.finish_fields().into_iter().map(|field| field.text);
+    Ok(fields.collect::<Vec<_>>().join(" "))
+}
+
+fn expand(word: &str, assignment: bool, mut fields: Splitter, runtime: &mut Runtime)
+    -> result::Result<Vec<Field>, String>
+{
+    expand_into(word, assignment, false, false, &mut fields, runtime)?;
+    Ok(fields.finish_fields())
+}
+
+// Expand the word onto the end of the fields, all of it as if it were in
+// double quotes when `quoted`. The unquoted text of a `nested` word, like
+// the word of a `${x:-word}`, is split like the rest of the expansion.
+fn expand_into(word: &str, assignment: bool, quoted: bool, nested: bool,
+               fields: &mut Splitter, runtime: &mut Runtime) -> result::Result<(), String>
+{
+    let mut chars = word.chars().peekable();
+    let mut double = quoted;
+    // Is the next character at the start of a tilde prefix.
+    let mut prefix = !quoted;
+
+    while let Some(c) = chars.next() {
+        if mem::take(&mut prefix) && c == '~' {
+            let end = match chars.peek() {
+                None | Some('/') => true,
+                Some(':') => assignment,
+                _ => false,
+            };
+            if end {
+                match runtime.variables.get("HOME") {
+                    Some(home) => fields.push_str(home),
+                    None => match home_dir() {
+                        Some(path) => fields.push_str(&path.to_string_lossy()),
+                        None => fields.push('~'),
+                    },
+                }
+                continue;
+            }
+        }
+
+        match c {
+            '\'' if !double => {
+                fields.start();
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    fields.push(c);
+                }
+            },
+            // Only the opening quote starts a field, so a `"$@"` can end
+            // up as no field at all.
+            '"' if quoted => {},
+            '"' => {
+                if !double {
+                    fields.start();
+                }
+                double = !double;
+            },
+            '\\' => {
+                match chars.next() {
+                    Some('\n') => {},
+                    Some(c) if !double || "$`\"\\".contains(c) => fields.push(c),
+                    Some(c) => {
+                        fields.push('\\');
+                        fields.push(c);
+                    },
+                    None => fields.push('\\'),
+                }
+            },
+            '$' => expand_parameter(&mut chars, double, fields, runtime)?,
+            '`' => substitute(&backquoted(&mut chars, double), double, fields, runtime)?,
+            ':' if assignment && !double => {
+                fields.push(c);
+                prefix = true;
+            },
+            c if double => fields.push(c),
+            c if nested => fields.split(c.encode_utf8(&mut [0; 4])),
+            c => fields.push_unquoted(c),
+        }
+    }
+    Ok(())
+}
+
+// Expand the parameter, command substitution or arithmetic expression
+// following a `$`, if there is one, splitting its value unless it's quoted.
+//
+// "$" => "$"
+// "$ " => "$ "
+// "$USER" => "nixpulvis"
+// "${USER}" => "nixpulvis"
+// "$1" => first positional parameter
+// "$#" => number of positional parameters
+// "$$" => process ID of the shell
+// "$!" => process ID of the last background job
+// "$?" => exit status of the last command
+// "$_" => last argument of the previous command (modern)
+// "${X:-word}" => $X, or the word if it's unset or empty
+// "${X:=word}" => $X, or the word assigned to X if it's unset or empty
+// "${X:?word}" => $X, or an error with the word if it's unset or empty
+// "${X:+word}" => the word if $X is set and not empty, otherwise nothing
+// "${#X}" => length of $X
+// "${X%pattern}" => $X without the shortest suffix matching the pattern
+// "${X%%pattern}" => $X without the longest suffix matching the pattern
+// "${X#pattern}" => $X without the shortest prefix matching the pattern
+// "${X##pattern}" => $X without the longest prefix matching the pattern
+// "$(command)" => output of the command
+// "$((expression))" => value of the arithmetic expression
+fn expand_parameter(chars: &mut Peekable<Chars>, quoted: bool, fields: &mut Splitter,
+                    runtime: &mut Runtime) -> result::Result<(), String>
+{
+    if chars.next_if_eq(&'(').is_some() {
+        // A `$((` which doesn't end with `))` is a command substitution of a
+        // subshell, like `$((cd /; ls) | wc -l)`.
+        let mut rest = chars.clone();
+        if rest.next_if_eq(&'(').is_some() {
+            if let Some(expression) = expression_text(&mut rest) {
+                *chars = rest;
+                return evaluate(&expression, quoted, fields, runtime);
+            }
+        }
+        return substitute(&command_text(chars), quoted, fields, runtime);
+    }
+
+    let (name, modifier) = match chars.peek() {
+        Some('{') => {
+            chars.next();
+            // The word of a modifier may have braces of its own.
+            let mut body = String::new();
+            let mut depth = 0;
+            for c in chars.by_ref() {
+                match c {
+                    '{' => depth += 1,
+                    '}' if depth == 0 => break,
+                    '}' => depth -= 1,
+                    _ => {},
+                }
+                body.push(c);
+            }
+            if let Some(length) = length(&body, runtime) {
+                let length = length?;
+                if quoted {
+                    fields.push_str(&length);
+                } else {
+                    fields.split(&length);
+                }
+                return Ok(());
+            }
+            let (name, modifier) = split_parameter(&body);
+            (name.to_string(), modifier.to_string())
+        },
+        Some(&c) if c == '_' || c.is_ascii_alphabetic() => {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek() {
+                if c == '_' || c.is_ascii_alphanumeric() {
+                    name.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            (name, String::new())
+        },
+        Some(&c) if c.is_ascii_digit() || "#@*$!?".contains(c) => {
+            chars.next();
+            (c.to_string(), String::new())
+        },
+        _ => {
+            fields.push('$');
+            return Ok(());
+        },
+    };
+
+    let mut value = parameter(&name, runtime);
+    // Only plain parameters are checked, a `${...}` with a modifier decides
+    // for itself what to do when it's unset.
+    if value.is_none() && modifier.is_empty() && must_be_set(&name, runtime) {
+        return Err(format!("{}: parameter not set", name));
+    }
+
+    // With a `:` the modifiers treat an empty parameter like an unset one.
+    let (colon, operator) = match modifier.strip_prefix(':') {
+        Some(operator) => (true, operator),
+        None => (false, &modifier[..]),
+    };
+    let unset = value.is_none() ||
+        (matches!(&name[..], "@" | "*") && runtime.positionals.is_empty()) ||
+        (colon && value.as_deref() == Some(""));
+    // Is the value replaced by the modifier's word.
+    let mut substituted = false;
+    match operator.chars().next() {
+        None if !colon => {},
+        // `${name-word}` uses the word instead, which is quoted as it's
+        // written, so `${x-"a b"}` is one field.
+        Some('-') if unset => {
+            return expand_into(&operator[1..], false, quoted, true, fields, runtime);
+        },
+        Some('-') => {},
+        // `${name=word}` assigns it as well, and then the value is split
+        // like any other.
+        Some('=') if unset => {
+            if !lex::is_name(&name) {
+                return Err(format!("${{{}{}}}: cannot assign in this way", name, modifier));
+            }
+            if runtime.variables.is_readonly(&name) {
+                return Err(format!("{}: readonly variable", name));
+            }
+            let word = expand_string(&operator[1..], runtime)?;
+            runtime.variables.set(&name, word.clone());
+            value = Some(word);
+        },
+        Some('=') => {},
+        // `${name?word}` is an error, with the word as its message.
+        Some('?') if unset => {
+            let message = match expand_string(&operator[1..], runtime)? {
+                message if !message.is_empty() => message,
+                _ if colon => "parameter null or not set".into(),
+                _ => "parameter not set".into(),
+            };
+            return Err(format!("{}: {}", name, message));
+        },
+        Some('?') => {},
+        // `${name+word}` uses the word only when it's set.
+        Some('+') if unset => value = None,
+        Some('+') => {
+            return expand_into(&operator[1..], false, quoted, true, fields, runtime);
+        },
+        // `${name%pattern}` and `${name#pattern}` remove the shortest
+        // matching suffix or prefix, and doubled the longest.
+        Some(c @ ('%' | '#')) if !colon => {
+            let longest = operator[1..].starts_with(c);
+            let pattern = expand_pattern(&operator[if longest { 2 } else { 1 }..], runtime)?;
+            value = value.map(|value| remove(&value, &pattern, c == '%', longest));
+            substituted = true;
+        },
+        // Anything else is a bad substitution, which expands to nothing.
+        _ => value = None,
+    }
+
+    if quoted && name == "@" && !substituted {
+        // Each positional parameter is its own field, even in quotes, which
+        // join the first and last with the rest of the word.
+        if runtime.positionals.is_empty() {
+            fields.discard();
+        }
+        for (i, value) in runtime.positionals.iter().enumerate() {
+            if i > 0 {
+                fields.end();
+                fields.start();
+            }
+            fields.push_str(value);
+        }
+    } else if quoted {
+        if let Some(value) = value {
+            fields.push_str(&value);
+        }
+    } else if (name == "@" || name == "*") && !substituted {
+        // Each positional parameter is split on its own.
+        for (i, value) in runtime.positionals.iter().enumerate() {
+            if i > 0 {
+                fields.end();
+            }
+            fields.split(value);
+        }
+    } else if let Some(value) = value {
+        fields.split(&value);
+    }
+    Ok(())
+}
+
+// Split the inside of a `${...}` into the name of its parameter and the
+// modifier which follows it, like `x` and `:=5` for `${x:=5}`.
+fn split_parameter(body: &str) -> (&str, &str) {
+    let end = match body.chars().next() {
+        Some(c) if c == '_' || c.is_ascii_alphabetic() => {
+            body.find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+        },
+        Some(c) if c.is_ascii_digit() => body.find(|c: char| !c.is_ascii_digit()),
+        Some(c) if "#@*$!?".contains(c) => Some(1),
+        _ => None,
+    };
+    body.split_at(end.unwrap_or(body.len()))
+}
+
+// The length of the parameter's value for a `${#name}`, in characters, or
+// the number of positional parameters for `${#@}` and `${#*}`. An unset
+// parameter's length is zero.
+fn length(body: &str, runtime: &Runtime) -> Option<result::Result<String, String>> {
+    let name = body.strip_prefix('#')?;
+    match split_parameter(name) {
+        ("@" | "*", "") => Some(Ok(runtime.positionals.len().to_string())),
+        (name, "") if !name.is_empty() => {
+            let value = match parameter(name, runtime) {
+                Some(value) => value,
+                None if must_be_set(name, runtime) => {
+                    return Some(Err(format!("{}: parameter not set", name)));
+                },
+                None => String::new(),
+            };
+            Some(Ok(value.chars().count().to_string()))
+        },
+        _ => None,
+    }
+}
+
+// Whether expanding the parameter while it's unset is an error, which it is
+// with `nounset` for any but `$@` and `$*`.
+fn must_be_set(name: &str, runtime: &Runtime) -> bool {
+    runtime.options.nounset &&
+        (lex::is_name(name) || name.chars().all(|c| c.is_ascii_digit()) || name == "!")
+}
+
+// Remove the shortest, or longest, prefix or suffix of the value which the
+// pattern matches, if any does.
+fn remove(value: &str, pattern: &Pattern, suffix: bool, longest: bool) -> String {
+    let mut bounds = value.char_indices().map(|(i, _)| i).collect::<Vec<_>>();
+    bounds.push(value.len());
+    // From the start, the prefixes are shortest first, and the suffixes
+    // longest first.
+    if suffix != longest {
+        bounds.reverse();
+    }
+    for i in bounds {
+        let (start, end) = value.split_at(i);
+        if suffix && pattern.matches(end) {
+            return start.into();
+        } else if !suffix && pattern.matches(start) {
+            return end.into();
+        }
+    }
+    value.into()
+}
+
+// Replace a command substitution with the output of its command, splitting
+// it unless it's quoted.
+fn substitute(text: &str, quoted: bool, fields: &mut Splitter, runtime: &mut Runtime)
+    -> result::Result<(), String>
+{
+    // The commands are all parsed first, so a syntax error fails the
+    // command being expanded, like any other error in its words.
+    let program = parse_primary(text.as_bytes()).map_err(|e| match e {
+        Error::Parse(e) => format!("command substitution: {}", e),
+        _ => "command substitution: can't be read".into(),
+    })?;
+    let output = capture(&program, runtime).map_err(|e| {
+        format!("command substitution: {}", e.desc())
+    })?;
+    if quoted {
+        fields.push_str(&output);
+    } else {
+        fields.split(&output);
+    }
+    Ok(())
+}
+
+// Replace an arithmetic expansion with the value of its expression, after
+// expanding the parameters and command substitutions within it.
+fn evaluate(expression: &str, quoted: bool, fields: &mut Splitter, runtime: &mut Runtime)
+    -> result::Result<(), String>
+{
+    let expression = expand_string(expression, runtime)?;
+    let value = arithmetic::evaluate(&expression, &mut runtime.variables).map_err(|e| {
+        format!("$(({})): {}", expression, e)
+    })?.to_string();
+    if quoted {
+        fields.push_str(&value);
+    } else {
+        fields.split(&value);
+    }
+    Ok(())
+}
+
+// Read the expression of a `$((..))` up to its closing `))`, if it has one
+// after any parentheses of its own.
+fn expression_text(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut text = String::new();
+    let mut depth = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            ')' => return chars.next_if_eq(&')').map(|_| text),
+            _ => {},
+        }
+        text.push(c);
+    }
+    None
+}
+
+// Read the command of a `$(..)` up to its closing `)`, which may have
+// parentheses and quotes of its own, like `$(echo ")" $(date))`, or the
+// patterns of a `case`.
+fn command_text(chars: &mut Peekable<Chars>) -> String {
+    let rest: String = chars.clone().collect();
+    match lex::substitution_len(&rest) {
+        Some(len) => {
+            chars.nth(rest[..len].chars().count() - 1);
+            rest[..len - 1].into()
+        },
+        None => chars.collect(),
+    }
+}
+
+// Read the command of a `` `..` `` up to its closing `` ` ``, where a `\`
+// only escapes a `$`, `` ` `` or `\`, or a `"` within double quotes.
+fn backquoted(chars: &mut Peekable<Chars>, double: bool) -> String {
+    let mut text = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '`' => break,
+            '\\' => match chars.peek() {
+                Some(&c) if "$`\\".contains(c) || (double && c == '"') => {
+                    chars.next();
+                    text.push(c);
+                },
+                _ => text.push('\\'),
+            },
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+// Lookup the value of the named parameter.
+fn parameter(name: &str, runtime: &Runtime) -> Option<String> {
+    match name {
+        #[cfg(feature = "modern")]
+        "_" => Some(runtime.last_argument.clone()),
+        "#" => Some(runtime.positionals.len().to_string()),
+        "@" => Some(runtime.positionals.join(" ")),
+        // Joined by the first character of `$IFS`.
+        "*" => {
+            let separator = field::ifs(runtime).chars().next().map(String::from);
+            Some(runtime.positionals.join(&separator.unwrap_or_default()))
+        },
+        "$" => Some(runtime.pid.to_string()),
+        "!" => runtime.last_background.map(|pid| pid.to_string()),
+        "?" => Some(runtime.last_status.to_string()),
+        n if n.chars().all(|c| c.is_ascii_digit()) => {
+            match n.parse::<usize>() {
+                Ok(0) => Some(runtime.name.clone()),
+                Ok(i) => runtime.positionals.get(i - 1).cloned(),
+                _ => None,
+            }
+        },
+        name => runtime.variables.get(name).map(String::from),
+    }
+}
+
+// The text as a pattern which only matches itself.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if "*?[\\".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// The pathnames which match the pattern, in order.
+//
+// Each component of the pattern between the `/`s is matched against the
+// entries of the directories matched so far, and a pathname starting with
+// `.` is only matched by a component which starts with one.
+fn pathnames(pattern: &str) -> Vec<String> {
+    let mut paths = vec![String::new()];
+    for (i, component) in pattern.split('/').enumerate() {
+        let component = Pattern::new(component);
+        let mut matched = vec![];
+        for path in paths {
+            let prefix = if i == 0 { path } else { format!("{}/", path) };
+            if let Some(name) = component.literal() {
+                matched.push(prefix + &name);
+                continue;
+            }
+            let dir = if prefix.is_empty() { "." } else { &prefix };
+            let entries = match fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            let mut names = entries.filter_map(|entry| {
+                entry.ok()?.file_name().into_string().ok()
+            }).filter(|name| {
+                (!name.starts_with('.') || component.starts_with_dot()) &&
+                component.matches(name)
+            }).collect::<Vec<_>>();
+            names.sort();
+            matched.extend(names.into_iter().map(|name| format!("{}{}", prefix, name)));
+        }
+        paths = matched;
+    }
+    // The literal components might not exist.
+    paths.retain(|path| fs::symlink_metadata(path).is_ok());
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+    use super::*;
+
+    fn runtime(variables: &[(&str, &str)]) -> Runtime {
+        Runtime::builder()
+            .env_clear()
+            .envs(variables.iter().copied())
+            .positionals(vec!["one".into(), "two  three".into()])
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn words() {
+        let mut runtime = runtime(&[
+            ("HOME", "/home/user"),
+            ("X", " a  b "),
+            ("EMPTY", ""),
+            ("COLONS", "a::b"),
+        ]);
+        let cases: &[(&str, &[&str])] = &[
+            ("plain", &["plain"]),
+            ("'a  b'", &["a  b"]),
+            ("\"a  b\"", &["a  b"]),
+            ("a\\ b", &["a b"]),
+            ("''", &[""]),
+            // Tilde expansion.
+            ("~", &["/home/user"]),
+            ("~/bin", &["/home/user/bin"]),
+            ("'~'", &["~"]),
+            ("a~", &["a~"]),
+            ("~:~", &["~:~"]),
+            // Parameter expansion, and field splitting.
+            ("$X", &["a", "b"]),
+            ("${X}c", &["a", "b", "c"]),
+            ("\"$X\"", &[" a  b "]),
+            ("'$X'", &["$X"]),
+            ("\\$X", &["$X"]),
+            ("\"\\$X\\a\"", &["$X\\a"]),
+            ("$", &["$"]),
+            ("$EMPTY", &[]),
+            ("$UNSET", &[]),
+            ("\"$EMPTY\"", &[""]),
+            ("$EMPTY''", &[""]),
+            ("$COLONS", &["a::b"]),
+            // Positional parameters.
+            ("$1", &["one"]),
+            ("$#", &["2"]),
+            ("$@", &["one", "two", "three"]),
+            ("$*", &["one", "two", "three"]),
+            ("\"$@\"", &["one", "two  three"]),
+            ("x\"$@\"y", &["xone", "two  threey"]),
+            ("\"$*\"", &["one two  three"]),
+            // Patterns which don't match are kept.
+            ("no-such-*-file", &["no-such-*-file"]),
+            ("'*'", &["*"]),
+        ];
+        for (word, fields) in cases.iter() {
+            assert_eq!(*fields, expand_word(word, &mut runtime).unwrap(), "expanding {:?}", word);
+        }
+    }
+
+    #[test]
+    fn ifs() {
+        let mut runtime = runtime(&[("IFS", ":"), ("COLONS", "a::b "), ("X", "x")]);
+        assert_eq!(vec!["a", "", "b "], expand_word("$COLONS", &mut runtime).unwrap());
+        assert_eq!(vec!["one:two  three"], expand_word("\"$*\"", &mut runtime).unwrap());
+        assert_eq!(vec!["a", "", "b ", "x"],
+                   expand_words(vec!["$COLONS", "$X"], &mut runtime).unwrap());
+    }
+
+    #[test]
+    fn nounset() {
+        let mut runtime = runtime(&[("EMPTY", "")]);
+        runtime.options.nounset = true;
+        assert_eq!(Err("UNSET: parameter not set".into()), expand_word("$UNSET", &mut runtime));
+        assert_eq!(Err("3: parameter not set".into()), expand_word("\"$3\"", &mut runtime));
+        assert_eq!(Err("UNSET: parameter not set".into()), expand_word("${#UNSET}", &mut runtime));
+        assert_eq!(Ok(vec![]), expand_word("$EMPTY", &mut runtime));
+    }
+
+    #[test]
+    fn assign_default() {
+        let mut runtime = runtime(&[("EMPTY", ""), ("X", "x")]);
+        assert_eq!(vec!["a", "b"], expand_word("${NEW:=a b}", &mut runtime).unwrap());
+        assert_eq!(Some("a b"), runtime.variables.get("NEW"));
+        assert_eq!(vec!["x"], expand_word("${X:=y}", &mut runtime).unwrap());
+        assert_eq!(vec![""], expand_word("\"${EMPTY=y}\"", &mut runtime).unwrap());
+        assert_eq!(vec!["x/"], expand_word("${EMPTY:=$X/}", &mut runtime).unwrap());
+        assert_eq!(Some("x/"), runtime.variables.get("EMPTY"));
+        assert_eq!(Err("${3:=y}: cannot assign in this way".into()),
+                   expand_word("${3:=y}", &mut runtime));
+    }
+
+    #[test]
+    fn default_and_alternate() {
+        let mut runtime = runtime(&[("EMPTY", ""), ("X", "x")]);
+        let cases: &[(&str, &[&str])] = &[
+            ("${X:-a b}", &["x"]),
+            ("${UNSET:-a b}", &["a", "b"]),
+            ("\"${UNSET:-a b}\"", &["a b"]),
+            ("${EMPTY:-$X/}", &["x/"]),
+            ("${EMPTY-y}", &[]),
+            ("\"${EMPTY-y}\"", &[""]),
+            ("${UNSET-y}", &["y"]),
+            ("${X:+a b}", &["a", "b"]),
+            ("${EMPTY:+y}", &[]),
+            ("${EMPTY+y}", &["y"]),
+            ("${UNSET+y}", &[]),
+            ("${@:+set}", &["set"]),
+            ("\"${@-none}\"", &["one", "two  three"]),
+            // The word keeps its own quotes.
+            ("${UNSET:-\"a b\"}", &["a b"]),
+            ("${UNSET:-x\"a b\"y c}", &["xa by", "c"]),
+            ("${UNSET:-'*'}", &["*"]),
+            ("${X:+'a  b'}", &["a  b"]),
+            ("${UNSET-$X\"$X\"}", &["xx"]),
+            ("\"${UNSET:-\"a  b\"}\"", &["a  b"]),
+            ("\"${UNSET:-'a'}\"", &["'a'"]),
+        ];
+        for (word, fields) in cases.iter() {
+            assert_eq!(*fields, expand_word(word, &mut runtime).unwrap(), "{:?}", word);
+        }
+        assert_eq!(None, runtime.variables.get("UNSET"));
+
+        assert_eq!(vec!["x"], expand_word("${X:?}", &mut runtime).unwrap());
+        assert_eq!(vec![""], expand_word("\"${EMPTY?}\"", &mut runtime).unwrap());
+        assert_eq!(Err("EMPTY: parameter null or not set".into()),
+                   expand_word("${EMPTY:?}", &mut runtime));
+        assert_eq!(Err("UNSET: parameter not set".into()),
+                   expand_word("${UNSET?}", &mut runtime));
+        assert_eq!(Err("UNSET: is $X".into()), expand_word("${UNSET:?is \\$X}", &mut runtime));
+    }
+
+    #[test]
+    fn length_and_removal() {
+        let mut runtime = runtime(&[("F", "src/main.rs.bak"), ("EXT", ".bak"), ("U", "héllo"),
+                                    ("S", "a*b*c"), ("P", "*")]);
+        let cases: &[(&str, &[&str])] = &[
+            ("${#F}", &["15"]),
+            ("${#U}", &["5"]),
+            ("${#UNSET}", &["0"]),
+            ("${#@}", &["2"]),
+            ("${#}", &["2"]),
+            ("${F%.*}", &["src/main.rs"]),
+            ("${F%%.*}", &["src/main"]),
+            ("${F#*/}", &["main.rs.bak"]),
+            ("${F##*.}", &["bak"]),
+            ("${F%$EXT}", &["src/main.rs"]),
+            ("${F%\"$EXT\"}", &["src/main.rs"]),
+            ("${F%x}", &["src/main.rs.bak"]),
+            ("${F#}", &["src/main.rs.bak"]),
+            ("${F##*}", &[]),
+            ("\"${F##*}\"", &[""]),
+            ("${F%[.]b*}", &["src/main.rs"]),
+            ("${U#h?}", &["llo"]),
+            ("${UNSET%x}", &[]),
+        ];
+        for (word, fields) in cases.iter() {
+            assert_eq!(*fields, expand_word(word, &mut runtime).unwrap(), "{:?}", word);
+        }
+        // Quoted pattern characters match themselves.
+        assert_eq!(vec!["a*b"], expand_word("${S%'*'*}", &mut runtime).unwrap());
+        assert_eq!(vec!["a*b"], expand_word("${S%\"$P\"*}", &mut runtime).unwrap());
+        assert_eq!(vec![""], expand_word("\"${S%%$P}\"", &mut runtime).unwrap());
+    }
+
+    #[test]
+    fn arithmetic() {
+        let mut runtime = runtime(&[("I", "4"), ("OP", "*"), ("IFS", "-")]);
+        let cases: &[(&str, &[&str])] = &[
+            ("$((1 + 2 * 3))", &["7"]),
+            ("$(( (I + 1) % 3 ))", &["2"]),
+            ("$(($I $OP 2))", &["8"]),
+            ("x$((I))y", &["x4y"]),
+            ("\"$((-I))\"", &["-4"]),
+            ("$((-I))", &["", "4"]),
+            ("$((I += 2))", &["6"]),
+            ("$(())", &["0"]),
+        ];
+        for (word, fields) in cases.iter() {
+            assert_eq!(*fields, expand_word(word, &mut runtime).unwrap(), "{:?}", word);
+        }
+        assert_eq!("6", runtime.variables.get("I").unwrap());
+        assert!(expand_word("$((1 / 0))", &mut runtime).is_err());
+        assert!(expand_word("$((I = ))", &mut runtime).is_err());
+    }
+
+    #[test]
+    fn pathnames() {
+        let dir = env::temp_dir().join(format!("oursh-expansion-{}", process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        for file in &["a.rs", "b.rs", "c.txt", ".hidden.rs", "sub/d.rs"] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        let d = dir.to_string_lossy().into_owned();
+        let pattern = format!("{}/*.txt", d);
+        let mut runtime = runtime(&[("D", &d), ("P", &pattern)]);
+
+        let cases: &[(&str, &[&str])] = &[
+            ("\"$D\"/*.rs", &["a.rs", "b.rs"]),
+            ("\"$D\"/?.txt", &["c.txt"]),
+            ("\"$D\"/[ab].rs", &["a.rs", "b.rs"]),
+            ("\"$D\"/[!ab]*", &["c.txt", "sub"]),
+            ("\"$D\"/.*.rs", &[".hidden.rs"]),
+            ("\"$D\"/*/*.rs", &["sub/d.rs"]),
+            ("\"$D\"/*/", &["sub/"]),
+            ("$P", &["c.txt"]),
+        ];
+        for (word, files) in cases.iter() {
+            let paths = files.iter().map(|f| format!("{}/{}", d, f)).collect::<Vec<_>>();
+            assert_eq!(paths, expand_word(word, &mut runtime).unwrap(), "expanding {:?}", word);
+        }
+        // Quoted, or without a match, the pattern is kept.
+        for word in &["\"$D/*.rs\"", "\"$D\"/'*'.rs", "\"$D\"/\\*.rs", "\"$D\"/x*"] {
+            assert_eq!(vec![expand_string(word, &mut runtime).unwrap()],
+                       expand_word(word, &mut runtime).unwrap(), "expanding {:?}", word);
+        }
+        assert_eq!(format!("{}/*.txt", d), expand_string("$P", &mut runtime).unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
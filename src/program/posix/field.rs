@@ -0,0 +1,275 @@
+//! Field splitting, the last of the word expansions before quote removal.
+//!
+//! The results of unquoted expansions are split into fields at the
+//! characters of `$IFS`, which is a space, tab and newline when it's unset.
+//! Runs of `$IFS` whitespace are a single delimiter, and are ignored at the
+//! start and end of the text, while every other `$IFS` character is a
+//! delimiter of its own, so empty fields are kept between them.
+//!
+//! ```sh
+//! x=' a  b '; printf '[%s]' $x        # [a][b]
+//! IFS=:; x='a::b'; printf '[%s]' $x   # [a][][b]
+//! IFS=; x='a b'; printf '[%s]' $x     # [a b]
+//! ```
+use crate::program::Runtime;
+
+/// The characters of `$IFS` when it's unset.
+const DEFAULT_IFS: &str = " \t\n";
+
+/// The current value of `$IFS`.
+pub(crate) fn ifs(runtime: &Runtime) -> &str {
+    runtime.variables.get("IFS").unwrap_or(DEFAULT_IFS)
+}
+
+/// A field of a word, and the pattern for its pathname expansion.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Field {
+    pub text: String,
+    /// The text as a pattern, with its quoted characters escaped, when it
+    /// has any unquoted `*`, `?` or `[`.
+    pub pattern: Option<String>,
+}
+
+/// Builds the fields of a word, from text which is split and text which is
+/// kept as it is.
+#[derive(Debug)]
+pub(crate) struct Splitter<'a> {
+    ifs: &'a str,
+    fields: Vec<Field>,
+    current: String,
+    // The current field as a pattern, and whether it has any unquoted
+    // pattern characters.
+    pattern: String,
+    glob: bool,
+    // Does the current field exist, even when it's empty, like after `""`.
+    started: bool,
+    // The delimiter being read, if the last character was part of one.
+    delimiter: Option<Delimiter>,
+    // The number of fields, after which the rest of the text is kept whole.
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Delimiter {
+    // Only whitespace so far, and whether it ended a field.
+    Whitespace(bool),
+    // Ending with a character other than whitespace.
+    Other,
+}
+
+impl<'a> Splitter<'a> {
+    /// Split at the characters of `ifs`, where an empty `ifs` never splits.
+    pub fn new(ifs: &'a str) -> Self {
+        Splitter {
+            ifs,
+            fields: vec![],
+            current: String::new(),
+            pattern: String::new(),
+            glob: false,
+            started: false,
+            delimiter: None,
+            limit: None,
+        }
+    }
+
+    /// Make at most `limit` fields, the last of which keeps the rest of the
+    /// text, without its leading and trailing whitespace, like `read` does.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit.max(1));
+        self
+    }
+
+    /// Add a character which isn't split, or matched as a pattern.
+    pub fn push(&mut self, c: char) {
+        if "*?[\\".contains(c) {
+            self.pattern.push('\\');
+        }
+        self.pattern.push(c);
+        self.current.push(c);
+        self.started = true;
+        self.delimiter = None;
+    }
+
+    /// Add an unquoted character of the word, which isn't split, but may be
+    /// part of a pattern.
+    pub fn push_unquoted(&mut self, c: char) {
+        if c == '\\' {
+            return self.push(c);
+        }
+        self.glob |= "*?[".contains(c);
+        self.pattern.push(c);
+        self.current.push(c);
+        self.started = true;
+        self.delimiter = None;
+    }
+
+    /// Add text which isn't split.
+    pub fn push_str(&mut self, text: &str) {
+        text.chars().for_each(|c| self.push(c));
+    }
+
+    /// Make sure there's a current field, even if nothing is added to it,
+    /// like for `""`.
+    pub fn start(&mut self) {
+        self.started = true;
+    }
+
+    /// End the current field, if there is one, whatever `$IFS` is, like
+    /// between the positional parameters of `$@`.
+    pub fn end(&mut self) {
+        if self.started {
+            self.field();
+        }
+        self.delimiter = None;
+    }
+
+    // Add the current field, even if it's empty.
+    fn field(&mut self) {
+        let pattern = std::mem::take(&mut self.pattern);
+        self.fields.push(Field {
+            text: std::mem::take(&mut self.current),
+            pattern: if std::mem::take(&mut self.glob) { Some(pattern) } else { None },
+        });
+        self.started = false;
+    }
+
+    /// Forget the current field if nothing has been added to it, like for a
+    /// `"$@"` without any positional parameters, which is no field at all.
+    pub fn discard(&mut self) {
+        if self.current.is_empty() {
+            self.started = false;
+        }
+    }
+
+    /// Add text which is split at the `$IFS` characters, and may be part of
+    /// a pattern.
+    pub fn split(&mut self, text: &str) {
+        for c in text.chars() {
+            if !self.ifs.contains(c) {
+                self.push_unquoted(c);
+                continue;
+            }
+
+            let whitespace = c == ' ' || c == '\t' || c == '\n';
+            let last = self.limit.map(|l| self.fields.len() + 1 >= l).unwrap_or(false);
+            if last {
+                // Only leading whitespace is dropped from the last field.
+                if !(whitespace && !self.started) {
+                    self.push_unquoted(c);
+                }
+                continue;
+            }
+
+            match (self.delimiter, whitespace) {
+                (None, true) => {
+                    let ended = self.started;
+                    self.end();
+                    self.delimiter = Some(Delimiter::Whitespace(ended));
+                },
+                (Some(_), true) => {},
+                // Whitespace around a single other character is part of
+                // the same delimiter.
+                (Some(Delimiter::Whitespace(true)), false) => {
+                    self.delimiter = Some(Delimiter::Other);
+                },
+                (_, false) => {
+                    self.field();
+                    self.delimiter = Some(Delimiter::Other);
+                },
+            }
+        }
+    }
+
+    /// The text of the fields, in order.
+    pub fn finish(self) -> Vec<String> {
+        self.finish_fields().into_iter().map(|field| field.text).collect()
+    }
+
+    /// The fields, in order.
+    pub fn finish_fields(mut self) -> Vec<Field> {
+        if self.limit.is_some() {
+            let ifs = self.ifs;
+            let trailing = |c: char| (c == ' ' || c == '\t' || c == '\n') && ifs.contains(c);
+            let trimmed = self.current.trim_end_matches(trailing).len();
+            self.current.truncate(trimmed);
+            let trimmed = self.pattern.trim_end_matches(trailing).len();
+            self.pattern.truncate(trimmed);
+        }
+        self.end();
+        self.fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(ifs: &str, text: &str) -> Vec<String> {
+        let mut splitter = Splitter::new(ifs);
+        splitter.split(text);
+        splitter.finish()
+    }
+
+    #[test]
+    fn whitespace() {
+        assert_eq!(vec!["a", "b"], split(DEFAULT_IFS, "  a \t\n b  "));
+        assert_eq!(Vec::<String>::new(), split(DEFAULT_IFS, "   "));
+        assert_eq!(Vec::<String>::new(), split(DEFAULT_IFS, ""));
+    }
+
+    #[test]
+    fn other() {
+        assert_eq!(vec!["a", "", "b"], split(":", "a::b"));
+        assert_eq!(vec!["", "a"], split(":", ":a"));
+        assert_eq!(vec!["a"], split(":", "a:"));
+        assert_eq!(vec!["a", " b "], split(":", "a: b "));
+        assert_eq!(vec!["a", "b"], split(" :", "a : b"));
+        assert_eq!(vec!["a", "", "b"], split(" :", "a : : b"));
+        assert_eq!(vec!["", "a"], split(" :", " : a"));
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(vec![" a b "], split("", " a b "));
+    }
+
+    #[test]
+    fn unsplit() {
+        let mut splitter = Splitter::new(DEFAULT_IFS);
+        splitter.push_str("x ");
+        splitter.split("a b");
+        splitter.start();
+        splitter.end();
+        splitter.split("");
+        splitter.start();
+        assert_eq!(vec!["x a", "b", ""], splitter.finish());
+    }
+
+    #[test]
+    fn patterns() {
+        let mut splitter = Splitter::new(DEFAULT_IFS);
+        splitter.push_unquoted('*');
+        splitter.push('?');
+        splitter.split(" [a] b");
+        splitter.end();
+        splitter.push_str("*");
+        assert_eq!(vec![
+            Field { text: "*?".into(), pattern: Some("*\\?".into()) },
+            Field { text: "[a]".into(), pattern: Some("[a]".into()) },
+            Field { text: "b".into(), pattern: None },
+            Field { text: "*".into(), pattern: None },
+        ], splitter.finish_fields());
+    }
+
+    #[test]
+    fn limit() {
+        let split = |ifs, limit, text| {
+            let mut splitter = Splitter::new(ifs).limit(limit);
+            splitter.split(text);
+            splitter.finish()
+        };
+        assert_eq!(vec!["a", "b  c"], split(DEFAULT_IFS, 2, "  a  b  c  "));
+        assert_eq!(vec!["x", "y:z"], split(":", 2, "x:y:z"));
+        assert_eq!(vec!["a b"], split(DEFAULT_IFS, 1, " a b "));
+    }
+}
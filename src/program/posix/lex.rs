@@ -18,6 +18,9 @@ pub type Span<T, E> = Result<(usize, T, usize), E>;
 #[derive(Debug)]
 pub enum Error {
     UnrecognizedChar(usize, char, usize),
+    /// The input ended within the quotes, or `$(..)` or `${..}` expression,
+    /// of the word starting at the first location.
+    Unterminated(usize, usize),
 }
 
 /// Every token in the langauge, these are the terminals of the grammar.
@@ -27,19 +30,14 @@ pub enum Token<'input> {
     Tab,
     Linefeed,
     Semi,
+    DSemi,
     Amper,
     RBrace,
     LBrace,
     RParen,
     LParen,
-    Backtick,
     Bang,
     Pipe,
-    Dollar,
-    Equals,
-    Backslash,
-    DoubleQuote,
-    SingleQuote,
     Great,
     DGreat,
     GreatAnd,
@@ -47,6 +45,7 @@ pub enum Token<'input> {
     Less,
     DLess,
     DLessDash,
+    TLess,
     LessAnd,
     LessGreat,
     And,
@@ -56,7 +55,6 @@ pub enum Token<'input> {
     Else,
     Elif,
     Fi,
-    Export,
     Do,
     Done,
     Case,
@@ -65,10 +63,39 @@ pub enum Token<'input> {
     Until,
     For,
     Word(&'input str),
+    Assignment(&'input str),
     IoNumber(usize),
+    Arithmetic(&'input str),
     HashLang(&'input str),
     Shebang(&'input str),
     Text(&'input str),
+    HereDoc(HereDoc<'input>),
+}
+
+/// A here-document, the delimiting word after a `<<` or `<<-`, along with
+/// the lines after the command up to the delimiter on its own.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct HereDoc<'input> {
+    /// The delimiter as it was written, quotes and all.
+    pub delimiter: &'input str,
+    /// The lines of the document, without the delimiter's line.
+    pub body: &'input str,
+    /// Is any of the delimiter quoted, so the body isn't expanded.
+    pub quoted: bool,
+    /// Are the leading tabs of each line removed, for `<<-`.
+    pub strip: bool,
+}
+
+impl HereDoc<'_> {
+    /// The text of the document, with the leading tabs of each line removed
+    /// for `<<-`.
+    pub fn text(&self) -> String {
+        if self.strip {
+            self.body.split_inclusive('\n').map(|l| l.trim_start_matches('\t')).collect()
+        } else {
+            self.body.into()
+        }
+    }
 }
 
 /// A lexer to feed the parser gernerated by LALRPOP.
@@ -89,6 +116,44 @@ pub struct Lexer<'input> {
     //
     // TODO: Generalize over this abstraction and the one needed for HERE docs.
     in_shebang: bool,
+
+    /// Set after a `<<` or `<<-`, whether it strips tabs, so the next word
+    /// is read as a here-document.
+    here: Option<bool>,
+
+    /// Where the line following the here-documents of the current line
+    /// starts, which is where the next one's body starts, and where the
+    /// lexer continues after the newline ending the current line.
+    here_end: Option<usize>,
+
+    /// Where the next word is, which decides if it's a reserved word.
+    context: Context,
+}
+
+/// Where a word is in a command. Reserved words like `if` or `done` are
+/// only recognized where they can start (or end) a command, anywhere else
+/// they're words like any other, as in `echo if`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Context {
+    /// The first word of a command, where every reserved word is
+    /// recognized.
+    Command,
+    /// Any other word, like an argument or the name of a file.
+    Argument,
+    /// The variable of a `for` loop.
+    ForName,
+    /// After the variable of a `for` loop, where `do` is recognized (and
+    /// `in` is left to the parser).
+    ForIn,
+    /// The word matched by a `case`.
+    CaseWord,
+    /// After the word of a `case`, before its `in`.
+    CaseIn,
+    /// The start of the patterns of a `case` item, where only `esac` is
+    /// recognized.
+    Pattern,
+    /// The rest of the patterns of a `case` item, up to its `)`.
+    Patterns,
 }
 
 impl<'input> Lexer<'input> {
@@ -103,6 +168,9 @@ impl<'input> Lexer<'input> {
             lookahead,
             #[cfg(feature = "shebang-block")]
             in_shebang: false,
+            here: None,
+            here_end: None,
+            context: Context::Command,
         }
     }
 }
@@ -111,6 +179,19 @@ impl<'input> Iterator for Lexer<'input> {
     type Item = Span<Token<'input>, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let span = self.token();
+        if let Some(Ok((s, token, e))) = span {
+            let token = self.reserved(token);
+            self.context = self.after(&token);
+            return Some(Ok((s, token, e)));
+        }
+        span
+    }
+}
+
+impl<'input> Lexer<'input> {
+    // Read the next token, with every word as a word, reserved or not.
+    fn token(&mut self) -> Option<Span<Token<'input>, Error>> {
         #[cfg(feature = "shebang-block")]
         {
             // If we're inside a shebang, parse a full TEXT block.
@@ -126,11 +207,34 @@ impl<'input> Iterator for Lexer<'input> {
             }
         }
 
+        if let Some(strip) = self.here.take() {
+            return self.here_document(strip);
+        }
+
         // Consume characters until we've got a token.
         while let Some((s, c, e)) = self.advance() {
             let tok = match c {
-                '\n' => Some(Ok((s, Token::Linefeed, e))),
-                ';'  => Some(Ok((s, Token::Semi, e))),
+                // The bodies of the line's here-documents are skipped, they
+                // have been read already.
+                '\n' => {
+                    if let Some(end) = self.here_end.take() {
+                        while matches!(self.lookahead, Some((s, _, _)) if s < end) {
+                            self.advance();
+                        }
+                    }
+                    Some(Ok((s, Token::Linefeed, e)))
+                },
+                // A `;;` ends the commands of a pattern in a `case`.
+                ';' => {
+                    if let Some((_, ';', e)) = self.lookahead {
+                        self.advance();
+                        Some(Ok((s, Token::DSemi, e)))
+                    } else {
+                        Some(Ok((s, Token::Semi, e)))
+                    }
+                },
+                // A `#` starting a token comments out the rest of the line,
+                // while one within a word or quotes is part of it.
                 '#'  => {
                     while let Some((_, c, _)) = self.lookahead {
                         match c {
@@ -138,16 +242,25 @@ impl<'input> Iterator for Lexer<'input> {
                             _ => self.advance(),
                         };
                     }
-                    self.next()
+                    continue;
                 }
                 ')'  => Some(Ok((s, Token::RParen, e))),
+                // An `((` with a matching `))` is an arithmetic command,
+                // otherwise it's the start of nested subshells.
+                '(' if matches!(self.lookahead, Some((_, '(', _))) &&
+                       arithmetic_len(&self.input[s..]).is_some() => {
+                    Some(Ok(self.arithmetic(s)))
+                },
                 '('  => Some(Ok((s, Token::LParen, e))),
-                '`'  => Some(Ok((s, Token::Backtick, e))),
-                '!'  => Some(Ok((s, Token::Bang, e))),
-                '='  => Some(Ok((s, Token::Equals, e))),
-                '\\' => Some(Ok((s, Token::Backslash, e))),
-                '\'' => Some(self.single_quote(s, e)),
-                '"'  => Some(self.double_quote(s, e)),
+                // A `!` on its own negates, while `!=` or `!x` are words.
+                '!' if self.lookahead.map(|(_, c, _)| !is_word_continue(c))
+                                     .unwrap_or(true) => {
+                    Some(Ok((s, Token::Bang, e)))
+                },
+                // Process substitutions, `<(..)` and `>(..)`, are words.
+                '<' | '>' if matches!(self.lookahead, Some((_, '(', _))) => {
+                    Some(self.word(s, e))
+                },
                 '>'  => {
                     match self.lookahead {
                         Some((_, '>', e)) => {
@@ -175,8 +288,13 @@ impl<'input> Iterator for Lexer<'input> {
                             self.advance();
                             if let Some((_, '-', e)) = self.lookahead {
                                 self.advance();
+                                self.here = Some(true);
                                 Some(Ok((s, Token::DLessDash, e)))
+                            } else if let Some((_, '<', e)) = self.lookahead {
+                                self.advance();
+                                Some(Ok((s, Token::TLess, e)))
                             } else {
+                                self.here = Some(false);
                                 Some(Ok((s, Token::DLess, e)))
                             }
                         },
@@ -203,15 +321,18 @@ impl<'input> Iterator for Lexer<'input> {
                         Some(Ok((s, Token::Pipe, e)))
                     }
                 },
-                '$' => {
-                    match self.lookahead {
-                        Some((_, '{', e)) |
-                        Some((_, '(', e)) => Some(Ok((s, Token::Dollar, e))),
-                        _ => Some(self.word(s, e)),
-                    }
-                },
+                // A brace group like `{a,b}` is a word, while a lone `{`
+                // starts a compound command.
+                '{' if !matches!(self.lookahead, Some((_, '#', _))) &&
+                       brace_len(&self.input[s..]).is_some() => Some(self.word(s, e)),
                 '{' => Some(self.block(s, s+e)),
                 '}' => Some(Ok((s, Token::RBrace, e))),
+                // An escaped newline between words joins the lines, within a
+                // word it's removed during expansion (unless single quoted).
+                '\\' if matches!(self.lookahead, Some((_, '\n', _))) => {
+                    self.advance();
+                    continue;
+                },
                 c if is_word_start(c) => Some(self.word(s, e)),
                 c if c.is_whitespace() => continue,
                 c => return Some(Err(Error::UnrecognizedChar(s, c, e))),
@@ -225,9 +346,59 @@ impl<'input> Iterator for Lexer<'input> {
         debug!("emit<end>: {:?}", tok);
         tok
     }
-}
 
-impl<'input> Lexer<'input> {
+    // The word as a reserved word, if it is one where it is.
+    fn reserved(&self, token: Token<'input>) -> Token<'input> {
+        let word = match token {
+            Token::Word(word) => word,
+            token => return token,
+        };
+        match (self.context, word) {
+            (Context::Command, "if")     => Token::If,
+            (Context::Command, "then")   => Token::Then,
+            (Context::Command, "else")   => Token::Else,
+            (Context::Command, "elif")   => Token::Elif,
+            (Context::Command, "fi")     => Token::Fi,
+            (Context::Command, "do")     => Token::Do,
+            (Context::Command, "done")   => Token::Done,
+            (Context::Command, "case")   => Token::Case,
+            (Context::Command, "esac")   => Token::Esac,
+            (Context::Command, "while")  => Token::While,
+            (Context::Command, "until")  => Token::Until,
+            (Context::Command, "for")    => Token::For,
+            (Context::ForIn, "do")       => Token::Do,
+            (Context::Pattern, "esac")   => Token::Esac,
+            _ => Token::Word(word),
+        }
+    }
+
+    // Where the word after the token is.
+    fn after(&self, token: &Token<'input>) -> Context {
+        match (self.context, token) {
+            (_, Token::For) => Context::ForName,
+            (_, Token::Case) => Context::CaseWord,
+            (Context::ForName, _) => Context::ForIn,
+            (Context::ForIn, Token::Word("in")) => Context::Argument,
+            (Context::ForIn, Token::Linefeed) => Context::ForIn,
+            (Context::CaseWord, _) => Context::CaseIn,
+            (Context::CaseIn, Token::Word("in")) => Context::Pattern,
+            (Context::CaseIn, Token::Linefeed) => Context::CaseIn,
+            (Context::Pattern, Token::Linefeed) => Context::Pattern,
+            // Patterns are separated by `|`, and may start with a `(`.
+            (Context::Pattern | Context::Patterns,
+             Token::Word(_) | Token::Assignment(_) | Token::Pipe | Token::LParen) => {
+                Context::Patterns
+            },
+            (_, Token::DSemi) => Context::Pattern,
+            (_, Token::Linefeed | Token::Semi | Token::Amper | Token::And |
+                Token::Or | Token::Pipe | Token::Bang | Token::LParen |
+                Token::RParen | Token::LBrace | Token::If | Token::Then |
+                Token::Else | Token::Elif | Token::Do | Token::While |
+                Token::Until) => Context::Command,
+            _ => Context::Argument,
+        }
+    }
+
     fn advance(&mut self) -> Option<(usize, char, usize)> {
         match self.lookahead {
             Some((s, c, e)) => {
@@ -241,6 +412,7 @@ impl<'input> Lexer<'input> {
     }
 
     // TODO: start and end arguments aren't quite right here.
+    #[cfg(feature = "shebang-block")]
     fn take_until<F>(&mut self, start: usize, mut end: usize,  mut terminate: F)
         -> (&'input str, usize)
         where F: FnMut(char) -> bool
@@ -255,6 +427,7 @@ impl<'input> Lexer<'input> {
         (&self.input[start..end], end)
     }
 
+    #[cfg(feature = "shebang-block")]
     fn take_while<F>(&mut self, start: usize, end: usize, mut keep_going: F)
         -> (&'input str, usize)
         where F: FnMut(char) -> bool,
@@ -262,47 +435,149 @@ impl<'input> Lexer<'input> {
         self.take_until(start, end, |c| !keep_going(c))
     }
 
-    fn single_quote(&mut self, start: usize, end: usize)
+    /// Read the rest of a word, the first character of which (from `start`
+    /// to `end`) has already been consumed.
+    ///
+    /// Words are returned as they appear in the source, quotes and all. The
+    /// quotes are removed later during expansion, so we only need to know
+    /// where the word ends here; quoted text, escaped characters, `${..}`
+    /// or `$(..)` expressions, and brace groups like `{a,b}` never end a
+    /// word.
+    fn word(&mut self, start: usize, end: usize)
         -> Result<(usize, Token<'input>, usize), Error>
     {
-        // TODO: This quitely stops at EOF.
-        let (_, end) = self.take_while(start, end, |c| c != '\'');
-        self.advance();  // Consume the ending single quote.
-        Ok((start, Token::Word(&self.input[start+1..end]), end))
-    }
+        let mut state = WordState::default();
+        let mut end = end;
+        match self.input[start..end].chars().next().unwrap() {
+            '{' => end = self.brace(start).unwrap_or(end),
+            c => state.push(c, self.lookahead),
+        }
+        while let Some((s, c, _)) = self.lookahead {
+            if state.is_bare() && c == '{' {
+                if let Some(e) = self.brace(s) {
+                    end = e;
+                    continue;
+                }
+            }
+            if state.is_bare() && !is_word_continue(c) {
+                break;
+            }
+            // The commands of a `$(..)` are read as tokens, up to the `)`
+            // closing them, unlike the expression of a `$((..))`.
+            if state.opening && c == '(' && !self.input[s+1..].starts_with('(') {
+                state.opening = false;
+                match self.commands(s + 1)? {
+                    Some(e) => end = e,
+                    None => return Err(Error::Unterminated(start, self.input.len())),
+                }
+                continue;
+            }
+            if let Some((_, c, e)) = self.advance() {
+                state.push(c, self.lookahead);
+                end = e;
+            }
+        }
+        if state.is_open() {
+            return Err(Error::Unterminated(start, end));
+        }
 
-    // TODO: Escapes
-    // TODO: Honestly, I think this needs to be handled in the .lalrpop file.
-    fn double_quote(&mut self, start: usize, end: usize)
-        -> Result<(usize, Token<'input>, usize), Error>
-    {
-        // TODO: This quitely stops at EOF.
-        let (input, end) = self.take_while(start, end, |c| c != '"');
-        self.advance();  // Consume the ending double quote.
-        Ok((start, Token::Word(&input[1..]), end))
+        let word = &self.input[start..end];
+        let tok = if is_assignment(word) {
+            Token::Assignment(word)
+        } else {
+            self.io_number(word)
+        };
+        Ok((start, tok, end))
     }
 
-    fn word(&mut self, start: usize, end: usize)
-        -> Result<(usize, Token<'input>, usize), Error>
-    {
-        let (word, end) = self.take_while(start, end, is_word_continue);
-        let tok = match word {
-            "if"     => Token::If,
-            "then"   => Token::Then,
-            "else"   => Token::Else,
-            "elif"   => Token::Elif,
-            "fi"     => Token::Fi,
-            "export" => Token::Export,
-            "do"     => Token::Do,
-            "done"   => Token::Done,
-            "case"   => Token::Case,
-            "esac"   => Token::Esac,
-            "while"  => Token::While,
-            "until"  => Token::Until,
-            "for"    => Token::For,
-            word     => self.io_number(word),
+    // Read the delimiter of a here-document, and find its body in the lines
+    // following the current one, or after the body of the line's last
+    // here-document. Anything but a word is left for the parser to reject.
+    fn here_document(&mut self, strip: bool) -> Option<Span<Token<'input>, Error>> {
+        while let Some((_, ' ' | '\t', _)) = self.lookahead {
+            self.advance();
+        }
+        match self.lookahead {
+            Some((_, c, _)) if is_word_start(c) => {},
+            _ => return self.token(),
+        }
+        let (s, _, e) = self.advance()?;
+        let (start, end) = match self.word(s, e) {
+            Ok((start, _, end)) => (start, end),
+            Err(e) => return Some(Err(e)),
         };
-        Ok((start, tok, end))
+        let word = &self.input[start..end];
+        let (delimiter, quoted) = unquote(word);
+
+        let input = self.input;
+        let body_start = match self.here_end {
+            Some(here_end) => here_end,
+            None => match input[end..].find('\n') {
+                Some(i) => end + i + 1,
+                None => return Some(Err(Error::Unterminated(start, input.len()))),
+            },
+        };
+        let mut line_start = body_start;
+        loop {
+            let line_end = input[line_start..].find('\n').map(|i| line_start + i);
+            let line = &input[line_start..line_end.unwrap_or(input.len())];
+            let line = if strip { line.trim_start_matches('\t') } else { line };
+            if line == delimiter {
+                self.here_end = Some(line_end.map_or(input.len(), |i| i + 1));
+                break;
+            }
+            match line_end {
+                Some(i) => line_start = i + 1,
+                None => return Some(Err(Error::Unterminated(start, input.len()))),
+            }
+        }
+
+        let here = HereDoc {
+            delimiter: word,
+            body: &input[body_start..line_start],
+            quoted,
+            strip,
+        };
+        Some(Ok((start, Token::HereDoc(here), end)))
+    }
+
+    // Consume the brace group starting at `start`, returning where it ends.
+    fn brace(&mut self, start: usize) -> Option<usize> {
+        let end = start + brace_len(&self.input[start..])?;
+        while let Some((s, _, _)) = self.lookahead {
+            if s >= end {
+                break;
+            }
+            self.advance();
+        }
+        Some(end)
+    }
+
+    // Consume the commands of a `$(..)`, `<(..)` or `>(..)` starting at
+    // `start`, after its `(`, returning where the `)` closing them ends, if
+    // one does.
+    fn commands(&mut self, start: usize) -> Result<Option<usize>, Error> {
+        let end = commands_end(self.input, start)?;
+        while let Some((s, _, _)) = self.lookahead {
+            if end.is_some_and(|end| s >= end) {
+                break;
+            }
+            self.advance();
+        }
+        Ok(end)
+    }
+
+    // Consume the arithmetic command starting at `start`, returning the
+    // expression between its `((` and `))`.
+    fn arithmetic(&mut self, start: usize) -> (usize, Token<'input>, usize) {
+        let end = start + arithmetic_len(&self.input[start..]).unwrap_or(0);
+        while let Some((s, _, _)) = self.lookahead {
+            if s >= end {
+                break;
+            }
+            self.advance();
+        }
+        (start, Token::Arithmetic(&self.input[start+2..end-2]), end)
     }
 
     fn io_number<'a>(&mut self, word: &'a str) -> Token<'a> {
@@ -372,13 +647,200 @@ fn is_word_continue(ch: char) -> bool {
     match ch {
         // List of syntax from above.
         // TODO: Make this list generated.
-        ';' | ')' | '(' | '`' | '!' | '=' | '\\' | '\'' | '"'
-            | '>' | '<' | '&' | '|' | '{' | '}' | '*'
+        ';' | ')' | '(' | '>' | '<' | '&' | '|' | '{' | '}'
           => false,
         _ => !ch.is_whitespace()
     }
 }
 
+// The length of the brace group at the start of the text, through its
+// matching `}`, unless whitespace or an operator comes first.
+fn brace_len(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some(q), c) if q == c => quote = None,
+            (Some(_), _) => {},
+            (None, '\\') => escaped = true,
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, '{') => depth += 1,
+            (None, '}') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            },
+            (None, c) if c.is_whitespace() || ";|&<>()".contains(c) => return None,
+            _ => {},
+        }
+    }
+    None
+}
+
+/// The length of the commands of a `$(..)` at the start of the text, after
+/// its `(`, through the `)` closing them, if one does.
+pub fn substitution_len(text: &str) -> Option<usize> {
+    commands_end(text, 0).ok().flatten()
+}
+
+// Where the commands starting at `start` in the input end, after the `)`
+// closing them, if one does. They're read as tokens, so a `)` within a word,
+// like a quoted one, or ending a pattern of a `case`, doesn't close them.
+fn commands_end(input: &str, start: usize) -> Result<Option<usize>, Error> {
+    let mut lexer = Lexer::new(input);
+    while matches!(lexer.lookahead, Some((s, _, _)) if s < start) {
+        lexer.advance();
+    }
+    // The parentheses open within, and how many were open at each `case`.
+    let mut depth = 0;
+    let mut cases = vec![];
+    for span in lexer {
+        let (_, token, end) = span?;
+        match token {
+            Token::LParen => depth += 1,
+            Token::Case => cases.push(depth),
+            Token::Esac if cases.last() == Some(&depth) => { cases.pop(); },
+            Token::RParen if cases.last() == Some(&depth) => {},
+            Token::RParen if depth == 0 => return Ok(Some(end)),
+            Token::RParen => depth -= 1,
+            _ => {},
+        }
+    }
+    Ok(None)
+}
+
+// The length of the arithmetic command at the start of the text, through
+// the `))` closing its `((`, unless a `)` closes the first `(` on its own.
+fn arithmetic_len(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut chars = text.char_indices().skip(2).peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            ')' => return match chars.peek() {
+                Some((_, ')')) => Some(i + 2),
+                _ => None,
+            },
+            _ => {},
+        }
+    }
+    None
+}
+
+// The word with its quotes removed, and whether it had any.
+fn unquote(word: &str) -> (String, bool) {
+    let mut text = String::new();
+    let mut quoted = false;
+    let mut quote = None;
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if q == c => quote = None,
+            (Some(_), c) => text.push(c),
+            (None, '\'') | (None, '"') => {
+                quoted = true;
+                quote = Some(c);
+            },
+            (None, '\\') => {
+                quoted = true;
+                text.extend(chars.next());
+            },
+            (None, c) => text.push(c),
+        }
+    }
+    (text, quoted)
+}
+
+/// Returns true for words of the form `NAME=value`, where `NAME` is a valid
+/// variable name.
+pub fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => is_name(name),
+        None => false,
+    }
+}
+
+/// Returns true for valid variable names, like `FOO` or `_bar1`.
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c == '_' || c.is_ascii_alphabetic() => {
+            chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+        },
+        _ => false,
+    }
+}
+
+/// The quoting context while reading a word.
+#[derive(Debug, Default)]
+struct WordState {
+    /// The currently open quote character, if any.
+    quote: Option<char>,
+    /// The last character was an unquoted (or double quoted) `\`.
+    escaped: bool,
+    /// The closing characters of nested `$((..))` and `${..}`
+    /// expressions, and the quote each was opened within, which is open
+    /// again after it. The commands of a `$(..)` are read as tokens
+    /// instead.
+    nesting: Vec<(char, Option<char>)>,
+    /// The last character was a `$`, `<` or `>` opening an expression with
+    /// the next `(`.
+    opening: bool,
+}
+
+impl WordState {
+    fn is_bare(&self) -> bool {
+        self.quote.is_none() && !self.escaped && !self.opening && self.nesting.is_empty()
+    }
+
+    /// Is a quote or expression still waiting to be closed.
+    fn is_open(&self) -> bool {
+        self.quote.is_some() || !self.nesting.is_empty()
+    }
+
+    fn push(&mut self, c: char, lookahead: Option<(usize, char, usize)>) {
+        if self.escaped {
+            self.escaped = false;
+            return;
+        }
+        match (self.quote, c) {
+            (Some('\''), '\'') => self.quote = None,
+            (Some('\''), _) => {},
+            (_, '\\') => self.escaped = true,
+            // Expressions are opened within double quotes too, and have
+            // quotes of their own, like `"$(echo "a b")"`.
+            (None, '$') | (Some('"'), '$') | (None, '<') | (None, '>') => {
+                match lookahead {
+                    Some((_, '(', _)) => self.opening = true,
+                    Some((_, '{', _)) if c == '$' => {
+                        self.nesting.push(('}', self.quote.take()));
+                    },
+                    _ => {},
+                }
+            },
+            (_, '(') if self.opening => {
+                self.opening = false;
+                self.nesting.push((')', self.quote.take()));
+            },
+            (Some(q), c) if q == c => self.quote = None,
+            (Some(_), _) => {},
+            (None, '\'') | (None, '"') | (None, '`') => self.quote = Some(c),
+            (None, '(') if !self.nesting.is_empty() => self.nesting.push((')', None)),
+            (None, c) if matches!(self.nesting.last(), Some((n, _)) if *n == c) => {
+                self.quote = self.nesting.pop().and_then(|(_, quote)| quote);
+            },
+            _ => {},
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -391,9 +853,20 @@ mod tests {
 
     #[test]
     fn error() {
-        let mut lexer = Lexer::new("*");
+        let mut lexer = Lexer::new("\u{7f}");
         assert_matches!(lexer.next(),
-                        Some(Err(Error::UnrecognizedChar(_, '*', _))));
+                        Some(Err(Error::UnrecognizedChar(_, '\u{7f}', _))));
+    }
+
+    #[test]
+    fn unterminated() {
+        let mut lexer = Lexer::new("echo 'a b");
+        lexer.next();
+        assert_matches!(lexer.next(), Some(Err(Error::Unterminated(5, 9))));
+        let mut lexer = Lexer::new("x=$(ls\n");
+        assert_matches!(lexer.next(), Some(Err(Error::Unterminated(0, 7))));
+        let mut lexer = Lexer::new("a${x");
+        assert_matches!(lexer.next(), Some(Err(Error::Unterminated(0, 4))));
     }
 
     #[test]
@@ -419,6 +892,184 @@ mod tests {
                         Some(Ok((_, Token::Word("$PATH"), _))));
     }
 
+    #[test]
+    fn quoted_words() {
+        let mut lexer = Lexer::new("echo 'a b' \"c d\" e\\ f");
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("echo"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("'a b'"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("\"c d\""), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("e\\ f"), _))));
+        assert!(lexer.next().is_none());
+
+        let mut lexer = Lexer::new("a'b;c'\"d|e\"");
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("a'b;c'\"d|e\""), _))));
+        let mut lexer = Lexer::new("${FOO} $");
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("${FOO}"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("$"), _))));
+    }
+
+    #[test]
+    fn line_continuation() {
+        let mut lexer = Lexer::new("ls \\\n  -la\\\nh \\\n| wc");
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("ls"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("-la\\\nh"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Pipe, _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("wc"), _))));
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn semicolons() {
+        let mut lexer = Lexer::new("a;;b; ;*)");
+        assert_matches!(lexer.next(), Some(Ok((_, Token::Word("a"), _))));
+        assert_matches!(lexer.next(), Some(Ok((1, Token::DSemi, 3))));
+        assert_matches!(lexer.next(), Some(Ok((_, Token::Word("b"), _))));
+        assert_matches!(lexer.next(), Some(Ok((_, Token::Semi, _))));
+        assert_matches!(lexer.next(), Some(Ok((_, Token::Semi, _))));
+        assert_matches!(lexer.next(), Some(Ok((_, Token::Word("*"), _))));
+        assert_matches!(lexer.next(), Some(Ok((_, Token::RParen, _))));
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn bang() {
+        let mut lexer = Lexer::new("! [ a != b ] !x");
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Bang, _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("["), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("a"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("!="), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("b"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("]"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("!x"), _))));
+    }
+
+    #[test]
+    fn brace_words() {
+        let mut lexer = Lexer::new("echo {a,b}{1,2} x{1..3}y {} { a; }");
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("echo"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("{a,b}{1,2}"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("x{1..3}y"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("{}"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::LBrace, _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("a"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Semi, _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::RBrace, _))));
+    }
+
+    #[test]
+    fn process_substitution_words() {
+        let mut lexer = Lexer::new("diff <(ls a) >(cat $(echo b)) < c");
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("diff"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("<(ls a)"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word(">(cat $(echo b))"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Less, _))));
+    }
+
+    #[test]
+    fn command_substitution_words() {
+        let mut lexer = Lexer::new("echo $(ls a) \"$(echo \"a b\")\" x`date`y \"${x:-\"a b\"}\" $((1))");
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("echo"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("$(ls a)"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("\"$(echo \"a b\")\""), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("x`date`y"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("\"${x:-\"a b\"}\""), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("$((1))"), _))));
+        assert!(lexer.next().is_none());
+        let mut lexer = Lexer::new("$(echo ')' \"(\")");
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("$(echo ')' \"(\")"), _))));
+    }
+
+    #[test]
+    fn command_substitution_case() {
+        // The `)` of a pattern doesn't close the substitution, but the one
+        // after `esac` does.
+        let mut lexer = Lexer::new("echo \"$(case x in x) echo in;; (y) ;; esac)\" done");
+        lexer.next();
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("\"$(case x in x) echo in;; (y) ;; esac)\""), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("done"), _))));
+        assert!(lexer.next().is_none());
+        let mut lexer = Lexer::new("$(echo case) $( (case x in x) ;; esac) )");
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("$(echo case)"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("$( (case x in x) ;; esac) )"), _))));
+        let mut lexer = Lexer::new("$(case x in x) echo)");
+        assert_matches!(lexer.next(), Some(Err(Error::Unterminated(0, 20))));
+    }
+
+    #[test]
+    fn arithmetic() {
+        let mut lexer = Lexer::new("(( i = (i + 1) * 2 )) && ((x))");
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Arithmetic(" i = (i + 1) * 2 "), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::And, _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Arithmetic("x"), _))));
+        assert!(lexer.next().is_none());
+
+        // Nested subshells aren't arithmetic.
+        let mut lexer = Lexer::new("((ls) && (date))");
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::LParen, _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::LParen, _))));
+    }
+
+    #[test]
+    fn assignments() {
+        let mut lexer = Lexer::new("FOO=bar _x=\"a b\" 1=2 =3 \"A\"=b");
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Assignment("FOO=bar"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Assignment("_x=\"a b\""), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("1=2"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("=3"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("\"A\"=b"), _))));
+    }
+
     #[test]
     fn redirects() {
         let mut lexer = Lexer::new(">");
@@ -443,6 +1094,9 @@ mod tests {
         let mut lexer = Lexer::new("<<-");
         assert_matches!(lexer.next(),
                         Some(Ok((_, Token::DLessDash, _))));
+        let mut lexer = Lexer::new("<<<");
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::TLess, _))));
         let mut lexer = Lexer::new("<&");
         assert_matches!(lexer.next(),
                         Some(Ok((_, Token::LessAnd, _))));
@@ -451,6 +1105,28 @@ mod tests {
                         Some(Ok((_, Token::LessGreat, _))));
     }
 
+    #[test]
+    fn here_document() {
+        let mut lexer = Lexer::new("cat <<-'E' <<F\n\ta\n\tE\nb\nF\nls");
+        assert_matches!(lexer.next(), Some(Ok((_, Token::Word("cat"), _))));
+        assert_matches!(lexer.next(), Some(Ok((_, Token::DLessDash, _))));
+        assert_matches!(lexer.next(), Some(Ok((_, Token::HereDoc(HereDoc {
+            delimiter: "'E'", body: "\ta\n", quoted: true, strip: true,
+        }), _))));
+        assert_matches!(lexer.next(), Some(Ok((_, Token::DLess, _))));
+        assert_matches!(lexer.next(), Some(Ok((_, Token::HereDoc(HereDoc {
+            delimiter: "F", body: "b\n", quoted: false, strip: false,
+        }), _))));
+        assert_matches!(lexer.next(), Some(Ok((_, Token::Linefeed, _))));
+        assert_matches!(lexer.next(), Some(Ok((_, Token::Word("ls"), _))));
+        assert_matches!(lexer.next(), None);
+
+        let mut lexer = Lexer::new("cat <<EOF\nno end");
+        lexer.next();
+        lexer.next();
+        assert_matches!(lexer.next(), Some(Err(Error::Unterminated(..))));
+    }
+
     #[test]
     fn io_number() {
         let mut lexer = Lexer::new("ls -la 1> /dev/null");
@@ -492,13 +1168,32 @@ mod tests {
 
     #[test]
     fn keywords() {
-        let mut lexer = Lexer::new("if ls done");
+        let mut lexer = Lexer::new("if ls; done");
         assert_matches!(lexer.next(),
                         Some(Ok((_, Token::If, _))));
         assert_matches!(lexer.next(),
                         Some(Ok((_, Token::Word("ls"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Semi, _))));
         assert_matches!(lexer.next(),
                         Some(Ok((_, Token::Done, _))));
+
+        // Anywhere but the start of a command they're just words.
+        let mut lexer = Lexer::new("echo fi done > case");
+        lexer.next();
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("fi"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("done"), _))));
+        lexer.next();
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("case"), _))));
+        let mut lexer = Lexer::new("for do do");
+        lexer.next();
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("do"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Do, _))));
     }
 
     #[test]
@@ -523,6 +1218,23 @@ mod tests {
         assert_matches!(lexer.next(),
                         Some(Ok((_, Token::Word("word#word"), _))));
         assert!(lexer.next().is_none());
+
+        let mut lexer = Lexer::new("\"a # b\" 'c # d' \\#e;#f");
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("\"a # b\""), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("'c # d'"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Word("\\#e"), _))));
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Semi, _))));
+        assert!(lexer.next().is_none());
+
+        // Many comment lines in a row.
+        let text = "# comment\n".repeat(100_000) + "word";
+        let mut lexer = Lexer::new(&text);
+        assert_matches!(lexer.next(),
+                        Some(Ok((_, Token::Linefeed, _))));
     }
 
     #[test]
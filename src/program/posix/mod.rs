@@ -100,12 +100,31 @@
 //!     exit 1
 //! fi
 //!
-//! # Check if $1 is equal to "foo".
-//! if [ "$1" -eq "foo" ]; then
+//! # Check if $1 is equal to "foo", `-eq` is only for integers.
+//! if [ "$1" = "foo" ]; then
 //!     echo "bar"
 //! fi
 //! ```
 //!
+//! Arithmetic can be tested with the `(( ))` command, which succeeds when
+//! the expression isn't zero. This is an extension from `bash` and `ksh`, not
+//! strict POSIX, but it's too convenient to leave to the `modern` language.
+//!
+//! ```sh
+//! (( i = i + 1 ))
+//! if (( i > 3 )); then
+//!     echo "more than three"
+//! fi
+//! ```
+//!
+//! Arithmetic expansion is POSIX though, and is replaced by the value of its
+//! expression, with the same operators.
+//!
+//! ```sh
+//! echo $(( i * 2 ))
+//! i=$((i + 1))
+//! ```
+//!
 //! # Specification
 //!
 //! The syntax and semantics of this module are strictly defined by the POSIX
@@ -115,34 +134,40 @@
 //! [1]: http://pubs.opengroup.org/onlinepubs/9699919799/
 
 use std::{
+    env,
     ffi::CString,
-    io::{Write, BufRead},
-    process::{self, Stdio},
-    fs::File,
-    os::unix::io::IntoRawFd,
-    env::{var, set_var}
+    io::{Write, BufRead, Seek, SeekFrom},
+    process,
+    fs::{self, File, OpenOptions},
+    os::unix::io::{IntoRawFd, RawFd},
+    mem,
+    result,
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 use lalrpop_util::ParseError;
 use nix::{
-    sys::wait::WaitStatus,
-    unistd::Pid,
+    fcntl::{fcntl, FcntlArg, FdFlag, OFlag},
+    sys::{
+        signal::{signal, SigHandler, Signal},
+        wait::WaitStatus,
+    },
+    unistd::{self, close, dup2, pipe2, setpgid, ForkResult, Pid},
 };
 #[cfg(feature = "raw")]
 use uuid::Uuid;
-use dirs::home_dir;
 use crate::{
-    process::{ProcessGroup, Process, Wait},
-    program::{Runtime, Result, Error},
+    process::{ProcessGroup, Process, Wait, IO, exit_code, signal_message},
+    program::{Runtime, Result, Error, Run, SyntaxError, parse_and_run},
 };
-use self::ast::{Assignment, Redirect};
+use self::ast::{Assignment, Interpreter, Redirect, Word};
 
 #[cfg(feature = "shebang-block")]
 use {
     std::io,
     std::process::ExitStatus,
-    std::fs,
-    std::os::unix::fs::PermissionsExt,
-    self::ast::Interpreter,
+    std::os::unix::fs::OpenOptionsExt,
+    crate::program::{parse, ModernProgram, PosixProgram},
 };
 
 // Re-exports.
@@ -150,6 +175,58 @@ pub use self::ast::Program;
 pub use self::ast::Command;
 pub use self::builtin::Builtin;
 
+/// Parse the text as a POSIX program without running it.
+///
+/// Errors describe where the text is invalid, and which tokens were
+/// expected instead, without printing anything.
+///
+/// ```
+/// use oursh::program::posix::parse_program;
+///
+/// assert_eq!(2, parse_program("ls; date").unwrap().0.len());
+///
+/// let error = parse_program("ls &&& date").unwrap_err();
+/// assert_eq!((5, 6), error.location);
+/// assert!(error.expected.contains(&"\"WORD\"".to_string()));
+/// assert!(error.message.starts_with("unexpected token Amper found at 5-6"));
+/// ```
+pub fn parse_program(text: &str) -> result::Result<Program, SyntaxError> {
+    let lexer = lex::Lexer::new(text);
+    let parser = parse::ProgramParser::new();
+    parser.parse(text, lexer).or_else(|e| {
+        let incomplete = matches!(e, ParseError::UnrecognizedEOF { .. } |
+                                     ParseError::User { error: lex::Error::Unterminated(..) });
+        let (location, expected, message) = match e {
+            ParseError::InvalidToken { location } => {
+                ((location, location), vec![],
+                 format!("invalid token found at {}", location))
+            },
+            ParseError::UnrecognizedToken { token: (s, t, e), expected } => {
+                let message = format!("unexpected token {:?} found at {}-{}, expecting one of: {}",
+                                      t, s, e, expected.join(", "));
+                ((s, e), expected, message)
+            },
+            // Nothing at all is an empty program.
+            ParseError::UnrecognizedEOF { location: 0, .. } => return Ok(Program(vec![])),
+            ParseError::UnrecognizedEOF { location, expected } => {
+                let message = format!("unexpected EOF found at {}, expecting one of: {}",
+                                      location, expected.join(", "));
+                ((location, location), expected, message)
+            },
+            ParseError::ExtraToken { token: (s, t, e) } => {
+                ((s, e), vec![], format!("extra token {:?} found at {}", t, s))
+            },
+            ParseError::User { error: lex::Error::UnrecognizedChar(s, c, e) } => {
+                ((s, e), vec![], format!("unexpected character {} found at {}-{}", c, s, e))
+            },
+            ParseError::User { error: lex::Error::Unterminated(s, e) } => {
+                ((s, e), vec![], format!("unterminated word found at {}-{}", s, e))
+            },
+        };
+        Err(SyntaxError { location, expected, message, incomplete })
+    })
+}
+
 /// The syntax and semantics of a single POSIX command.
 ///
 /// ```
@@ -167,40 +244,7 @@ impl super::Program for Program {
         if reader.read_to_string(&mut string).is_err() {
             return Err(Error::Read);
         }
-
-        let lexer = lex::Lexer::new(&string);
-        let parser = parse::ProgramParser::new();
-        match parser.parse(&string, lexer) {
-            Ok(parsed) => Ok(parsed),
-            Err(e) => {
-                match e {
-                    ParseError::InvalidToken { location } => {
-                        eprintln!("invalid token found at {}", location);
-                    },
-                    ParseError::UnrecognizedToken { token, expected } => {
-                        let (s, t, e) = token;
-                        eprintln!("unexpected token {:?} found at {}-{}, expecting one of: {}",
-                                  t, s, e, expected.join(", "));
-                    },
-                    ParseError::UnrecognizedEOF { location, expected } => {
-                        if location == 0 {
-                            return Ok(Program(vec![]))
-                        } else {
-                            eprintln!("unexpected EOF found at {}, expecting one of: {}",
-                                      location, expected.join(", "));
-                        }
-                    }
-                    ParseError::ExtraToken { token: (i, t, _) } => {
-                        eprintln!("extra token {:?} found at {}", t, i);
-                    }
-                    ParseError::User { error } => {
-                        let lex::Error::UnrecognizedChar(s, c, e) = error;
-                        eprintln!("unexpected character {} found at {}-{}", c, s, e);
-                    },
-                }
-                Err(Error::Parse)
-            }
-        }
+        parse_program(&string).map_err(Error::Parse)
     }
 
     fn commands(&self) -> &[Self::Command] {
@@ -213,88 +257,71 @@ impl super::Command for Command {}
 
 impl super::Run for Command {
     fn run(&self, runtime: &mut Runtime) -> Result<WaitStatus> {
+        let result = self.execute(runtime);
+        // Whatever the command was, its status is `$?` for the next.
+        if let Ok(status) = result {
+            runtime.last_status = exit_code(status);
+        }
+        // Trapped signals are handled once the command they arrived during
+        // has finished.
+        if !runtime.trapped {
+            for condition in builtin::pending() {
+                trap(&condition, runtime)?;
+            }
+        }
+        result
+    }
+}
+
+impl Command {
+    fn execute(&self, runtime: &mut Runtime) -> Result<WaitStatus> {
         #[allow(unreachable_patterns)]
         match *self {
             Command::Simple(ref assignments, ref words, ref redirects) => {
-                for Assignment(name, value) in assignments {
-                    set_var(name, expand_vars(value));
+                #[cfg(feature = "modern")]
+                if !runtime.trapped && runtime.traps.contains_key("DEBUG") {
+                    let text = assignments.iter().map(|Assignment(n, v)| {
+                        format!("{}={}", n, v)
+                    }).chain(words.iter().map(|w| w.0.clone()))
+                      .collect::<Vec<_>>()
+                      .join(" ");
+                    runtime.variables.set("BASH_COMMAND", text);
+                    trap("DEBUG", runtime)?;
                 }
 
-                for r in redirects {
-                    match r {
-                        Redirect::RW { n, filename, .. } => {
-                            let file = File::options()
-                                            .create(true)
-                                            .read(true)
-                                            .write(true)
-                                            .open(filename).unwrap();
-                            let fd = file.into_raw_fd();
-                            runtime.io.0[*n as usize] = fd;
-                        },
-                        Redirect::Read { n, filename, .. } => {
-                            let file = File::options()
-                                            .read(true)
-                                            .write(false)
-                                            .open(filename).unwrap();
-                            let fd = file.into_raw_fd();
-                            runtime.io.0[*n as usize] = fd;
-                        },
-                        Redirect::Write { n, filename, append, .. } => {
-                            // TODO: Clobber
-                            let file = File::options()
-                                            .create(true)
-                                            .read(false)
-                                            .write(true)
-                                            .append(*append)
-                                            .open(filename).unwrap();
-                            let fd = file.into_raw_fd();
-                            runtime.io.0[*n as usize] = fd;
-                        },
-                    };
-                }
-
-                // expand order: variables then home
-                // $ FOO=~
-                // $ echo $FOO
-                // /home/nixpulvis
-                let argv: Vec<CString> = words.iter().map(|word| {
-                    CString::new(&expand_home(&expand_vars(&word.0)) as &str)
-                        .expect("error in word UTF-8")
-                }).collect();
-
-                if let Some(command) = argv.clone().first() {
-                    match command.to_string_lossy().as_ref() {
-                        "."       => builtin::Dot.run(argv, runtime),
-                        ":"       => builtin::Return(0).run(argv, runtime),
-                        "cd"      => builtin::Cd.run(argv, runtime),
-                        "command" => builtin::Command.run(argv, runtime),
-                        "exit"    => builtin::Exit.run(argv, runtime),
-                        "export"  => builtin::Export.run(argv, runtime),
-                        "false"   => builtin::Return(1).run(argv, runtime),
-                        "jobs"    => builtin::Jobs.run(argv, runtime),
-                        "true"    => builtin::Return(0).run(argv, runtime),
-                        "wait"    => builtin::Wait.run(argv, runtime),
-                        _ => {
-                            let id = (runtime.jobs.borrow().len() + 1).to_string();
-                            let name = argv[0].to_string_lossy().to_string();
-                            let process = Process::fork(argv, runtime.io).map_err(|_| Error::Runtime)?;
-                            if runtime.background {
-                                let status = process.status();
-                                eprintln!("[{}]\t{}", id, process.pid());
-                                runtime.jobs.borrow_mut().push((id, ProcessGroup(process)));
-                                status.map_err(|_| Error::Runtime)
-                            } else {
-                                let status = process.wait().map_err(|_| Error::Runtime);
-                                if let Ok(WaitStatus::Exited(_, 127)) = status {
-                                    eprintln!("oursh: {}: command not found", name);
-                                }
-                                status
-                            }
-                        },
+                // Redirections only apply to this command, so work on a copy
+                // of the runtime's IO.
+                let (io, mut opened) = match redirect(redirects, runtime) {
+                    Ok(redirected) => redirected,
+                    Err(RedirectError::Expansion(e)) => return expansion_error(e, runtime),
+                    Err(RedirectError::Open(e)) => {
+                        let _ = writeln!(runtime.io.stderr(), "oursh: {}", e);
+                        return Ok(WaitStatus::Exited(Pid::this(), 1));
+                    },
+                };
+                let result = simple(assignments, words, io.clone(), runtime);
+                // `exec` without a command keeps its redirections for the
+                // shell itself.
+                if matches!(&words[..], [Word(word)] if word == "exec") {
+                    let shell = io.0.iter().zip(runtime.io.0).map(|(&fd, shell_fd)| (shell_fd, fd));
+                    for (shell_fd, fd) in shell.chain(io.1.iter().copied()) {
+                        if fd < 0 {
+                            let _ = close(shell_fd);
+                        } else if fd != shell_fd {
+                            let _ = dup2(fd, shell_fd);
+                        } else if opened.contains(&fd) {
+                            // Opened as this very file descriptor, which is
+                            // kept open, even for the programs it runs.
+                            let _ = fcntl(fd, FcntlArg::F_SETFD(FdFlag::empty()));
+                            opened.retain(|&o| o != fd);
+                        }
                     }
-                } else {
-                    Ok(WaitStatus::Exited(Pid::this(), 0))
                 }
+                // The command has its own copies of the files by now.
+                for fd in opened {
+                    let _ = close(fd);
+                }
+                result
             },
             // { sleep 3; date; }&
             // { sleep 3; date; }& ls
@@ -308,75 +335,246 @@ impl super::Run for Command {
                 Ok(last)
             },
             Command::Not(ref command) => {
-                match command.run(runtime) {
-                    Ok(WaitStatus::Exited(p, c)) => {
-                        Ok(WaitStatus::Exited(p, (c == 0) as i32))
-                    }
-                    Ok(s) => Ok(s),
-                    Err(_) => Err(Error::Runtime),
-                }
+                let (_, code) = condition(command, runtime)?;
+                Ok(WaitStatus::Exited(Pid::this(), (code == 0) as i32))
             },
+            // The left side's status is kept as it is when it decides.
             Command::And(ref left, ref right) => {
-                match left.run(runtime) {
-                    Ok(WaitStatus::Exited(_, c)) if c == 0 => {
-                        right.run(runtime).map_err(|_| Error::Runtime)
-                    },
-                    Ok(s) => Ok(s),
-                    Err(_) => Err(Error::Runtime),
+                match condition(left, runtime)? {
+                    (_, 0) => right.run(runtime),
+                    (status, _) => Ok(status),
                 }
             },
             Command::Or(ref left, ref right) => {
-                match left.run(runtime) {
-                    Ok(WaitStatus::Exited(_, c)) if c != 0 => {
-                        right.run(runtime).map_err(|_| Error::Runtime)
+                match condition(left, runtime)? {
+                    (status, 0) => Ok(status),
+                    _ => right.run(runtime),
+                }
+            },
+            Command::If(ref cond, ref then, ref els) => {
+                // Without a branch to run the status is zero.
+                if condition(cond, runtime)?.1 == 0 {
+                    then.run(runtime)
+                } else if let Some(els) = els {
+                    els.run(runtime)
+                } else {
+                    Ok(WaitStatus::Exited(Pid::this(), 0))
+                }
+            },
+            Command::While(ref cond, ref body) => {
+                // The status is the body's last, or zero if it never ran.
+                let mut status = WaitStatus::Exited(Pid::this(), 0);
+                while condition(cond, runtime)?.1 == 0 {
+                    status = body.run(runtime)?;
+                }
+                Ok(status)
+            },
+            Command::For(ref name, ref words, ref body) => {
+                let values = match words {
+                    Some(words) => {
+                        let words = braces(words, runtime);
+                        match expand_words(words.iter().map(String::as_str), runtime) {
+                            Ok(values) => values,
+                            Err(e) => return expansion_error(e, runtime),
+                        }
                     },
-                    Ok(s) => Ok(s),
-                    Err(_) => Err(Error::Runtime),
+                    None => runtime.positionals.clone(),
+                };
+                // The status is the body's last, or zero if it never ran.
+                let mut status = WaitStatus::Exited(Pid::this(), 0);
+                for value in values {
+                    if runtime.variables.is_readonly(name) {
+                        let _ = writeln!(runtime.io.stderr(), "oursh: {}: readonly variable", name);
+                        return Ok(WaitStatus::Exited(Pid::this(), 1));
+                    }
+                    runtime.variables.set(name, value);
+                    status = body.run(runtime)?;
                 }
+                Ok(status)
+            },
+            Command::Case(ref word, ref items) => {
+                let word = match expand_string(&word.0, runtime) {
+                    Ok(word) => word,
+                    Err(e) => return expansion_error(e, runtime),
+                };
+                for (patterns, body) in items {
+                    for pattern in patterns {
+                        match expand_pattern(&pattern.0, runtime) {
+                            Ok(pattern) if pattern.matches(&word) => return body.run(runtime),
+                            Ok(_) => {},
+                            Err(e) => return expansion_error(e, runtime),
+                        }
+                    }
+                }
+                // Without a pattern matching the status is zero.
+                Ok(WaitStatus::Exited(Pid::this(), 0))
+            },
+            Command::Subshell(ref program) if runtime.background => {
+                background(&self.to_string(), runtime, |runtime| program.run(runtime))
             },
             Command::Subshell(ref program) => {
-                // TODO #4: Run in a *subshell* ffs.
-                program.run(runtime)
-            },
-            Command::Pipeline(ref left, ref right) => {
-                // TODO: This is obviously a temporary hack.
-                if let box Command::Simple(_assigns, lwords, _redirs) = left {
-                    let child = process::Command::new(&lwords[0].0)
-                        .args(lwords.iter().skip(1).map(|w| &w.0))
-                        .stdout(Stdio::piped())
-                        .spawn()
-                        .expect("error swawning pipeline process");
-
-                    let output = child.wait_with_output()
-                        .expect("error reading stdout");
-
-                    if let box Command::Simple(_assigns, rwords, _redirs) = right {
-                        let mut child = process::Command::new(&rwords[0].0)
-                            .args(rwords.iter().skip(1).map(|w| &w.0))
-                            .stdin(Stdio::piped())
-                            .spawn()
-                            .expect("error swawning pipeline process");
-
-                        {
-                            let stdin = child.stdin.as_mut()
-                                .expect("error opening stdin");
-                            stdin.write_all(&output.stdout)
-                                .expect("error writing to stdin");
+                // The program is run by a copy of the shell, so its changes
+                // to variables, the working directory, and so on are lost.
+                match unsafe { unistd::fork() }.map_err(|_| Error::Runtime)? {
+                    ForkResult::Parent { child } => {
+                        match child.wait().map_err(|_| Error::Runtime)? {
+                            status @ WaitStatus::Signaled(pid, ..) => {
+                                Ok(WaitStatus::Exited(pid, exit_code(status)))
+                            },
+                            status => Ok(status),
                         }
+                    },
+                    ForkResult::Child => subshell(runtime, |runtime| program.run(runtime)),
+                }
+            },
+            Command::Pipeline(..) => {
+                // `a | b | c` is parsed as `(a | b) | c`, but each command is
+                // its own stage, run by a copy of the shell like a subshell,
+                // all at the same time, with a pipe from each to the next.
+                let mut stages = vec![];
+                let mut command = self;
+                while let Command::Pipeline(ref left, ref right) = *command {
+                    stages.push(&**right);
+                    command = left;
+                }
+                stages.push(command);
+                stages.reverse();
 
-                        child.wait()
-                            .expect("error waiting for piped command");
+                let [stdin, stdout, stderr] = runtime.io.0;
+                let mut input = stdin;
+                let mut pids = vec![];
+                let mut failed = None;
+                for (i, command) in stages.iter().enumerate() {
+                    let (output, next) = if i + 1 < stages.len() {
+                        match pipe2(OFlag::O_CLOEXEC) {
+                            Ok((read, write)) => (write, Some(read)),
+                            Err(_) => {
+                                failed = Some(Error::Runtime);
+                                break;
+                            },
+                        }
+                    } else {
+                        (stdout, None)
+                    };
+                    let started = stage(command, IO([input, output, stderr], runtime.io.1.clone()), next, runtime);
+                    // The stages have their own copies of the pipes.
+                    if input != stdin {
+                        let _ = close(input);
+                    }
+                    if output != stdout {
+                        let _ = close(output);
+                    }
+                    match started {
+                        Ok(pid) => pids.push(pid),
+                        Err(e) => {
+                            if let Some(next) = next {
+                                let _ = close(next);
+                            }
+                            failed = Some(e);
+                            break;
+                        },
                     }
+                    input = next.unwrap_or(stdin);
                 }
-                Ok(WaitStatus::Exited(Pid::this(), 0))
+                if input != stdin {
+                    let _ = close(input);
+                }
+
+                // Every stage which was started is waited for, even when
+                // another couldn't be.
+                let mut statuses = vec![];
+                for pid in pids {
+                    statuses.push(match pid.wait().map_err(|_| Error::Runtime)? {
+                        status @ WaitStatus::Signaled(pid, ..) => {
+                            WaitStatus::Exited(pid, exit_code(status))
+                        },
+                        status => status,
+                    });
+                }
+                if let Some(e) = failed {
+                    return Err(e);
+                }
+                runtime.pipestatus = statuses;
+                // With `pipefail` the last command to fail decides.
+                if runtime.options.pipefail {
+                    if let Some(status) = runtime.pipestatus.iter().rev().find(|s| {
+                        exit_code(**s) != 0
+                    }) {
+                        return Ok(*status);
+                    }
+                }
+                Ok(*runtime.pipestatus.last().expect("a pipeline has stages"))
             },
             Command::Background(ref command) => {
+                // Utilities and subshells are started in the background on
+                // their own, anything else is run by a copy of the shell, as
+                // a single job.
+                let alone = match **command {
+                    Command::Simple(_, ref words, _) => !words.is_empty(),
+                    Command::Subshell(_) => true,
+                    _ => false,
+                };
+                if !alone {
+                    let text = command.to_string();
+                    return background(&text, runtime, |runtime| command.run(runtime));
+                }
+                let background = runtime.background;
                 runtime.background = true;
-                command.run(runtime)
+                let result = command.run(runtime);
+                runtime.background = background;
+                result
+            },
+            Command::Redirected(ref command, ref redirects) => {
+                // Every command within uses the redirected IO, like each
+                // simple command uses its own.
+                let (io, opened) = match redirect(redirects, runtime) {
+                    Ok(redirected) => redirected,
+                    Err(RedirectError::Expansion(e)) => return expansion_error(e, runtime),
+                    Err(RedirectError::Open(e)) => {
+                        let _ = writeln!(runtime.io.stderr(), "oursh: {}", e);
+                        return Ok(WaitStatus::Exited(Pid::this(), 1));
+                    },
+                };
+                let outer_io = mem::replace(&mut runtime.io, io);
+                let result = command.run(runtime);
+                runtime.io = outer_io;
+                for fd in opened {
+                    let _ = close(fd);
+                }
+                result
+            },
+            Command::Function(ref name, ref body) => {
+                runtime.functions.insert(name.clone(), Rc::new(*body.clone()));
+                Ok(WaitStatus::Exited(Pid::this(), 0))
+            },
+            Command::Arithmetic(_) if runtime.options.posix => {
+                not_posix("arithmetic command `((...))`", runtime)
+            },
+            Command::Lang(Interpreter::Shebang(_), _) if runtime.options.posix => {
+                not_posix("shebang block `{#!...}`", runtime)
+            },
+            Command::Lang(..) if runtime.options.posix => {
+                not_posix("language block `{#...}`", runtime)
+            },
+            Command::Arithmetic(ref expression) => {
+                // Parameters are expanded before the expression is evaluated,
+                // so `$x` and `x` are both the value of `x`.
+                let expression = match expand_string(expression, runtime) {
+                    Ok(expression) => expression,
+                    Err(e) => return expansion_error(e, runtime),
+                };
+                match arithmetic::evaluate(&expression, &mut runtime.variables) {
+                    Ok(value) => Ok(WaitStatus::Exited(Pid::this(), (value == 0) as i32)),
+                    Err(e) => {
+                        let _ = writeln!(runtime.io.stderr(), "oursh: (({})): {}",
+                                         expression, e);
+                        Ok(WaitStatus::Exited(Pid::this(), 1))
+                    },
+                }
             },
             #[cfg(feature = "shebang-block")]
             Command::Lang(ref interpreter, ref text) => {
-                fn bridge(interpreter: &str, text: &str) -> io::Result<ExitStatus> {
+                fn bridge(interpreter: &str, text: &str, runtime: &Runtime) -> io::Result<ExitStatus> {
                     // TODO: Even for the Shebang interpretor, we shouldn't
                     // create files like this.
                     let bridgefile = format!("/tmp/.oursh_bridge-{}", Uuid::new_v4());
@@ -384,7 +582,11 @@ impl super::Run for Command {
                         // TODO: Use our job interface without creating any
                         // fucking files... The shebang isn't even a real
                         // POSIX standard.
-                        let mut file = File::create(&bridgefile)?;
+                        // Executable, as far as the umask allows.
+                        let mut file = File::options().write(true)
+                                                      .create_new(true)
+                                                      .mode(0o777)
+                                                      .open(&bridgefile)?;
                         let mut interpreter = interpreter.chars()
                                                          .map(|c| c as u8)
                                                          .collect::<Vec<u8>>();
@@ -396,14 +598,29 @@ impl super::Run for Command {
                                        .map(|c| c as u8)
                                        .collect::<Vec<u8>>();
                         file.write_all(&text)?;
-
-                        let mut perms = fs::metadata(&bridgefile)?.permissions();
-                        perms.set_mode(0o777);
-                        fs::set_permissions(&bridgefile, perms)?;
                     }
-                    process::Command::new(&bridgefile).spawn()?.wait()
+                    process::Command::new(&bridgefile)
+                        .env_clear()
+                        .envs(runtime.variables.exported())
+                        .stdin(runtime.io.stdio(0)?)
+                        .stdout(runtime.io.stdio(1)?)
+                        .stderr(runtime.io.stdio(2)?)
+                        .spawn()?
+                        .wait()
                 }
-                // TODO: Pass text off to another parser.
+                // The languages of oursh itself are parsed and run right
+                // here, everything else is passed off to the interpreter.
+                let language = match interpreter {
+                    Interpreter::HashLang(ref language) |
+                    Interpreter::Shebang(ref language) => language.as_str(),
+                    _ => "",
+                };
+                match language {
+                    "posix" => return parse::<PosixProgram, _>(text.as_bytes())?.run(runtime),
+                    "modern" => return parse::<ModernProgram, _>(text.as_bytes())?.run(runtime),
+                    _ => {},
+                }
+
                 let interpreter = match interpreter {
                     Interpreter::Primary => {
                         unimplemented!()
@@ -425,7 +642,7 @@ impl super::Run for Command {
                     },
                 };
 
-                bridge(interpreter, text).map_err(|_| Error::Read)?;
+                bridge(interpreter, text, runtime).map_err(|_| Error::Read)?;
                 Ok(WaitStatus::Exited(Pid::this(), 0))
             },
             #[cfg(not(feature = "shebang-block"))]
@@ -435,62 +652,550 @@ impl super::Run for Command {
         }
     }
 }
-fn expand_home(word: &str) -> String {
-    if let Some(w) = word.strip_prefix('~') {
-        if let Some(path) = home_dir() {
-            format!("{}{}", &path.to_str().expect("error: home not set"), w)
-        } else {
-            "~".into()
+
+// Run the command in the background, in a copy of the shell which leads a
+// process group of its own, adding it to the job table with the text.
+fn background(text: &str, runtime: &mut Runtime,
+              run: impl FnOnce(&mut Runtime) -> Result<WaitStatus>) -> Result<WaitStatus> {
+    match unsafe { unistd::fork() }.map_err(|_| Error::Runtime)? {
+        ForkResult::Parent { child } => {
+            // Also set by the child, whichever runs first.
+            let _ = setpgid(child, child);
+            let body = CString::new(text).unwrap_or_default();
+            runtime.add_job(ProcessGroup::new(Process::child(vec![body], child)));
+            Ok(WaitStatus::StillAlive)
+        },
+        ForkResult::Child => {
+            let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+            runtime.background = false;
+            subshell(runtime, run)
+        },
+    }
+}
+
+// Start running one stage of a pipeline in a copy of the shell, with the
+// given IO, returning its pid. The copy closes the read end of the next
+// pipe, if there is one, which it would otherwise keep open.
+fn stage(command: &Command, io: IO, next: Option<RawFd>, runtime: &mut Runtime)
+    -> Result<Pid>
+{
+    match unsafe { unistd::fork() }.map_err(|_| Error::Runtime)? {
+        ForkResult::Parent { child } => Ok(child),
+        ForkResult::Child => {
+            if let Some(next) = next {
+                let _ = close(next);
+            }
+            // Like any other command, writing to a pipe nobody reads from
+            // anymore ends it.
+            let _ = unsafe { signal(Signal::SIGPIPE, SigHandler::SigDfl) };
+            if io.dup().is_err() {
+                process::exit(1);
+            }
+            runtime.io = IO::default();
+            runtime.background = false;
+            subshell(runtime, |runtime| command.run(runtime))
+        },
+    }
+}
+
+// Run the command as a subshell, in this copy of the shell, and exit with
+// its status.
+//
+// The traps of the shell aren't the subshell's, except for the ignored
+// signals, so only an `EXIT` trap set by the command itself is run at the
+// end.
+pub(crate) fn subshell(runtime: &mut Runtime,
+                         run: impl FnOnce(&mut Runtime) -> Result<WaitStatus>) -> ! {
+    // Only ignored signals stay as they were in the shell.
+    for (condition, action) in runtime.traps.iter() {
+        if !action.is_empty() {
+            let _ = builtin::install(condition, None);
         }
+    }
+    runtime.traps.retain(|_, action| action.is_empty());
+    let code = match run(runtime) {
+        Ok(status) => exit_code(status),
+        // A `return` from a function ends the subshell it's run in.
+        Err(Error::Exit(code)) |
+        Err(Error::Return(code)) => code,
+        Err(_) => 1,
+    };
+    process::exit(runtime.shutdown(code))
+}
+
+// Refuse to run an extension of the language in POSIX mode, saying which.
+fn not_posix(extension: &str, runtime: &mut Runtime) -> Result<WaitStatus> {
+    let _ = writeln!(runtime.io.stderr(), "oursh: {} isn't POSIX", extension);
+    Ok(WaitStatus::Exited(Pid::this(), 2))
+}
+
+// The words with their brace groups expanded. Brace expansion isn't POSIX,
+// so in POSIX mode the words are left as they are.
+fn braces(words: &[Word], runtime: &Runtime) -> Vec<String> {
+    if runtime.options.posix {
+        words.iter().map(|word| word.0.clone()).collect()
     } else {
-        word.into()
+        words.iter().flat_map(|word| brace::expand(&word.0)).collect()
     }
 }
 
-// "$" => "$"
-// "$ " => "$ "
-// "$USER" => "nixpulvis"
-fn expand_vars(string: &str) -> String {
-    let mut result = String::new();
-    let mut variable = String::new();
-    let mut variable_start = -1;
-    for (i, c) in string.char_indices() {
-        if c == '$' || c == ' ' {
-            // It's not possible for these to be vars.
-            if variable.is_empty() {
-                result.push(c);
-            } else {
-                result += &var(&variable).unwrap_or_else(|_| "".into());
+// Run a simple command, with its redirections already opened.
+fn simple(assignments: &[Assignment], words: &[Word], io: IO, runtime: &mut Runtime)
+    -> Result<WaitStatus>
+{
+    runtime.substitution_status = None;
+    // Process substitutions last until the command has finished.
+    let mut substitutions: Vec<Substitution> = vec![];
+    // The arguments of `export` and `readonly` which are assignments are
+    // expanded like the assignments before a command, as a single field.
+    let declaration = matches!(words.first(), Some(Word(name)) if name == "export" ||
+                                                                 name == "readonly");
+    let mut argv = vec![];
+    for word in braces(words, runtime) {
+        let word = match Substitution::parse(&word) {
+            Some(_) if runtime.options.posix => {
+                for substitution in substitutions {
+                    substitution.finish();
+                }
+                return not_posix("process substitution `<(...)`", runtime);
+            },
+            Some((direction, text)) => {
+                match Substitution::spawn(direction, text, &substitutions, runtime) {
+                    Ok(substitution) => {
+                        let path = substitution.path.clone();
+                        substitutions.push(substitution);
+                        path
+                    },
+                    Err(e) => {
+                        let _ = writeln!(runtime.io.stderr(), "oursh: {}: {}", word, e);
+                        for substitution in substitutions {
+                            substitution.finish();
+                        }
+                        return Ok(WaitStatus::Exited(Pid::this(), 1));
+                    },
+                }
+            },
+            None if declaration && lex::is_assignment(&word) => {
+                let (name, value) = word.split_once('=').expect("assignment without '='");
+                match expand_assignment(value, runtime) {
+                    Ok(value) => format!("{}={}", name, value),
+                    Err(e) => {
+                        for substitution in substitutions {
+                            substitution.finish();
+                        }
+                        return expansion_error(e, runtime);
+                    },
+                }
+            },
+            None => {
+                match expand_word(&word, runtime) {
+                    Ok(fields) => argv.extend(fields.into_iter().map(|field| {
+                        CString::new(field).expect("error in word UTF-8")
+                    })),
+                    Err(e) => {
+                        for substitution in substitutions {
+                            substitution.finish();
+                        }
+                        return expansion_error(e, runtime);
+                    },
+                }
+                continue;
+            },
+        };
+        argv.push(CString::new(word).expect("error in word UTF-8"));
+    }
+
+    let result = assign_and_run(assignments, argv, io, runtime);
+    for substitution in substitutions {
+        substitution.finish();
+    }
+    result
+}
+
+// Run the expanded command, with the assignments made for it.
+fn assign_and_run(assignments: &[Assignment], argv: Vec<CString>, io: IO, runtime: &mut Runtime)
+    -> Result<WaitStatus>
+{
+    // Nothing is run when a readonly variable would be assigned.
+    if let Some(Assignment(name, _)) = assignments.iter().find(|Assignment(name, _)| {
+        runtime.variables.is_readonly(name)
+    }) {
+        let _ = writeln!(runtime.io.stderr(), "oursh: {}: readonly variable", name);
+        return Ok(WaitStatus::Exited(Pid::this(), 1));
+    }
+
+    // Without a command name, or for special builtins like `export`, the
+    // assignments are made to the shell itself. Otherwise they only apply to
+    // (and are exported for) the command.
+    if argv.is_empty() || is_special(&argv[0]) {
+        for Assignment(name, value) in assignments {
+            match expand_assignment(value, runtime) {
+                Ok(value) => runtime.variables.set(name, value),
+                Err(e) => return expansion_error(e, runtime),
             }
-            variable.clear();
-            variable_start = -1;
         }
+        // The status of a command without a name is that of its last
+        // command substitution, if it has one.
+        if argv.is_empty() {
+            let code = runtime.substitution_status.unwrap_or(0);
+            return Ok(WaitStatus::Exited(Pid::this(), code));
+        }
+        return run_simple(argv, io, runtime);
+    }
+
+    let mut saved = vec![];
+    let mut failed = None;
+    for Assignment(name, value) in assignments {
+        let value = match expand_assignment(value, runtime) {
+            Ok(value) => value,
+            Err(e) => {
+                failed = Some(e);
+                break;
+            },
+        };
+        let old = runtime.variables.get(name).map(String::from);
+        let exported = runtime.variables.is_exported(name);
+        runtime.variables.set(name, value);
+        runtime.variables.export(name);
+        saved.push((name, old, exported));
+    }
+    let result = match failed {
+        Some(e) => expansion_error(e, runtime),
+        None => run_simple(argv, io, runtime),
+    };
+    for (name, old, exported) in saved.into_iter().rev() {
+        match old {
+            Some(value) => runtime.variables.set(name, value),
+            None => runtime.variables.unset(name),
+        }
+        if !exported {
+            runtime.variables.unexport(name);
+        }
+    }
+    result
+}
+
+// Why a redirection failed. A word which couldn't be expanded is an error
+// like it is for the words of a command, unlike a file which can't be opened.
+enum RedirectError {
+    Expansion(String),
+    Open(String),
+}
+
+impl From<String> for RedirectError {
+    fn from(message: String) -> Self {
+        RedirectError::Open(message)
+    }
+}
+
+// Open the files of the redirections, returning the IO for the command and
+// the newly opened file descriptors.
+fn redirect(redirects: &[Redirect], runtime: &mut Runtime)
+    -> result::Result<(IO, Vec<RawFd>), RedirectError>
+{
+    let mut io = runtime.io.clone();
+    let mut opened = vec![];
+    for r in redirects {
+        match open(r, &mut io, runtime) {
+            Ok(Some(fd)) => opened.push(fd),
+            Ok(None) => {},
+            Err(e) => {
+                for fd in opened {
+                    let _ = close(fd);
+                }
+                return Err(e);
+            },
+        }
+    }
+    Ok((io, opened))
+}
 
-        if c == '$' {
-            variable_start = i as i32;
-        } else if c == ' ' {
-            variable_start = -1;
-        } else if c == '@' || c == ':' {
-            result += &var(&variable).unwrap_or_else(|_| "".into());
-            variable.clear();
-            variable_start = -1;
-            result.push(c);
-        } else if variable_start > -1 {
-            if variable.is_empty() {
-                result.pop();  // remove the leading '$'.
+// Open the file of one redirection in place of its file descriptor in the
+// IO, returning the newly opened file descriptor, if there is one.
+//
+// A duplication like `2>&1` copies the file descriptor as it is in the IO
+// so far, so `2>&1 >file` writes errors to the old standard output, while
+// `>file 2>&1` writes them to the file. Duplicating `-` closes it instead.
+fn open(redirect: &Redirect, io: &mut IO, runtime: &mut Runtime)
+    -> result::Result<Option<RawFd>, RedirectError>
+{
+    let mut options = File::options();
+    let (n, filename) = match redirect {
+        Redirect::Read { n, filename, duplicate: true } |
+        Redirect::Write { n, filename, duplicate: true, .. } => {
+            let word = expand_string(filename, runtime).map_err(RedirectError::Expansion)?;
+            let source = match (word.as_str(), word.parse::<RawFd>()) {
+                ("-", _) => -1,
+                (_, Ok(m)) => match io.get(m) {
+                    Some(fd) if fd >= 0 => fd,
+                    // Any other file descriptor must be one the shell has
+                    // open.
+                    None if m >= 0 && fcntl(m, FcntlArg::F_GETFD).is_ok() => m,
+                    _ => return Err(format!("{}: bad file descriptor", m).into()),
+                },
+                (_, Err(_)) => return Err(format!("{}: ambiguous redirect", word).into()),
+            };
+            io.set(*n, source);
+            return Ok(None);
+        },
+        Redirect::RW { n, filename, .. } => {
+            options.create(true).read(true).write(true);
+            (n, expand_string(filename, runtime).map_err(RedirectError::Expansion)?)
+        },
+        Redirect::Read { n, filename, .. } => {
+            options.read(true);
+            (n, expand_string(filename, runtime).map_err(RedirectError::Expansion)?)
+        },
+        Redirect::Write { n, filename, append, clobber, .. } => {
+            let filename = expand_string(filename, runtime).map_err(RedirectError::Expansion)?;
+            options.write(true).append(*append).truncate(!*append);
+            // With `noclobber` only a new file is created, though files
+            // which aren't regular, like `/dev/null`, are still written.
+            if runtime.options.noclobber && !*append && !*clobber {
+                match fs::metadata(&filename) {
+                    Ok(m) if m.is_file() => {
+                        return Err(format!("{}: cannot overwrite existing file", filename).into());
+                    },
+                    Ok(_) => {},
+                    Err(_) => { options.create_new(true); },
+                }
+            } else {
+                options.create(true);
             }
-            variable.push(c);
-        } else {
-            result.push(c);
+            (n, filename)
+        },
+        Redirect::String { n, word } => {
+            if runtime.options.posix {
+                return Err(RedirectError::Open("here-string `<<<` isn't POSIX".into()));
+            }
+            // The word is a single line of input, never split into fields.
+            let word = expand_string(word, runtime).map_err(RedirectError::Expansion)?;
+            let text = format!("{}\n", word);
+            let fd = here(&text, "here-string")?;
+            io.set(*n, fd);
+            return Ok(Some(fd));
+        },
+        Redirect::Here { n, text, quoted, .. } => {
+            let text = if *quoted {
+                text.clone()
+            } else {
+                expand_here(text, runtime).map_err(RedirectError::Expansion)?
+            };
+            let fd = here(&text, "here-document")?;
+            io.set(*n, fd);
+            return Ok(Some(fd));
+        },
+    };
+    let fd = options.open(&filename).map_err(|e| format!("{}: {}", filename, e))?.into_raw_fd();
+    io.set(*n, fd);
+    Ok(Some(fd))
+}
+
+// A file with the text written to it, returning it opened for reading from
+// the start, for the input of a here-document or here-string, as `kind`
+// says. The file is removed right away, so it's gone once nothing has it
+// open anymore, and it holds any amount of text.
+fn here(text: &str, kind: &str) -> result::Result<RawFd, String> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let path = env::temp_dir().join(format!(".oursh_here-{}-{}", process::id(),
+                                            COUNT.fetch_add(1, Ordering::Relaxed)));
+    let mut file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)
+        .map_err(|e| format!("{}: {}", kind, e))?;
+    let _ = fs::remove_file(&path);
+    file.write_all(text.as_bytes())
+        .and_then(|_| file.seek(SeekFrom::Start(0)))
+        .map_err(|e| format!("{}: {}", kind, e))?;
+    Ok(file.into_raw_fd())
+}
+
+// Run a command whose failure is tested, like the left side of `&&`, and
+// so isn't an error, returning its status along with its exit code.
+fn condition(command: &Command, runtime: &mut Runtime) -> Result<(WaitStatus, i32)> {
+    let outer = runtime.condition;
+    runtime.condition = true;
+    let result = command.run(runtime);
+    runtime.condition = outer;
+    result.map(|status| (status, exit_code(status)))
+}
+
+// Run the action trapped for the condition, if there is one.
+//
+// Traps aren't triggered from within the action of another. Only an `exit`
+// from the action is returned, other errors are ignored, and `$?` is left as
+// it was.
+fn trap(condition: &str, runtime: &mut Runtime) -> Result<()> {
+    if runtime.trapped {
+        return Ok(());
+    }
+    if let Some(action) = runtime.traps.get(condition).cloned() {
+        runtime.trapped = true;
+        #[cfg(feature = "modern")]
+        let last = runtime.last_argument.clone();
+        let status = runtime.last_status;
+        let result = parse_and_run(&action, runtime);
+        #[cfg(feature = "modern")]
+        {
+            runtime.last_argument = last;
+        }
+        runtime.last_status = status;
+        runtime.trapped = false;
+        if let Err(Error::Exit(code)) = result {
+            return Err(Error::Exit(code));
         }
     }
-    result += &var(&variable).unwrap_or_else(|_| "".into());
+    Ok(())
+}
+
+// Special builtins keep the assignments made before them.
+fn is_special(name: &CString) -> bool {
+    matches!(name.to_bytes(), b"." | b":" | b"exit" | b"export" | b"readonly" |
+             b"return" | b"set" | b"shift" | b"trap" | b"unset")
+}
+
+// Run a builtin or executable with the given (expanded) arguments.
+fn run_simple(argv: Vec<CString>, io: IO, runtime: &mut Runtime) -> Result<WaitStatus> {
+    #[cfg(feature = "modern")]
+    let last = argv.last().map(|a| a.to_string_lossy().into_owned());
+
+    // Functions are found before all but the special builtins.
+    let name = argv[0].to_string_lossy().into_owned();
+    let function = runtime.functions.get(&name).filter(|_| {
+        !is_special(&argv[0])
+    }).cloned();
+    let result = match function {
+        // Like builtins, a function run in the background is run by a copy
+        // of the shell.
+        Some(body) if runtime.background => {
+            let text = text(&argv);
+            background(&text, runtime, |runtime| call(&body, argv, io, runtime))
+        },
+        Some(body) => call(&body, argv, io, runtime),
+        None => {
+            // Builtins write to the command's IO through the runtime.
+            let outer_io = mem::replace(&mut runtime.io, io.clone());
+            let result = run_utility(argv, io, runtime);
+            runtime.io = outer_io;
+            #[cfg(feature = "modern")]
+            if let Ok(status) = result {
+                if exit_code(status) != 0 && !runtime.condition {
+                    trap("ERR", runtime)?;
+                }
+            }
+            result
+        },
+    };
+
+    #[cfg(feature = "modern")]
+    if let Some(last) = last {
+        runtime.last_argument = last;
+    }
     result
 }
 
+// Call a function, with the arguments as its positional parameters, until
+// it ends or `return`s.
+fn call(body: &Command, argv: Vec<CString>, io: IO, runtime: &mut Runtime) -> Result<WaitStatus> {
+    let args = argv[1..].iter().map(|a| {
+        a.to_string_lossy().into_owned()
+    }).collect();
+    let positionals = mem::replace(&mut runtime.positionals, args);
+    let outer_io = mem::replace(&mut runtime.io, io);
+    runtime.calls += 1;
+    let result = body.run(runtime);
+    runtime.calls -= 1;
+    runtime.io = outer_io;
+    runtime.positionals = positionals;
+    match result {
+        Err(Error::Return(code)) => Ok(WaitStatus::Exited(Pid::this(), code)),
+        result => result,
+    }
+}
+
+// Run a builtin utility or executable.
+fn run_utility(argv: Vec<CString>, io: IO, runtime: &mut Runtime) -> Result<WaitStatus> {
+    if let Some(builtin) = builtin::enabled(&argv[0].to_string_lossy(), runtime) {
+        if runtime.background {
+            let text = text(&argv);
+            return background(&text, runtime, |runtime| builtin(argv, runtime));
+        }
+        return builtin(argv, runtime);
+    }
+
+    let env = runtime.variables.environment();
+    let mut process = Process::new(argv).env(env).io(io);
+    if runtime.options.pathwarn {
+        process = process.warn_cwd();
+    }
+    // Background jobs are in a process group of their own.
+    if runtime.background {
+        process = process.process_group();
+    }
+    let process = process.spawn().map_err(|_| Error::Runtime)?;
+    if runtime.background {
+        // The job isn't checked on yet, so a quick one's status is
+        // still there for `wait`.
+        runtime.add_job(ProcessGroup::new(process));
+        Ok(WaitStatus::StillAlive)
+    } else {
+        match process.wait().map_err(|_| Error::Runtime)? {
+            // A command killed by a signal is a failure like any
+            // other, with the status 128 plus the signal's number.
+            status @ WaitStatus::Signaled(pid, signal, core_dumped) => {
+                if let Some(message) = signal_message(signal) {
+                    let core = if core_dumped { " (core dumped)" } else { "" };
+                    let _ = writeln!(runtime.io.stderr(), "{}{}", message, core);
+                }
+                Ok(WaitStatus::Exited(pid, exit_code(status)))
+            },
+            status => Ok(status),
+        }
+    }
+}
+
+// The text of a command's (expanded) words, as shown for its job.
+fn text(argv: &[CString]) -> String {
+    argv.iter().map(|a| a.to_string_lossy()).collect::<Vec<_>>().join(" ")
+}
+
+/// Report an error from expanding the words of a command, which isn't run.
+///
+/// Like `exit 1`, this ends a shell which isn't interactive, otherwise the
+/// status is 1.
+pub(crate) fn expansion_error(message: String, runtime: &mut Runtime) -> Result<WaitStatus> {
+    let _ = writeln!(runtime.io.stderr(), "oursh: {}", message);
+    if runtime.interactive {
+        Ok(WaitStatus::Exited(Pid::this(), 1))
+    } else if runtime.embedded {
+        Err(Error::Exit(1))
+    } else {
+        process::exit(runtime.shutdown(1))
+    }
+}
+
 // Builtin functions for the POSIX language, like `exit` and `cd`.
 pub mod builtin;
 
+// Arithmetic expressions, like `(( i = i + 1 ))`.
+mod arithmetic;
+
+// Brace expansion, like `{a,b}` and `{1..5}`.
+mod brace;
+
+// Word expansion, like `~/$NAME`.
+mod expansion;
+pub use self::expansion::{expand_word, expand_words};
+pub(crate) use self::expansion::{expand_string, expand_assignment, expand_here, expand_pattern};
+
+// Field splitting by `$IFS`.
+mod field;
+
+// Pattern matching, like `*.rs`.
+mod pattern;
+
+// Process and command substitution, like `<(ls)` and `$(ls)`.
+mod substitution;
+use self::substitution::Substitution;
+
 // The POSIX AST data structures and helper functions.
 pub mod ast;
 
@@ -520,5 +1225,10 @@ mod tests {
         let result: Result<Program> = Program::parse(b"" as &[u8]);
         assert!(result.is_ok());
         assert!(result.unwrap().0.is_empty());
+        for text in &["   ", "\n\n", "# comment", " # comment\n", ";", " ;\n"] {
+            assert!(parse_program(text).unwrap().0.is_empty(), "parsing {:?}", text);
+        }
+        assert_eq!(1, parse_program("\n\nls\n").unwrap().0.len());
+        assert_eq!(1, parse_program("# comment\nls #done").unwrap().0.len());
     }
 }
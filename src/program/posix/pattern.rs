@@ -0,0 +1,252 @@
+//! Pattern matching notation, used by pathname expansion and `case`.
+//!
+//! A `*` matches any text, including none, a `?` matches any single
+//! character, and a bracket expression matches any single character in it,
+//! or not in it when it starts with `!` or `^`. A bracket expression is made
+//! of characters, ranges like `a-z`, and classes like `[:digit:]`, and a `]`
+//! right after the opening `[` is part of it. A `[` without a closing `]` is
+//! just a `[`.
+//!
+//! Any character after a `\` is matched as it is.
+//!
+//! ```sh
+//! *.rs        # main.rs lib.rs
+//! ?.txt       # a.txt
+//! [!a-c]*     # d e.txt
+//! ```
+
+/// A parsed pattern.
+#[derive(Debug, Clone)]
+pub(crate) struct Pattern(Vec<Token>);
+
+#[derive(Debug, Clone)]
+enum Token {
+    Char(char),
+    // `?`
+    Any,
+    // `*`
+    Star,
+    // `[..]`, and whether it's negated.
+    Bracket(bool, Vec<Item>),
+}
+
+#[derive(Debug, Clone)]
+enum Item {
+    Range(char, char),
+    Class(fn(&char) -> bool),
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Self {
+        let chars = pattern.chars().collect::<Vec<_>>();
+        let mut tokens = vec![];
+        let mut i = 0;
+        while i < chars.len() {
+            let token = match chars[i] {
+                '*' => Token::Star,
+                '?' => Token::Any,
+                '\\' if i + 1 < chars.len() => {
+                    i += 1;
+                    Token::Char(chars[i])
+                },
+                '[' => match bracket(&chars[i + 1..]) {
+                    Some((token, length)) => {
+                        i += length;
+                        token
+                    },
+                    None => Token::Char('['),
+                },
+                c => Token::Char(c),
+            };
+            tokens.push(token);
+            i += 1;
+        }
+        Pattern(tokens)
+    }
+
+    /// Does the pattern match all of the text.
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.chars().collect::<Vec<_>>();
+        let (mut t, mut i) = (0, 0);
+        // The last `*`, and where in the text it's matched up to.
+        let mut star = None;
+        while i < text.len() {
+            match self.0.get(t) {
+                Some(Token::Star) => {
+                    star = Some((t, i));
+                    t += 1;
+                    continue;
+                },
+                Some(token) if token.matches(text[i]) => {
+                    t += 1;
+                    i += 1;
+                    continue;
+                },
+                _ => {},
+            }
+            // Let the last `*` match one more character, and try again.
+            match star {
+                Some((s, j)) => {
+                    star = Some((s, j + 1));
+                    t = s + 1;
+                    i = j + 1;
+                },
+                None => return false,
+            }
+        }
+        self.0[t..].iter().all(|token| matches!(token, Token::Star))
+    }
+
+    /// The text the pattern matches, if it only matches one.
+    pub fn literal(&self) -> Option<String> {
+        self.0.iter().map(|token| match token {
+            Token::Char(c) => Some(c),
+            _ => None,
+        }).collect()
+    }
+
+    /// Does the pattern start with a `.`, which it must for a pathname
+    /// starting with one to match.
+    pub fn starts_with_dot(&self) -> bool {
+        matches!(self.0.first(), Some(Token::Char('.')))
+    }
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Char(t) => *t == c,
+            Token::Any => true,
+            Token::Star => false,
+            Token::Bracket(negated, items) => {
+                items.iter().any(|item| match item {
+                    Item::Range(start, end) => (*start..=*end).contains(&c),
+                    Item::Class(class) => class(&c),
+                }) != *negated
+            },
+        }
+    }
+}
+
+// Parse the bracket expression after a `[`, returning it and the number of
+// characters it used, including the closing `]`.
+fn bracket(chars: &[char]) -> Option<(Token, usize)> {
+    let mut i = 0;
+    let negated = matches!(chars.first(), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+    let mut items = vec![];
+    let start = i;
+    loop {
+        let c = match chars.get(i) {
+            Some(']') if i > start => return Some((Token::Bracket(negated, items), i + 1)),
+            Some('[') if chars.get(i + 1) == Some(&':') => {
+                let rest = chars[i + 2..].iter().collect::<String>();
+                let name = &rest[..rest.find(":]")?];
+                items.push(Item::Class(class(name)?));
+                i += name.chars().count() + 4;
+                continue;
+            },
+            Some('\\') if i + 1 < chars.len() => {
+                i += 1;
+                chars[i]
+            },
+            Some(&c) => c,
+            None => return None,
+        };
+        i += 1;
+        match (chars.get(i), chars.get(i + 1)) {
+            (Some('-'), Some(&end)) if end != ']' => {
+                items.push(Item::Range(c, end));
+                i += 2;
+            },
+            _ => items.push(Item::Range(c, c)),
+        }
+    }
+}
+
+fn class(name: &str) -> Option<fn(&char) -> bool> {
+    Some(match name {
+        "alnum" => char::is_ascii_alphanumeric,
+        "alpha" => char::is_ascii_alphabetic,
+        "blank" => |c: &char| *c == ' ' || *c == '\t',
+        "cntrl" => char::is_ascii_control,
+        "digit" => char::is_ascii_digit,
+        "graph" => char::is_ascii_graphic,
+        "lower" => char::is_ascii_lowercase,
+        "print" => |c: &char| c.is_ascii_graphic() || *c == ' ',
+        "punct" => char::is_ascii_punctuation,
+        "space" => char::is_ascii_whitespace,
+        "upper" => char::is_ascii_uppercase,
+        "xdigit" => char::is_ascii_hexdigit,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        let cases = [
+            ("", "", true),
+            ("*", "", true),
+            ("*", "anything", true),
+            ("?", "", false),
+            ("?", "a", true),
+            ("?", "ab", false),
+            ("*.rs", "main.rs", true),
+            ("*.rs", "main.rs.bak", false),
+            ("a*b*c", "aXbYbZc", true),
+            ("a*b*c", "aXbYbZ", false),
+            ("*a*", "bab", true),
+            ("**", "x", true),
+        ];
+        for (pattern, text, matches) in cases.iter() {
+            assert_eq!(*matches, Pattern::new(pattern).matches(text),
+                       "{:?} matching {:?}", pattern, text);
+        }
+    }
+
+    #[test]
+    fn brackets() {
+        let cases = [
+            ("[abc]", "b", true),
+            ("[abc]", "d", false),
+            ("[a-c]x", "bx", true),
+            ("[!a-c]", "b", false),
+            ("[^a-c]", "d", true),
+            ("[]]", "]", true),
+            ("[!]]", "]", false),
+            ("[a-]", "-", true),
+            ("[[:digit:]x]", "7", true),
+            ("[[:digit:]x]", "x", true),
+            ("[[:upper:]]", "a", false),
+            ("[ab", "[ab", true),
+            ("[ab", "a", false),
+        ];
+        for (pattern, text, matches) in cases.iter() {
+            assert_eq!(*matches, Pattern::new(pattern).matches(text),
+                       "{:?} matching {:?}", pattern, text);
+        }
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(Pattern::new("\\*").matches("*"));
+        assert!(!Pattern::new("\\*").matches("a"));
+        assert!(Pattern::new("a\\?").matches("a?"));
+        assert!(Pattern::new("[\\]]").matches("]"));
+        assert!(Pattern::new("a\\").matches("a\\"));
+    }
+
+    #[test]
+    fn literal() {
+        assert_eq!(Some("a*b".into()), Pattern::new("a\\*b").literal());
+        assert_eq!(None, Pattern::new("a*b").literal());
+        assert!(Pattern::new(".*").starts_with_dot());
+        assert!(!Pattern::new("*").starts_with_dot());
+    }
+}
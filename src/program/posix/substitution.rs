@@ -0,0 +1,178 @@
+//! Process substitution, `<(command)` and `>(command)`, and command
+//! substitution, `$(command)`.
+//!
+//! The command is run by a copy of the shell, connected to a pipe whose
+//! other end is given to the surrounding command as a path, like
+//! `/dev/fd/63`. The command's output is read from the path of a `<(..)`,
+//! and its input is written to the path of a `>(..)`.
+//!
+//! ```sh
+//! diff <(ls a) <(ls b)
+//! tee >(wc -l) < log
+//! ```
+//!
+//! Without `/dev/fd` a named pipe (FIFO) in the temporary directory is used
+//! instead.
+//!
+//! A command substitution is replaced by the output of its command instead,
+//! read in full before the surrounding command runs.
+use std::{
+    env,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    process,
+    os::unix::io::{FromRawFd, RawFd},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use nix::{
+    fcntl::{open, OFlag},
+    sys::{stat::Mode, wait::waitpid},
+    unistd::{self, close, mkfifo, pipe, pipe2, ForkResult, Pid},
+};
+use crate::{
+    process::exit_code,
+    program::{Runtime, parse_and_run, posix::{Program, subshell}},
+};
+
+/// A running process substitution, which lasts until the surrounding
+/// command has finished.
+#[derive(Debug)]
+pub(crate) struct Substitution {
+    /// The path given to the surrounding command in place of the word.
+    pub path: String,
+    // The end of the pipe kept open for the surrounding command.
+    fd: Option<RawFd>,
+    // The named pipe, when `/dev/fd` isn't available.
+    fifo: Option<PathBuf>,
+    child: Pid,
+}
+
+impl Substitution {
+    /// The direction (`<` or `>`) and command text of the word, if it's a
+    /// process substitution.
+    pub fn parse(word: &str) -> Option<(char, &str)> {
+        let direction = word.chars().next().filter(|c| *c == '<' || *c == '>')?;
+        word[1..].strip_prefix('(')?.strip_suffix(')').map(|text| (direction, text))
+    }
+
+    /// Start running the command text in a copy of the shell.
+    ///
+    /// The file descriptors of the earlier substitutions of the same command
+    /// are closed in the copy, so each pipe is only held open by the
+    /// processes using it.
+    pub fn spawn(direction: char, text: &str, earlier: &[Substitution], runtime: &mut Runtime)
+        -> nix::Result<Self>
+    {
+        if Path::new("/dev/fd").is_dir() {
+            let (read, write) = pipe()?;
+            // Our end, and the child's.
+            let (fd, theirs) = if direction == '<' { (read, write) } else { (write, read) };
+            match unsafe { unistd::fork() }? {
+                ForkResult::Parent { child } => {
+                    close(theirs)?;
+                    Ok(Substitution {
+                        path: format!("/dev/fd/{}", fd),
+                        fd: Some(fd),
+                        fifo: None,
+                        child,
+                    })
+                },
+                ForkResult::Child => {
+                    let _ = close(fd);
+                    run(direction, theirs, text, earlier, runtime)
+                },
+            }
+        } else {
+            static COUNT: AtomicUsize = AtomicUsize::new(0);
+            let fifo = env::temp_dir().join(format!(".oursh_fifo-{}-{}", process::id(),
+                                                    COUNT.fetch_add(1, Ordering::Relaxed)));
+            mkfifo(&fifo, Mode::S_IRUSR | Mode::S_IWUSR)?;
+            match unsafe { unistd::fork() }? {
+                ForkResult::Parent { child } => {
+                    Ok(Substitution {
+                        path: fifo.to_string_lossy().into_owned(),
+                        fd: None,
+                        fifo: Some(fifo),
+                        child,
+                    })
+                },
+                ForkResult::Child => {
+                    // Opening blocks until the surrounding command opens the
+                    // other end.
+                    let flag = if direction == '<' { OFlag::O_WRONLY } else { OFlag::O_RDONLY };
+                    match open(&fifo, flag, Mode::empty()) {
+                        Ok(fd) => run(direction, fd, text, earlier, runtime),
+                        Err(_) => process::exit(1),
+                    }
+                },
+            }
+        }
+    }
+
+    /// Close our end of the pipe, and wait for the command to finish.
+    pub fn finish(self) {
+        if let Some(fd) = self.fd {
+            let _ = close(fd);
+        }
+        if let Some(fifo) = self.fifo {
+            // Open and close the other end, in case the surrounding command
+            // never did, so the child's own open doesn't block forever.
+            if let Ok(fd) = open(&fifo, OFlag::O_RDWR | OFlag::O_NONBLOCK, Mode::empty()) {
+                let _ = close(fd);
+            }
+            let _ = unistd::unlink(&fifo);
+        }
+        let _ = waitpid(self.child, None);
+    }
+}
+
+// Run the command text in the child, with the given end of the pipe as
+// its stdout for `<(..)` or its stdin for `>(..)`, then exit.
+fn run(direction: char, fd: RawFd, text: &str, earlier: &[Substitution], runtime: &mut Runtime) -> ! {
+    for substitution in earlier {
+        if let Some(fd) = substitution.fd {
+            let _ = close(fd);
+        }
+    }
+    if direction == '<' {
+        runtime.io.0[1] = fd;
+    } else {
+        runtime.io.0[0] = fd;
+    }
+    subshell(runtime, |runtime| parse_and_run(text, runtime))
+}
+
+/// Run the (already parsed) commands in a copy of the shell, returning their
+/// output without any trailing newlines.
+///
+/// The status of the command is the status of the last command, `$?`, and
+/// of the command being expanded if it has no name.
+pub(crate) fn capture(program: &Program, runtime: &mut Runtime) -> nix::Result<String> {
+    // Neither end is inherited by the commands the shell runs meanwhile,
+    // which would keep the pipe open.
+    let (read, write) = pipe2(OFlag::O_CLOEXEC)?;
+    let fork = unsafe { unistd::fork() };
+    if fork.is_err() {
+        let _ = close(read);
+        let _ = close(write);
+    }
+    match fork? {
+        ForkResult::Parent { child } => {
+            let _ = close(write);
+            // Whatever was read is used, even if reading fails part way.
+            let mut output = vec![];
+            let _ = unsafe { File::from_raw_fd(read) }.read_to_end(&mut output);
+            let code = waitpid(child, None).map(exit_code).unwrap_or(1);
+            runtime.last_status = code;
+            runtime.substitution_status = Some(code);
+            let output = String::from_utf8_lossy(&output);
+            Ok(output.trim_end_matches('\n').to_string())
+        },
+        ForkResult::Child => {
+            let _ = close(read);
+            runtime.io.0[1] = write;
+            subshell(runtime, |runtime| runtime.run(program))
+        },
+    }
+}
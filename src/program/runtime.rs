@@ -1,14 +1,838 @@
-use docopt::ArgvMap;
-use crate::process::{Jobs, IO};
+use std::{
+    env,
+    io::{self, BufRead, Read, Write},
+    fs::File,
+    process,
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
+    os::unix::io::{FromRawFd, RawFd},
+    cell::RefCell,
+    ffi::CString,
+    path::PathBuf,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+};
+use docopt::{ArgvMap, Docopt};
+use nix::{
+    fcntl::OFlag,
+    unistd::{self, close, getpid, pipe2, setpgid, ForkResult, Pid},
+    sys::{
+        signal::{killpg, Signal},
+        wait::{waitpid, WaitPidFlag, WaitStatus},
+    },
+};
+use termion::is_tty;
+use crate::process::{Jobs, IO, ProcessGroup, exit_code, jobs};
+use crate::program::{
+    Result, Error, Run, PrimaryProgram, Program as ProgramTrait, parse_and_run, parse_primary,
+    posix::ast::Command,
+};
+use crate::NAME;
+use crate::invocation::USAGE;
 #[cfg(feature = "history")]
 use crate::repl::history::History;
 
+/// How long a program which timed out has to finish after `SIGTERM`.
+const TIMEOUT_GRACE: Duration = Duration::from_secs(1);
+
+/// How often to check whether a program with a timeout has finished.
+const TIMEOUT_POLL: Duration = Duration::from_millis(10);
+
+/// The state of a running shell, shared by every program it runs.
+///
+/// Shells embedded in another program are made with a [`Builder`], and run
+/// with [`Runtime::run_str`], or [`Runtime::run`] for a program which is
+/// parsed once.
+///
+/// ```
+/// use oursh::program::{Runtime, Error, parse_primary};
+///
+/// let mut runtime = Runtime::builder()
+///     .env("GREETING", "hello")
+///     .positionals(vec!["world".into()])
+///     .build()
+///     .unwrap();
+/// runtime.run_str("test \"$GREETING $1\" = 'hello world'").unwrap();
+///
+/// // The `exit` builtin stops the program, but not this process.
+/// let program = parse_primary(b"true && exit 3; echo unreachable" as &[u8]).unwrap();
+/// assert!(matches!(runtime.run(&program), Err(Error::Exit(3))));
+/// ```
 #[derive(Debug)]
-pub struct Runtime<'a> {
+pub struct Runtime {
     pub background: bool,
+    /// The standard input and outputs of the commands being run. Builtins
+    /// write through [`IO::stdout`] and [`IO::stderr`], so their output is
+    /// redirected and captured just like an executable's.
     pub io: IO,
-    pub jobs: &'a mut Jobs,
-    pub args: &'a ArgvMap,
+    pub jobs: Jobs,
+    pub args: ArgvMap,
+    /// The shell's variables, some of which may be exported.
+    pub variables: Variables,
+    /// The functions defined by the shell, by name.
+    pub functions: HashMap<String, Rc<Command>>,
+    /// The builtins turned off with `enable -n`, so executables of the same
+    /// name are run instead.
+    pub disabled_builtins: BTreeSet<String>,
+    /// The actions to run on each trapped condition, by name.
+    pub traps: BTreeMap<String, String>,
+    /// Set while running a trap's action, so traps aren't triggered again
+    /// from within.
+    pub trapped: bool,
+    /// Set while running a command whose failure is tested, like the
+    /// condition of an `if`, so it isn't treated as an error.
+    pub condition: bool,
+    /// The number of functions and scripts run by `.` being run, which
+    /// `return` can only be used within.
+    pub calls: usize,
+    /// The name of the shell or script, `$0`.
+    pub name: String,
+    /// The positional parameters, `$1`, `$2`, ... `$n`.
+    pub positionals: Vec<String>,
+    /// The process ID of the shell, `$$`, which is kept by subshells.
+    pub pid: Pid,
+    /// The process ID of the most recent background job, `$!`.
+    pub last_background: Option<Pid>,
+    /// The exit status of the last command, `$?`.
+    pub last_status: i32,
+    /// The status of the last command substitution of the command being
+    /// expanded, which is the status of a command without a name, like
+    /// `x=$(false)`.
+    pub substitution_status: Option<i32>,
+    /// The options set with `set -o`.
+    pub options: Options,
+    /// The status of each command of the last pipeline, in order.
+    pub pipestatus: Vec<WaitStatus>,
+    /// The position within the current argument for `getopts`, and the
+    /// `OPTIND` it's the position within.
+    pub getopts_offset: (usize, usize),
+    /// The last argument of the previous simple command, `$_`.
+    #[cfg(feature = "modern")]
+    pub last_argument: String,
     #[cfg(feature = "history")]
-    pub history: &'a mut History,
+    pub history: History,
+    /// Set when the shell is part of another program, so it must never
+    /// exit the process, e.g. for the `exit` builtin.
+    pub embedded: bool,
+    /// Is the shell reading commands from a user, see
+    /// [`Builder::interactive`]. An interactive shell keeps running after
+    /// errors which end any other, announces its background jobs, and reads
+    /// its startup script and history.
+    pub interactive: bool,
+    /// Is the shell a login shell, started with `--login` or with a name
+    /// like `-oursh`.
+    pub login: bool,
+    job_hook: Option<JobHook>,
+}
+
+// A callback for the changes in state of the jobs, see [`Runtime::on_job`].
+struct JobHook(Box<JobChanged>);
+
+type JobChanged = dyn FnMut(&str, Pid, WaitStatus);
+
+impl fmt::Debug for JobHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("JobHook")
+    }
+}
+
+impl Runtime {
+    /// Start building a runtime for an embedded shell.
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
+    /// Parse and run the text, returning the status of the last command.
+    pub fn run_str(&mut self, text: &str) -> Result<WaitStatus> {
+        parse_and_run(text, self)
+    }
+
+    /// Parse and run the program read from the reader a command at a time,
+    /// like a script, returning the status of the last command.
+    ///
+    /// Each command is run before the next is read, so commands like `read`
+    /// can take the input which follows them, and a syntax error only stops
+    /// the program once it's reached.
+    ///
+    /// ```
+    /// use oursh::program::Runtime;
+    /// use nix::sys::wait::WaitStatus;
+    ///
+    /// let mut runtime = Runtime::builder().build().unwrap();
+    /// let script = b"x=1\nif true; then\n  x=2\nfi\ntest $x = 2\n" as &[u8];
+    /// let status = runtime.run_reader(script).unwrap();
+    /// assert!(matches!(status, WaitStatus::Exited(_, 0)));
+    /// ```
+    pub fn run_reader<R: BufRead>(&mut self, mut reader: R) -> Result<WaitStatus> {
+        let mut last = WaitStatus::Exited(Pid::this(), 0);
+        while let Some(result) = PrimaryProgram::parse_next(&mut reader) {
+            let program = match result {
+                Ok(program) => program,
+                Err(Error::Parse(e)) => {
+                    eprintln!("oursh: {}", e);
+                    return Err(Error::Parse(e));
+                },
+                Err(e) => {
+                    eprintln!("oursh: {:?}", e);
+                    return Err(e);
+                },
+            };
+            if self.args.get_bool("--ast") {
+                eprintln!("{:#?}", program);
+            }
+            // Without any commands the status is left as it was.
+            if !program.commands().is_empty() {
+                last = self.run(&program)?;
+            }
+        }
+        Ok(last)
+    }
+
+    /// Run an already parsed program, which may be run any number of times.
+    ///
+    /// Finished background jobs are kept until they're waited for, or
+    /// removed with [`reap`](crate::process::jobs::reap).
+    pub fn run<P: Run>(&mut self, program: &P) -> Result<WaitStatus> {
+        program.run(self)
+    }
+
+    /// Parse and run the text like [`Runtime::run_str`], collecting what it
+    /// writes to stdout and stderr instead of passing it to the runtime's
+    /// IO.
+    ///
+    /// ```
+    /// use oursh::program::Runtime;
+    /// use nix::sys::wait::WaitStatus;
+    ///
+    /// let mut runtime = Runtime::builder().build().unwrap();
+    /// let output = runtime.run_captured("echo out; getopts").unwrap();
+    /// assert!(matches!(output.status, WaitStatus::Exited(_, 2)));
+    /// assert_eq!(b"out\n", &output.stdout[..]);
+    /// assert_eq!(b"oursh: getopts: usage: getopts optstring name [arg...]\n",
+    ///            &output.stderr[..]);
+    /// ```
+    pub fn run_captured(&mut self, text: &str) -> Result<Output> {
+        let (stdout, stdout_writer) = pipe2(OFlag::O_CLOEXEC).map_err(|_| Error::Runtime)?;
+        let (stderr, stderr_writer) = pipe2(OFlag::O_CLOEXEC).map_err(|_| Error::Runtime)?;
+
+        // Read both pipes as the program runs, so it can't fill them up.
+        let stdout = collect(stdout);
+        let stderr = collect(stderr);
+
+        let outer_io = self.io.clone();
+        self.io.0[1] = stdout_writer;
+        self.io.0[2] = stderr_writer;
+        let result = self.run_str(text);
+        self.io = outer_io;
+        let _ = close(stdout_writer);
+        let _ = close(stderr_writer);
+
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+        result.map(|status| Output { status, stdout, stderr })
+    }
+
+    /// Parse and run the text like [`Runtime::run_str`], but stop it if it
+    /// takes longer than the timeout, returning [`Error::Timeout`].
+    ///
+    /// The text is run by a copy of the shell in its own process group,
+    /// like a subshell, so its changes to variables and the like aren't
+    /// kept. When the timeout passes the whole group is sent `SIGTERM`,
+    /// followed by `SIGKILL` if it's still running a second later.
+    ///
+    /// ```
+    /// use std::time::{Duration, Instant};
+    /// use oursh::program::{Runtime, Error};
+    /// use nix::sys::wait::WaitStatus;
+    ///
+    /// let mut runtime = Runtime::builder().build().unwrap();
+    /// let status = runtime.run_timeout("exit 3", Duration::from_secs(5)).unwrap();
+    /// assert!(matches!(status, WaitStatus::Exited(_, 3)));
+    ///
+    /// let start = Instant::now();
+    /// let result = runtime.run_timeout("sleep 5; sleep 5", Duration::from_millis(50));
+    /// assert!(matches!(result, Err(Error::Timeout)));
+    /// assert!(start.elapsed() < Duration::from_secs(2));
+    /// ```
+    pub fn run_timeout(&mut self, text: &str, timeout: Duration) -> Result<WaitStatus> {
+        let program = parse_primary(text.as_bytes())?;
+        let child = match unsafe { unistd::fork() }.map_err(|_| Error::Runtime)? {
+            ForkResult::Parent { child } => child,
+            ForkResult::Child => {
+                let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+                let code = match program.run(self) {
+                    Ok(status) => exit_code(status),
+                    Err(Error::Exit(code)) => code,
+                    Err(_) => 1,
+                };
+                process::exit(code)
+            },
+        };
+        // Also set by the child, whichever runs first.
+        let _ = setpgid(child, child);
+
+        if let Some(status) = wait_until(child, Instant::now() + timeout)? {
+            return Ok(status);
+        }
+        let _ = killpg(child, Signal::SIGTERM);
+        if wait_until(child, Instant::now() + TIMEOUT_GRACE)?.is_none() {
+            let _ = killpg(child, Signal::SIGKILL);
+            let _ = waitpid(child, None);
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Finish the shell, running the `EXIT` trap, hanging up the jobs of an
+    /// interactive login shell with the `huponexit` option, and saving the
+    /// history of an interactive shell, and returning the status to exit
+    /// with.
+    ///
+    /// The status is `code`, which is `$?` in the trap, unless the trap runs
+    /// `exit` itself. This is done by the `exit` builtin and at the end of
+    /// input, except for an embedded shell, which leaves it to the embedding
+    /// program. A subshell only runs its trap.
+    pub fn shutdown(&mut self, code: i32) -> i32 {
+        let mut code = code;
+        // The trap is removed first, so an `exit` within it doesn't run it
+        // again.
+        if let Some(action) = self.traps.remove("EXIT") {
+            self.trapped = true;
+            self.last_status = code;
+            if let Err(Error::Exit(status)) = parse_and_run(&action, self) {
+                code = status;
+            }
+            self.trapped = false;
+        }
+
+        // Only the shell itself, and not a subshell, which is a copy of it
+        // with the same `$$`, has any more to do.
+        if getpid() != self.pid {
+            return code;
+        }
+
+        // Jobs which haven't been disowned are hung up, and continued in case
+        // they're stopped, so they see it. Any other shell leaves its jobs
+        // running, as they may well be meant to outlive it.
+        if self.options.huponexit && self.interactive && self.login {
+            for (_, job) in self.jobs.borrow().iter() {
+                let group = job.leader().pid();
+                let _ = killpg(group, Signal::SIGHUP);
+                let _ = killpg(group, Signal::SIGCONT);
+            }
+        }
+
+        // Failing to save the history, like in a read-only home directory,
+        // is only worth a warning.
+        #[cfg(feature = "history")]
+        if self.interactive {
+            if let Err(e) = self.history.save(&self.variables) {
+                let path = History::path(&self.variables).unwrap_or_default();
+                let _ = writeln!(self.io.stderr(), "oursh: warning: {}: history not saved: {}",
+                                 path.display(), e);
+            }
+        }
+        code
+    }
+
+    /// The index in [`jobs`](Runtime::jobs) of the job given by the spec,
+    /// or a message saying why there's no such job.
+    ///
+    /// - `%%`, `%+` and `%` are the current job, the most recently started,
+    ///   and `%-` is the previous job, started just before it.
+    /// - `%n` is the job numbered `n`.
+    /// - `%string` is the job whose command starts with `string`, and
+    ///   `%?string` the job whose command contains it.
+    /// - A bare number is the job whose process has that pid.
+    ///
+    /// ```
+    /// use oursh::program::Runtime;
+    ///
+    /// let mut runtime = Runtime::builder().build().unwrap();
+    /// runtime.run_str("sleep 0 & sh -c 'exit 1' & sh -c 'exit 2' &").unwrap();
+    /// assert_eq!(Ok(2), runtime.job("%+"));
+    /// assert_eq!(Ok(1), runtime.job("%-"));
+    /// assert_eq!(Ok(0), runtime.job("%sl"));
+    /// assert_eq!(Ok(2), runtime.job("%?2"));
+    /// assert_eq!(Err("%sh: ambiguous job spec".into()), runtime.job("%sh"));
+    /// assert_eq!(Err("%4: no such job".into()), runtime.job("%4"));
+    /// runtime.run_str("wait").unwrap();
+    /// ```
+    pub fn job(&self, spec: &str) -> std::result::Result<usize, String> {
+        let jobs = self.jobs.borrow();
+        let matching = |test: &dyn Fn(&str) -> bool| {
+            let mut found = jobs.iter().enumerate().filter(|(_, (_, job))| {
+                test(&job.leader().body())
+            }).map(|(i, _)| i);
+            match (found.next(), found.next()) {
+                (Some(i), None) => Ok(Some(i)),
+                (Some(_), Some(_)) => Err(format!("{}: ambiguous job spec", spec)),
+                (None, _) => Ok(None),
+            }
+        };
+        let index = match spec.strip_prefix('%') {
+            Some("") | Some("%") | Some("+") => jobs.len().checked_sub(1),
+            Some("-") => jobs.len().checked_sub(2),
+            Some(id) if id.bytes().all(|b| b.is_ascii_digit()) => {
+                jobs.iter().position(|(i, _)| i == id)
+            },
+            Some(text) => match text.strip_prefix('?') {
+                Some(text) => matching(&|body| body.contains(text))?,
+                None => matching(&|body| body.starts_with(text))?,
+            },
+            None => spec.parse().ok().and_then(|pid| {
+                jobs.iter().position(|(_, job)| job.leader().pid() == Pid::from_raw(pid))
+            }),
+        };
+        index.ok_or_else(|| format!("{}: no such job", spec))
+    }
+
+    /// Add a job which was started in the background, numbered one more
+    /// than the highest numbered job, returning its id.
+    ///
+    /// An interactive shell announces the job like `[1] 1234`, with its id
+    /// and the pid of its leader, which is also kept for `$!`.
+    pub(crate) fn add_job(&mut self, job: ProcessGroup) -> String {
+        let id = self.jobs.borrow().iter().filter_map(|(id, _)| id.parse::<usize>().ok())
+                                          .max().unwrap_or(0) + 1;
+        let id = id.to_string();
+        let pid = job.leader().pid();
+        if self.interactive {
+            let _ = writeln!(self.io.stderr(), "[{}] {}", id, pid);
+        }
+        self.last_background = Some(pid);
+        self.jobs.borrow_mut().push((id.clone(), job));
+        self.job_changed(&id, pid, WaitStatus::StillAlive);
+        id
+    }
+
+    /// Call the hook whenever a job changes state, with the job's id, the
+    /// pid of its leader, and its new status. There's no hook by default.
+    ///
+    /// A job which has just started is `StillAlive`, and later it's
+    /// `Stopped`, `Continued`, or finally `Exited` or `Signaled`. The changes
+    /// are found when the jobs are checked on, by [`Runtime::reap_jobs`] or
+    /// `wait`.
+    ///
+    /// ```
+    /// use std::{cell::RefCell, rc::Rc};
+    /// use nix::sys::wait::WaitStatus;
+    /// use oursh::program::Runtime;
+    ///
+    /// let mut runtime = Runtime::builder().build().unwrap();
+    /// let changes = Rc::new(RefCell::new(vec![]));
+    /// let seen = changes.clone();
+    /// runtime.on_job(move |id, _, status| seen.borrow_mut().push((id.to_string(), status)));
+    /// runtime.run_str("sh -c 'exit 3' & wait %1").unwrap();
+    /// assert!(matches!(&changes.borrow()[..], [
+    ///     (a, WaitStatus::StillAlive),
+    ///     (b, WaitStatus::Exited(_, 3)),
+    /// ] if a == "1" && b == "1"));
+    /// ```
+    pub fn on_job(&mut self, hook: impl FnMut(&str, Pid, WaitStatus) + 'static) {
+        self.job_hook = Some(JobHook(Box::new(hook)));
+    }
+
+    /// Tell the hook given to [`Runtime::on_job`] that the job has changed
+    /// state.
+    pub(crate) fn job_changed(&mut self, id: &str, pid: Pid, status: WaitStatus) {
+        if let Some(JobHook(hook)) = &mut self.job_hook {
+            hook(id, pid, status);
+        }
+    }
+
+    /// Check on the jobs without blocking, like [`jobs::reap`], also telling
+    /// the hook given to [`Runtime::on_job`] about each change.
+    pub fn reap_jobs(&mut self) -> Vec<String> {
+        let jobs = self.jobs.clone();
+        let hook = &mut self.job_hook;
+        jobs::reap_with(&jobs, |id, pid, status| {
+            if let Some(JobHook(hook)) = hook {
+                hook(id, pid, status);
+            }
+        })
+    }
+
+    /// Check on the jobs without blocking, like [`jobs::update`], recording
+    /// each one's state and telling the hook given to [`Runtime::on_job`]
+    /// about each change. Finished jobs are kept.
+    pub fn update_jobs(&mut self) {
+        let jobs = self.jobs.clone();
+        let hook = &mut self.job_hook;
+        jobs::update(&jobs, |id, pid, status| {
+            if let Some(JobHook(hook)) = hook {
+                hook(id, pid, status);
+            }
+        });
+    }
+
+}
+
+/// The status and output of a program run by [`Runtime::run_captured`].
+#[derive(Debug)]
+pub struct Output {
+    pub status: WaitStatus,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+// Wait for the child to finish, returning `None` if the deadline passes
+// first.
+fn wait_until(child: Pid, deadline: Instant) -> Result<Option<WaitStatus>> {
+    loop {
+        match waitpid(child, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::StillAlive) if Instant::now() >= deadline => return Ok(None),
+            Ok(WaitStatus::StillAlive) => thread::sleep(TIMEOUT_POLL),
+            Ok(status) => return Ok(Some(status)),
+            Err(_) => return Err(Error::Runtime),
+        }
+    }
+}
+
+// Read everything from the file descriptor on another thread.
+fn collect(fd: RawFd) -> thread::JoinHandle<Vec<u8>> {
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    thread::spawn(move || {
+        let mut bytes = vec![];
+        let _ = file.read_to_end(&mut bytes);
+        bytes
+    })
+}
+
+/// Configures a new [`Runtime`].
+///
+/// By default the runtime is embedded, with the environment of this
+/// process, the name `oursh`, no positional parameters, and the current
+/// working directory.
+#[derive(Debug)]
+pub struct Builder {
+    args: Option<ArgvMap>,
+    variables: Variables,
+    name: String,
+    positionals: Vec<String>,
+    current_dir: Option<PathBuf>,
+    io: IO,
+    embedded: bool,
+    interactive: Option<bool>,
+    login: bool,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            args: None,
+            variables: Variables::from_env(),
+            name: NAME.into(),
+            positionals: vec![],
+            current_dir: None,
+            io: IO::default(),
+            embedded: true,
+            interactive: None,
+            login: false,
+        }
+    }
+}
+
+impl Builder {
+    /// Use the parsed command line arguments of the shell.
+    pub fn args(mut self, args: ArgvMap) -> Self {
+        self.args = Some(args);
+        self
+    }
+
+    /// Set an exported variable.
+    pub fn env(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.variables.set(name, value);
+        self.variables.export(name);
+        self
+    }
+
+    /// Set many exported variables.
+    pub fn envs<'a>(mut self, vars: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        for (name, value) in vars {
+            self = self.env(name, value);
+        }
+        self
+    }
+
+    /// Start without any variables, instead of this process's environment.
+    pub fn env_clear(mut self) -> Self {
+        self.variables = Variables::default();
+        self
+    }
+
+    /// Set the name of the shell or script, `$0`.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Set the positional parameters, `$1`, `$2`, ... `$n`.
+    pub fn positionals(mut self, positionals: Vec<String>) -> Self {
+        self.positionals = positionals;
+        self
+    }
+
+    /// Change the working directory, and `$PWD` along with it.
+    ///
+    /// The working directory belongs to the whole process, so this changes
+    /// it for the embedding program too.
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    /// Use the given file descriptors for stdin, stdout and stderr.
+    pub fn io(mut self, io: IO) -> Self {
+        self.io = io;
+        self
+    }
+
+    /// Should the shell avoid exiting the process, on by default.
+    pub fn embedded(mut self, embedded: bool) -> Self {
+        self.embedded = embedded;
+        self
+    }
+
+    /// Should the shell be interactive, regardless of how it was started.
+    ///
+    /// Otherwise it's interactive when started with `-i`, or when it reads
+    /// commands from a terminal, rather than from `-c` or a script, and
+    /// isn't embedded. This is decided once, so a subshell reading from a
+    /// pipe is still part of an interactive shell.
+    pub fn interactive(mut self, interactive: bool) -> Self {
+        self.interactive = Some(interactive);
+        self
+    }
+
+    /// Should the shell be a login shell, like one started with `--login`.
+    pub fn login(mut self, login: bool) -> Self {
+        self.login = login;
+        self
+    }
+
+    /// Create the runtime, failing if the working directory can't be
+    /// changed.
+    ///
+    /// This is the start of another shell, so `$SHLVL` is incremented and
+    /// exported, starting again from 1 if it isn't a number.
+    pub fn build(self) -> io::Result<Runtime> {
+        let mut variables = self.variables;
+        let level = variables.get("SHLVL").and_then(|l| l.trim().parse::<u32>().ok())
+                                          .map_or(1, |l| l.saturating_add(1));
+        variables.set("SHLVL", level.to_string());
+        variables.export("SHLVL");
+        if let Some(dir) = self.current_dir {
+            env::set_current_dir(&dir)?;
+            let pwd = env::current_dir()?;
+            variables.set("PWD", pwd.to_string_lossy());
+        }
+
+        let args = match self.args {
+            Some(args) => args,
+            None => Docopt::new(USAGE)
+                           .and_then(|d| d.argv(vec!["oursh"]).parse())
+                           .expect("valid default arguments"),
+        };
+
+        let embedded = self.embedded;
+        let interactive = self.interactive.unwrap_or_else(|| {
+            args.get_bool("-i") ||
+                (!embedded &&
+                 !args.get_bool("--command") &&
+                 args.get_str("<command_file>").is_empty() &&
+                 is_tty(&io::stdin()))
+        });
+
+        let login = self.login || args.get_bool("--login");
+
+        // Started with `--posix` the shell is strict from the beginning.
+        let options = Options { posix: args.get_bool("--posix"), ..Options::default() };
+
+        Ok(Runtime {
+            background: false,
+            io: self.io,
+            jobs: Rc::new(RefCell::new(vec![])),
+            args,
+            variables,
+            functions: HashMap::new(),
+            disabled_builtins: BTreeSet::new(),
+            traps: BTreeMap::new(),
+            trapped: false,
+            condition: false,
+            calls: 0,
+            name: self.name,
+            positionals: self.positionals,
+            pid: getpid(),
+            last_background: None,
+            last_status: 0,
+            substitution_status: None,
+            options,
+            pipestatus: vec![],
+            getopts_offset: (0, 0),
+            #[cfg(feature = "modern")]
+            last_argument: env::current_exe()
+                .map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            #[cfg(feature = "history")]
+            history: History::default(),
+            embedded,
+            interactive,
+            login,
+            job_hook: None,
+        })
+    }
+}
+
+/// The options of the shell, each of which is on or off, set with `set -o
+/// NAME` and unset with `set +o NAME`.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// Send `SIGHUP` to the jobs still in the job table when an interactive
+    /// login shell exits, `huponexit`.
+    pub huponexit: bool,
+    /// Don't let `>` overwrite an existing file, `noclobber` or `-C`. The
+    /// `>|` redirection still does.
+    pub noclobber: bool,
+    /// Make expanding a parameter which isn't set an error, `nounset` or
+    /// `-u`.
+    pub nounset: bool,
+    /// Warn when a command is found in the current directory because of an
+    /// empty entry in `$PATH`, like a leading or trailing `:`, `pathwarn`.
+    pub pathwarn: bool,
+    /// Make a pipeline's status that of the last of its commands to fail,
+    /// instead of always its last command, `pipefail`.
+    pub pipefail: bool,
+    /// Refuse to run the extensions of the language which aren't POSIX, like
+    /// shebang blocks, `((...))`, process substitution and `<<<`, `posix` or
+    /// `--posix` when the shell is started.
+    pub posix: bool,
+}
+
+impl Options {
+    /// The names of all the options.
+    pub const NAMES: &'static [&'static str] = &[
+        "huponexit", "noclobber", "nounset", "pathwarn", "pipefail", "posix",
+    ];
+
+    /// The single letter flags for options, like `set -C` for `set -o
+    /// noclobber`.
+    pub const FLAGS: &'static [(char, &'static str)] = &[('C', "noclobber"), ('u', "nounset")];
+
+    /// Is the named option on, or `None` if there's no such option.
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "huponexit" => Some(self.huponexit),
+            "noclobber" => Some(self.noclobber),
+            "nounset" => Some(self.nounset),
+            "pathwarn" => Some(self.pathwarn),
+            "pipefail" => Some(self.pipefail),
+            "posix" => Some(self.posix),
+            _ => None,
+        }
+    }
+
+    /// The named option, to turn it on or off.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "huponexit" => Some(&mut self.huponexit),
+            "noclobber" => Some(&mut self.noclobber),
+            "nounset" => Some(&mut self.nounset),
+            "pathwarn" => Some(&mut self.pathwarn),
+            "pipefail" => Some(&mut self.pipefail),
+            "posix" => Some(&mut self.posix),
+            _ => None,
+        }
+    }
+}
+
+/// The variables of the shell.
+///
+/// Only variables which have been exported are passed to the environment of
+/// the commands the shell runs, all others are local to the shell.
+///
+/// Readonly variables are only marked here, it's up to the commands which
+/// assign or unset variables for the user to refuse to change them.
+#[derive(Debug, Default, Clone)]
+pub struct Variables {
+    values: BTreeMap<String, String>,
+    exported: BTreeSet<String>,
+    readonly: BTreeSet<String>,
+}
+
+impl Variables {
+    /// Load the variables of this process's environment, each of which is
+    /// exported.
+    pub fn from_env() -> Self {
+        let mut variables = Variables::default();
+        for (name, value) in env::vars_os() {
+            if let (Some(name), Some(value)) = (name.to_str(), value.to_str()) {
+                variables.set(name, value);
+                variables.export(name);
+            }
+        }
+        variables
+    }
+
+    /// Return the value of the named variable, if it's set.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(|v| v.as_str())
+    }
+
+    /// Set the value of the named variable.
+    pub fn set(&mut self, name: &str, value: impl Into<String>) {
+        self.values.insert(name.into(), value.into());
+    }
+
+    /// Unset the named variable, removing it from the environment as well.
+    pub fn unset(&mut self, name: &str) {
+        self.values.remove(name);
+        self.exported.remove(name);
+    }
+
+    /// Mark the named variable to be passed to the environment of commands.
+    pub fn export(&mut self, name: &str) {
+        self.exported.insert(name.into());
+    }
+
+    /// Stop passing the named variable to the environment of commands.
+    pub fn unexport(&mut self, name: &str) {
+        self.exported.remove(name);
+    }
+
+    pub fn is_exported(&self, name: &str) -> bool {
+        self.exported.contains(name)
+    }
+
+    /// Mark the named variable as readonly, which can't be undone.
+    pub fn readonly(&mut self, name: &str) {
+        self.readonly.insert(name.into());
+    }
+
+    pub fn is_readonly(&self, name: &str) -> bool {
+        self.readonly.contains(name)
+    }
+
+    /// Iterate the names of the readonly variables in order, whether or not
+    /// they're set.
+    pub fn readonly_names(&self) -> impl Iterator<Item = &str> {
+        self.readonly.iter().map(|n| n.as_str())
+    }
+
+    /// Iterate all the set variables in order by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    /// Iterate the set variables which are exported in order by name.
+    pub fn exported(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.iter().filter(move |(n, _)| self.exported.contains(*n))
+    }
+
+    /// The environment for a new command, as a list of `NAME=value` strings.
+    pub fn environment(&self) -> Vec<CString> {
+        self.exported().filter_map(|(name, value)| {
+            CString::new(format!("{}={}", name, value)).ok()
+        }).collect()
+    }
 }
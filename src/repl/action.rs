@@ -1,18 +1,18 @@
 //! Actions to be bound to input methods.
-use std::io::{Write, Stdout};
+use std::{
+    io::{Write, Stdout},
+    mem,
+};
 
 use std::process::exit;
 use termion::{
     cursor::DetectCursorPos,
     raw::RawTerminal,
 };
-use docopt::ArgvMap;
-use crate::program::{Runtime, parse_and_run};
-use crate::process::{IO, Jobs};
-use crate::repl::prompt;
-
+use crate::program::Runtime;
+use crate::repl::{continues, notify, prompt, run};
 #[cfg(feature = "history")]
-use super::history::History;
+use crate::repl::record;
 
 #[cfg(feature = "completion")]
 use super::completion::*;
@@ -22,16 +22,29 @@ pub struct Action;
 
 pub struct ActionContext<'a> {
     pub stdout: &'a mut RawTerminal<Stdout>,
-    pub io: &'a mut IO,
-    pub jobs: &'a mut Jobs,
-    pub args: &'a mut ArgvMap,
+    pub runtime: &'a mut Runtime,
     // TODO: Remove this field.
     #[cfg(feature = "raw")]
     pub prompt_length: u16,
     #[cfg(feature = "raw")]
     pub text: &'a mut String,
-    #[cfg(feature = "history")]
-    pub history: &'a mut History,
+    /// The earlier lines of a command continued with a trailing `\`.
+    #[cfg(feature = "raw")]
+    pub pending: String,
+    /// The status of the last command, which the shell exits with at the
+    /// end of input.
+    pub status: i32,
+}
+
+#[cfg(feature = "raw")]
+impl ActionContext<'_> {
+    // Measure the prompt just displayed, since `$PS1` and `$PS2` may
+    // differ.
+    fn reset_prompt_length(&mut self) {
+        if let Ok((x, _)) = self.stdout.cursor_pos() {
+            self.prompt_length = x;
+        }
+    }
 }
 
 #[cfg(feature = "raw")]
@@ -41,28 +54,35 @@ impl Action {
         print!("\n\r");
         context.stdout.flush().unwrap();
 
+        // Wait for the rest of a continued command.
+        if continues(context.text) {
+            context.pending += context.text;
+            context.pending.push('\n');
+            context.text.clear();
+            prompt::ps2(&mut context.stdout, context.runtime);
+            context.reset_prompt_length();
+            return;
+        }
+        let text = mem::take(&mut context.pending) + context.text;
+
         // Run the command.
         context.stdout.suspend_raw_mode().unwrap();
-        let mut runtime = Runtime {
-            background: false,
-            io: context.io.clone(),
-            jobs: context.jobs,
-            args: context.args,
-            #[cfg(feature = "history")]
-            history: context.history,
-        };
-        if parse_and_run(context.text, &mut runtime).is_ok() {
-            #[cfg(feature = "history")]
-            context.history.add(&context.text, 1);
+        #[cfg(feature = "history")]
+        if let Some(text) = record(&text, context.runtime) {
+            context.status = run(&text, context.runtime).unwrap_or(context.status);
         }
+        #[cfg(not(feature = "history"))]
+        {
+            context.status = run(&text, context.runtime).unwrap_or(context.status);
+        }
+        notify(context.runtime);
         context.stdout.activate_raw_mode().unwrap();
 
         // Reset for the next program.
         context.text.clear();
-        #[cfg(feature = "history")]
-        context.history.reset_index();
 
-        prompt::ps1(&mut context.stdout);
+        prompt::ps1(&mut context.stdout, context.runtime);
+        context.reset_prompt_length();
     }
 
     pub fn insert(context: &mut ActionContext, c: char) {
@@ -94,28 +114,24 @@ impl Action {
         }
     }
 
+    // Commands are run outside of raw mode, where the terminal sends them
+    // the interrupt itself, so this is only ever the line being typed.
     pub fn interrupt(context: &mut ActionContext) {
-        // TODO: Send signal if we're running a program.
         context.text.clear();
+        context.pending.clear();
         print!("^C\n\r");
-        prompt::ps1(&mut context.stdout);
+        prompt::ps1(&mut context.stdout, context.runtime);
+        context.reset_prompt_length();
     }
 
     pub fn eof(context: &mut ActionContext) {
         if context.text.is_empty() {
             print!("exit\n\r");
             context.stdout.flush().unwrap();
+            context.stdout.suspend_raw_mode().unwrap();
 
-            // Save history to file in $HOME.
-            #[cfg(feature = "history")]
-            context.history.save();
-
-            // Manually drop the raw terminal.
-            // TODO: Needed?
-            // drop(context.stdout);
-
-            // Exit this wonderful world.
-            exit(0)
+            // Exit this wonderful world, the same way as the `exit` builtin.
+            exit(context.runtime.shutdown(context.status))
         }
     }
 
@@ -156,7 +172,7 @@ impl Action {
         print!("{}{}",
                termion::clear::All,
                termion::cursor::Goto(1, 1));
-        prompt::ps1(&mut context.stdout);
+        prompt::ps1(&mut context.stdout, context.runtime);
     }
 
     #[cfg(feature = "history")]
@@ -164,9 +180,9 @@ impl Action {
         print!("{}{}",
                termion::cursor::Left(1000),  // XXX
                termion::clear::CurrentLine);
-        context.prompt.display(&mut context.stdout);
+        prompt::ps1(&mut context.stdout, context.runtime);
 
-        if let Some(history_text) = context.history.get_up() {
+        if let Some(history_text) = context.runtime.history.get_up() {
             *context.text = history_text;
             print!("{}", context.text);
         }
@@ -178,9 +194,9 @@ impl Action {
         print!("{}{}",
                termion::cursor::Left(1000),  // XXX
                termion::clear::CurrentLine);
-        context.prompt.display(&mut context.stdout);
+        prompt::ps1(&mut context.stdout, context.runtime);
 
-        if let Some(history_text) = context.history.get_down() {
+        if let Some(history_text) = context.runtime.history.get_down() {
             *context.text = history_text;
             print!("{}", context.text);
             context.stdout.flush().unwrap();
@@ -191,7 +207,7 @@ impl Action {
 
     #[cfg(feature = "completion")]
     pub fn complete(context: &mut ActionContext) {
-        match complete(&context.text) {
+        match Completer::new(context.runtime).complete(context.text) {
             Completion::Partial(possibilities) => {
                 if possibilities.len() > 25 {
                     print!("\n\r");
@@ -202,7 +218,7 @@ impl Action {
                 } else {
                     print!("\n\r{}\n\r", possibilities.join("\t"));
                 }
-                prompt::ps1(&mut context.stdout);
+                prompt::ps1(&mut context.stdout, context.runtime);
                 print!("{}", context.text);
                 context.stdout.flush().unwrap();
             },
@@ -212,7 +228,7 @@ impl Action {
                        termion::cursor::Left(1000),  // XXX
                        termion::clear::CurrentLine);
                 context.stdout.flush().unwrap();
-                prompt::ps1(&mut context.stdout);
+                prompt::ps1(&mut context.stdout, context.runtime);
                 print!("{}", context.text);
                 context.stdout.flush().unwrap();
             },
@@ -4,24 +4,30 @@
 //! example taken from the current REPL.
 //!
 //! ```
-//! use oursh::repl::completion::complete;
+//! use oursh::program::Variables;
+//! use oursh::repl::completion::Completer;
 //!
-//! // String holding the user's input.
-//! let mut text = "car".to_string();
+//! let mut variables = Variables::default();
+//! variables.set("EDITOR", "vi");
+//! let completer = Completer {
+//!     variables: &variables,
+//!     commands: vec!["cd".into(), "command".into()],
+//! };
 //!
-//! // Perform the completion, on `\t` perhaps.
-//! text = complete(&text).first();
-//!
-//! // The user's input is updated to the complete executable.
-//! assert_eq!("cargo", &text);
+//! // The user's input is updated to the complete value.
+//! assert_eq!("echo $EDITOR", completer.complete("echo $ED").first());
+//! assert_eq!("cd ", completer.complete("cd").first());
 //! ```
 
 use std::{
-    env,
     fs,
-    cmp::Ordering::Equal,
+    path::Path,
     os::unix::fs::PermissionsExt,
 };
+use crate::{
+    process::path_dirs,
+    program::{Runtime, Variables, posix::builtin},
+};
 
 /// The result of a query for text completion.
 ///
@@ -70,119 +76,321 @@ impl Completion {
             Completion::Complete(ref t) => vec![t.clone()],
         }
     }
-
-    // fn guess
 }
 
-/// Return a completed (valid) program text from the partial string
-/// given.
-///
-/// ### Examples
+/// Completes the word at the end of the user's text.
 ///
-/// ```
-/// use oursh::repl::completion::complete;
+/// - In command position the word is completed against `commands` and the
+///   executables in `$PATH`.
+/// - After a `$` the word is completed against the names of `variables`.
+/// - Otherwise the word is completed as a path, with a trailing `/` for
+///   directories.
 ///
-/// assert_eq!("pwd", complete("pw").first());
-/// ```
-pub fn complete(text: &str) -> Completion {
-    match executable_completions(text) {
-        c @ Completion::Partial(_) |
-        c @ Completion::Complete(_) => c,
-        Completion::None => path_complete(text),
-    }
+/// A complete result is the whole text with the word completed, while a
+/// partial result is the list of possible words.
+#[derive(Debug)]
+pub struct Completer<'a> {
+    /// The variables to complete, including `PATH`.
+    pub variables: &'a Variables,
+    /// The names of commands other than executables, like builtins and
+    /// functions.
+    pub commands: Vec<String>,
 }
 
-/// Return a list of the matches from the given partial program text.
-///
-/// ### Examples
-///
-/// ```
-/// use oursh::repl::completion::executable_completions;
-///
-/// assert!(executable_completions("ru").possibilities()
-///     .contains(&"rustc".into()));
-/// assert!(executable_completions("ru").possibilities()
-///     .contains(&"ruby".into()));
-/// ```
-pub fn executable_completions(text: &str) -> Completion {
-    match env::var_os("PATH") {
-        Some(paths) => {
-            let mut matches = vec![];
-            for dir in env::split_paths(&paths) {
-                if let Ok(executables) = fs::read_dir(dir) {
-                    let paths = executables.filter_map(|e| {
-                        match e { Ok(p) => Some(p.path()), _ => None }
-                    });
-
-                    for path in paths {
-                        if let Some(filename) = path.file_name() {
-                            let filename = filename.to_string_lossy();
-                            if let Ok(metadata) = fs::metadata(&path) {
-                                if (metadata.permissions()
-                                            .mode() & 0o111 != 0)
-                                    && filename.starts_with(text)
-                                {
-                                    matches.push(filename.into());
-                                }
-                            }
-                        }
+impl<'a> Completer<'a> {
+    /// Create a completer for the builtins, functions and variables of the
+    /// runtime.
+    pub fn new(runtime: &'a Runtime) -> Self {
+        let mut commands = builtin::names().map(String::from).collect::<Vec<_>>();
+        commands.extend(runtime.functions.keys().cloned());
+        Completer {
+            variables: &runtime.variables,
+            commands,
+        }
+    }
+
+    /// Return a completed text from the partial text given.
+    pub fn complete(&self, text: &str) -> Completion {
+        let word = Word::last(text);
+        let prefix = &text[..word.start];
+        let raw = &text[word.start..];
+
+        if let Some(dollar) = word.dollar {
+            let name = &text[dollar + 1..];
+            let (brace, name) = match name.strip_prefix('{') {
+                Some(name) => (true, name),
+                None => (false, name),
+            };
+            let matches = self.variables.iter()
+                                        .map(|(n, _)| n)
+                                        .filter(|n| n.starts_with(name))
+                                        .map(String::from)
+                                        .collect();
+            return finish(matches, |m| {
+                let end = if brace { "}" } else { "" };
+                format!("{}{}{}", &text[..text.len() - name.len()], m, end)
+            });
+        }
+
+        if word.command && word.quote.is_none() && !word.value.contains('/') {
+            let mut matches = self.commands.iter()
+                                           .filter(|c| c.starts_with(&word.value))
+                                           .cloned()
+                                           .collect::<Vec<_>>();
+            matches.extend(self.executables(&word.value));
+            matches.sort();
+            matches.dedup();
+            return finish(matches, |m| {
+                format!("{}{} ", prefix, quote(m, None))
+            });
+        }
+
+        let (dir, file) = match word.value.rfind('/') {
+            Some(i) => word.value.split_at(i + 1),
+            None => ("", word.value.as_str()),
+        };
+        let search = match dir {
+            "" => ".".to_string(),
+            dir if dir.starts_with("~/") => match self.variables.get("HOME") {
+                Some(home) => format!("{}{}", home, &dir[1..]),
+                None => dir.into(),
+            },
+            dir => dir.into(),
+        };
+        let mut matches = vec![];
+        if let Ok(entries) = fs::read_dir(&search) {
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if !name.starts_with(file) ||
+                    (name.starts_with('.') && !file.starts_with('.'))
+                {
+                    continue;
+                }
+                if entry.path().is_dir() {
+                    matches.push(format!("{}/", name));
+                } else {
+                    matches.push(name);
+                }
+            }
+        }
+        matches.sort();
+        finish(matches, |m| {
+            // Keep the directory as the user wrote it.
+            let dir = &raw[..raw.len() - raw_suffix_len(raw, file)];
+            let end = if m.ends_with('/') { "" } else { " " };
+            format!("{}{}{}{}", prefix, dir, quote(m, word.quote), end)
+        })
+    }
+
+    // The executables in `$PATH` starting with the given text.
+    fn executables(&self, text: &str) -> Vec<String> {
+        let mut matches = vec![];
+        let path = match self.variables.get("PATH") {
+            Some(path) => path,
+            None => return matches,
+        };
+        for dir in path_dirs(path.as_ref()) {
+            if let Ok(entries) = fs::read_dir(dir) {
+                for entry in entries.flatten() {
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    if name.starts_with(text) && is_executable(&entry.path()) {
+                        matches.push(name);
                     }
                 }
             }
+        }
+        matches
+    }
+}
 
-            match matches.len() {
-                0 => Completion::None,
-                1 => Completion::Complete(matches.remove(0)),
-                _ => {
-                    matches.sort_by(|a, b| {
-                        match a.len().cmp(&b.len()) {
-                            Equal => b.cmp(&a),
-                            o => o
+// The word being completed at the end of the text.
+#[derive(Debug)]
+struct Word {
+    // Where the word starts in the text.
+    start: usize,
+    // The word with quotes and escapes removed.
+    value: String,
+    // The open quote, if the word ends inside one.
+    quote: Option<char>,
+    // Where an unquoted, or double quoted `$` starts a parameter which
+    // runs to the end of the word.
+    dollar: Option<usize>,
+    // Is the word the name of a command.
+    command: bool,
+}
+
+impl Word {
+    fn last(text: &str) -> Self {
+        let mut word = Word {
+            start: 0,
+            value: String::new(),
+            quote: None,
+            dollar: None,
+            command: true,
+        };
+        let mut escaped = false;
+        for (i, c) in text.char_indices() {
+            if escaped {
+                escaped = false;
+                word.value.push(c);
+                continue;
+            }
+            match (word.quote, c) {
+                (Some('\''), '\'') | (Some('"'), '"') => word.quote = None,
+                (Some('\''), c) => word.value.push(c),
+                (_, '\\') => escaped = true,
+                (None, '\'') | (None, '"') => word.quote = Some(c),
+                (_, '$') => {
+                    word.dollar = Some(i);
+                    word.value.push(c);
+                },
+                (None, c) if c.is_whitespace() || ";|&(){}!".contains(c) => {
+                    // The next word is a command after a separator, or
+                    // when this word was an assignment.
+                    let separator = !c.is_whitespace();
+                    let assignment = word.value.contains('=') &&
+                                     word.command &&
+                                     !word.value.starts_with('=');
+                    word.command = separator ||
+                        (word.command && (word.value.is_empty() || assignment));
+                    word.start = i + c.len_utf8();
+                    word.value.clear();
+                    word.dollar = None;
+                },
+                (_, c) => {
+                    if let Some(d) = word.dollar {
+                        if !(c == '{' && i == d + 1) &&
+                            !(c == '_' || c.is_ascii_alphanumeric())
+                        {
+                            word.dollar = None;
                         }
-                    });
-                    Completion::Partial(matches)
-                }
+                    }
+                    word.value.push(c);
+                },
             }
+        }
+        word
+    }
+}
 
+// Return the single match completed, or the list of matches.
+fn finish(mut matches: Vec<String>, complete: impl Fn(&str) -> String) -> Completion {
+    match matches.len() {
+        0 => Completion::None,
+        1 => Completion::Complete(complete(&matches.remove(0))),
+        _ => Completion::Partial(matches),
+    }
+}
+
+// The length of the raw text at the end of `raw` which was unquoted into
+// `value`.
+fn raw_suffix_len(raw: &str, value: &str) -> usize {
+    let mut remaining = value.chars().rev().peekable();
+    let mut len = 0;
+    for c in raw.chars().rev() {
+        if remaining.peek().is_none() {
+            break;
+        }
+        if remaining.peek() == Some(&c) {
+            remaining.next();
         }
-        None => panic!("PATH is undefined"),
+        len += c.len_utf8();
     }
+    len
 }
 
-/// Complete a path at the end of the given string.
-///
-/// ### Examples
-///
-/// ```
-/// use oursh::repl::completion::path_complete;
-///
-/// assert_eq!("/usr/bin/", path_complete("/usr/b").first());
-/// assert_eq!("ls /home/", path_complete("ls /hom").first());
-/// ```
-pub fn path_complete(text: &str) -> Completion {
-    match text {
-        "/hom" => Completion::Complete("/home/".into()),
-        "/usr/b" => Completion::Complete("/usr/bin/".into()),
-        "ls /hom" => Completion::Complete("ls /home/".into()),
-        _ => Completion::None,
+// Quote the completed text to match how the word was started.
+fn quote(text: &str, quote: Option<char>) -> String {
+    match quote {
+        Some(_) => text.into(),
+        None => {
+            let mut quoted = String::new();
+            for c in text.chars() {
+                if c.is_whitespace() || "\\'\"$`;|&(){}<>*?!#~".contains(c) {
+                    quoted.push('\\');
+                }
+                quoted.push(c);
+            }
+            quoted
+        },
     }
 }
 
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).map(|m| {
+        m.is_file() && m.permissions().mode() & 0o111 != 0
+    }).unwrap_or(false)
+}
+
 #[cfg(test)]
 mod tests {
+    use std::{env, fs, process};
     use super::*;
 
-    // TODO: Mock PATH to make deterministic.
+    // A directory of files, and a completer with it as the `PATH`.
+    fn fixture(name: &str) -> (String, Variables) {
+        let dir = env::temp_dir().join(format!("oursh-complete-{}-{}", name, process::id()));
+        fs::create_dir_all(dir.join("some dir")).unwrap();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("cargo"), "").unwrap();
+        fs::write(dir.join("script"), "").unwrap();
+        let mut perms = fs::metadata(dir.join("cargo")).unwrap().permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(dir.join("cargo"), perms).unwrap();
+
+        let dir = dir.to_string_lossy().into_owned();
+        let mut variables = Variables::default();
+        variables.set("PATH", dir.as_str());
+        variables.set("HOME", "/home/user");
+        variables.set("HOSTNAME", "box");
+        (dir, variables)
+    }
+
+    fn completer(variables: &Variables) -> Completer<'_> {
+        Completer {
+            variables,
+            commands: vec!["cd".into(), "command".into()],
+        }
+    }
+
+    #[test]
+    fn commands() {
+        let (dir, variables) = fixture("commands");
+        let completer = completer(&variables);
+        assert_eq!("cargo ", completer.complete("car").first());
+        assert_eq!("true; cargo ", completer.complete("true; car").first());
+        assert_eq!("X=1 cargo ", completer.complete("X=1 car").first());
+        assert_eq!(vec!["cd", "command"], completer.complete("c").possibilities()[1..]);
+        // The script isn't executable.
+        assert!(!completer.complete("scr").is_complete());
+        fs::remove_dir_all(dir).unwrap();
+    }
 
     #[test]
-    fn lexicographical_order() {
-        assert_eq!("cargo", complete("car").first());
+    fn variables() {
+        let (dir, variables) = fixture("variables");
+        let completer = completer(&variables);
+        assert_eq!(vec!["HOME", "HOSTNAME"], completer.complete("echo $HO").possibilities());
+        assert_eq!("echo $HOSTNAME", completer.complete("echo $HOS").first());
+        assert_eq!("echo \"${HOSTNAME}", completer.complete("echo \"${HOS").first());
+        assert!(!completer.complete("echo '$HOS").is_complete());
+        fs::remove_dir_all(dir).unwrap();
     }
 
     #[test]
     fn paths() {
-        assert_eq!("/home/", complete("/hom").first());
-        assert_eq!("/usr/bin/", complete("/usr/b").first());
-        assert_eq!("ls /home/", complete("ls /hom").first());
+        let (dir, variables) = fixture("paths");
+        let completer = completer(&variables);
+        assert_eq!(format!("ls {}/src/", dir),
+                   completer.complete(&format!("ls {}/sr", dir)).first());
+        assert_eq!(format!("ls {}/script ", dir),
+                   completer.complete(&format!("ls {}/scr", dir)).first());
+        assert_eq!(format!("ls {}/some\\ dir/", dir),
+                   completer.complete(&format!("ls {}/so", dir)).first());
+        assert_eq!(format!("ls '{}/some dir/", dir),
+                   completer.complete(&format!("ls '{}/some d", dir)).first());
+        assert_eq!(format!("{}/cargo ", dir),
+                   completer.complete(&format!("{}/car", dir)).first());
+        fs::remove_dir_all(dir).unwrap();
     }
 }
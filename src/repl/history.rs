@@ -1,119 +1,371 @@
 //! Keeps a record of previous commands, used for completion and archeology.
 use std::{
-    env,
-    io::prelude::*,
-    fs::File,
-    path::Path,
+    io::{self, prelude::*},
+    fs::{self, OpenOptions},
+    path::{Path, PathBuf},
 };
+use crate::program::Variables;
+
+/// The number of commands kept when `$HISTSIZE` isn't set.
+const DEFAULT_SIZE: usize = 500;
 
 /// The history of a user's provided commands.
-#[derive(Debug)]
-pub struct History(pub Option<usize>, pub Vec<(String, usize)>);
+///
+/// The number of commands kept is capped by `$HISTSIZE`, and the number of
+/// lines kept in the history file by `$HISTFILESIZE` (which defaults to
+/// `$HISTSIZE`). The colon separated values of `$HISTCONTROL` decide which
+/// commands are recorded.
+///
+/// - `ignorespace` skips commands starting with a space.
+/// - `ignoredups` skips a command which is the same as the previous one.
+/// - `ignoreboth` is both of the above.
+/// - `erasedups` removes all the previous copies of a command.
+#[derive(Debug, Default)]
+pub struct History {
+    // The position while moving up and down through the history.
+    index: Option<usize>,
+    // The commands, oldest first.
+    entries: Vec<String>,
+    // The number of leading entries already in the history file.
+    saved: usize,
+}
 
 impl History {
-    pub fn reset_index(&mut self) {
-        self.0 = None;
+    /// The file the history is saved to, `$HISTFILE`, or `~/.oursh_history`
+    /// when it's not set.
+    pub fn path(variables: &Variables) -> Option<PathBuf> {
+        match variables.get("HISTFILE") {
+            Some("") => None,
+            Some(path) => Some(path.into()),
+            None => variables.get("HOME").map(|home| {
+                Path::new(home).join(".oursh_history")
+            }),
+        }
+    }
+
+    /// Load the history from the history file, if there is one.
+    pub fn load(variables: &Variables) -> Self {
+        let mut history = History::default();
+        if let Some(contents) = Self::path(variables)
+                                    .and_then(|p| fs::read_to_string(p).ok())
+        {
+            history.entries = contents.lines()
+                                      .filter(|l| !l.is_empty())
+                                      .map(String::from)
+                                      .collect();
+            history.saved = history.entries.len();
+            history.truncate(size(variables));
+        }
+        history
     }
 
-    pub fn add(&mut self, text: &str, count: usize) {
-        if text.is_empty() {
+    /// Append the commands added since the last save to the history file,
+    /// so concurrent shells don't overwrite each other's history.
+    pub fn save(&mut self, variables: &Variables) -> io::Result<()> {
+        let path = match Self::path(variables) {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        for text in &self.entries[self.saved..] {
+            writeln!(file, "{}", text)?;
+        }
+        self.saved = self.entries.len();
+
+        // Keep only the newest lines of the file.
+        let limit = variables.get("HISTFILESIZE")
+                             .and_then(|s| s.parse().ok())
+                             .unwrap_or_else(|| size(variables));
+        let contents = fs::read_to_string(&path)?;
+        let lines = contents.lines().collect::<Vec<_>>();
+        if lines.len() > limit {
+            let mut contents = lines[lines.len() - limit..].join("\n");
+            contents.push('\n');
+            fs::write(&path, contents)?;
+        }
+        Ok(())
+    }
+
+    /// Record a command, as controlled by `$HISTCONTROL` and `$HISTSIZE`.
+    pub fn add(&mut self, text: &str, variables: &Variables) {
+        let text = text.trim_end_matches('\n');
+        if text.trim().is_empty() {
             return;
         }
 
-        // HACK: There's got to be a cleaner way.
-        let mut index = 0;
-        if self.1.iter().enumerate().find(|(i, (t, _))| {
-            index = *i;
-            text == t
-        }).is_some() {
-            self.1[index].1 += count;
-            let text = self.1.remove(index);
-            self.1.insert(0, text);
-            debug!("updating history item: {:?}", self.1[0]);
-        } else {
-            self.1.insert(0, (text.to_owned(), count));
-            debug!("adding history item: {:?}", self.1[0]);
+        let control = variables.get("HISTCONTROL").unwrap_or("");
+        let control = control.split(':').collect::<Vec<_>>();
+        let both = control.contains(&"ignoreboth");
+        if (both || control.contains(&"ignorespace")) && text.starts_with(' ') {
+            return;
+        }
+        if (both || control.contains(&"ignoredups")) &&
+            self.entries.last().map(|t| t == text).unwrap_or(false)
+        {
+            return;
+        }
+        if control.contains(&"erasedups") {
+            let saved = self.saved;
+            let mut index = 0;
+            let mut erased = 0;
+            self.entries.retain(|t| {
+                let keep = t != text;
+                if !keep && index < saved {
+                    erased += 1;
+                }
+                index += 1;
+                keep
+            });
+            self.saved -= erased;
+        }
+
+        debug!("adding history item: {:?}", text);
+        self.entries.push(text.into());
+        self.truncate(size(variables));
+    }
+
+    /// Expand the history references in the text.
+    ///
+    /// - `!!` is the previous command.
+    /// - `!n` is the command numbered `n`, and `!-n` is the command `n`
+    ///   commands back.
+    /// - `!string` is the most recent command starting with `string`.
+    /// - `!$` is the last argument of the previous command.
+    ///
+    /// A `!` which is single quoted, escaped with a `\`, or followed by a
+    /// space, `=` or `(` is left alone. An error for the first reference
+    /// which isn't in the history is returned.
+    pub fn expand(&self, text: &str) -> Result<String, String> {
+        let mut result = String::new();
+        let (mut single, mut double) = (false, false);
+        let mut i = 0;
+        while let Some(c) = text[i..].chars().next() {
+            i += c.len_utf8();
+            match c {
+                '\'' if !double => single = !single,
+                '"' if !single => double = !double,
+                '\\' if !single => {
+                    if let Some(next) = text[i..].chars().next() {
+                        result.push(c);
+                        result.push(next);
+                        i += next.len_utf8();
+                        continue;
+                    }
+                },
+                '!' if !single => {
+                    let rest = &text[i..];
+                    let len = match rest.chars().next() {
+                        Some('!') | Some('$') => 1,
+                        Some(c) if c.is_ascii_digit() || c == '-' => {
+                            1 + rest[1..].find(|c: char| !c.is_ascii_digit())
+                                         .unwrap_or(rest.len() - 1)
+                        },
+                        Some(c) if !c.is_whitespace() && !"=(\"".contains(c) => {
+                            rest.find(|c: char| c.is_whitespace() || ";|&".contains(c))
+                                .unwrap_or(rest.len())
+                        },
+                        _ => 0,
+                    };
+                    if len > 0 {
+                        let event = &rest[..len];
+                        let previous = self.entries.last();
+                        let found = match event {
+                            "!" => previous.cloned(),
+                            "$" => previous.and_then(|p| {
+                                p.split_whitespace().last().map(String::from)
+                            }),
+                            _ => match event.parse::<isize>() {
+                                Ok(n) if n > 0 => self.entries.get(n as usize - 1).cloned(),
+                                Ok(n) if n < 0 => {
+                                    let back = n.unsigned_abs();
+                                    self.entries.len().checked_sub(back)
+                                                      .and_then(|i| self.entries.get(i))
+                                                      .cloned()
+                                },
+                                Ok(_) => None,
+                                Err(_) => self.entries.iter().rev().find(|e| {
+                                    e.starts_with(event)
+                                }).cloned(),
+                            },
+                        };
+                        match found {
+                            Some(found) => result += &found,
+                            None => return Err(format!("!{}: event not found", event)),
+                        }
+                        i += len;
+                        continue;
+                    }
+                },
+                _ => {},
+            }
+            result.push(c);
         }
+        Ok(result)
+    }
+
+    /// The recorded commands, oldest first.
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Forget all the recorded commands.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.saved = 0;
+        self.index = None;
+    }
+
+    pub fn reset_index(&mut self) {
+        self.index = None;
     }
 
     pub fn get_up(&mut self) -> Option<String> {
-        let text_len = self.1.len();
+        let text_len = self.entries.len();
         if text_len > 0 {
-            match self.0 {
+            match self.index {
                 Some(i) => {
-                    self.0 = Some(i.saturating_add(1)
-                                   .min(text_len - 1));
+                    self.index = Some(i.saturating_add(1)
+                                       .min(text_len - 1));
                 },
-                None => self.0 = Some(0),
+                None => self.index = Some(0),
             }
         } else {
-            self.0 = None;
+            self.index = None;
         }
 
-        match self.0 {
-            Some(i) => Some(self.1[i].0.clone()),
-            None => None,
-        }
+        self.index.map(|i| self.entries[text_len - 1 - i].clone())
     }
 
     pub fn get_down(&mut self) -> Option<String> {
-        match self.0 {
-            Some(i) if i == 0 => self.0 = None,
-            Some(i) => self.0 = Some(i.saturating_sub(1)),
+        match self.index {
+            Some(0) => self.index = None,
+            Some(i) => self.index = Some(i.saturating_sub(1)),
             None => {},
         };
 
-        match self.0 {
-            Some(i) => Some(self.1[i].0.clone()),
-            None => None,
+        let text_len = self.entries.len();
+        self.index.map(|i| self.entries[text_len - 1 - i].clone())
+    }
+
+    // Drop the oldest commands, keeping at most `size`.
+    fn truncate(&mut self, size: usize) {
+        if self.entries.len() > size {
+            let drop = self.entries.len() - size;
+            self.entries.drain(..drop);
+            self.saved = self.saved.saturating_sub(drop);
         }
     }
+}
 
-    pub fn load() -> Self {
-        let mut history = History(None, vec![]);
-        let home = env::var("HOME").expect("HOME variable not set.");
-        let history_path = format!("{}/.oursh_history", home);
-        if Path::new(&history_path).exists() {
-            let mut f = File::open(&history_path)
-                .expect("error cannot find history");
-            let mut contents = String::new();
-            f.read_to_string(&mut contents)
-                .expect("error reading history");
-            // TODO: We really need something like serde or serde-json
-            //       for the pair if we want to have historical run counts.
-            // let hist = contents.split("\n").map(|s| {
-            //     String::from(s).split(" ").map(|s| {
-            //         println!("{:?}", s);
-            //     })
-            // }).collect::<Vec<String, usize>>();
-            let hist = contents.split("\n").map(|s| {
-                (String::from(s), 0)
-            });
+// The number of commands to keep, `$HISTSIZE`.
+fn size(variables: &Variables) -> usize {
+    variables.get("HISTSIZE")
+             .and_then(|s| s.parse().ok())
+             .unwrap_or(DEFAULT_SIZE)
+}
 
-            // Add each entry to the history in order.
-            for (text, index) in hist {
-                history.add(&text, index);
-            }
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+    use super::*;
 
-            // Reverse the order so users get the most recent commands first.
-            history.1 = history.1.into_iter().rev().collect();
+    fn vars(pairs: &[(&str, &str)]) -> Variables {
+        let mut variables = Variables::default();
+        for (name, value) in pairs {
+            variables.set(name, *value);
         }
+        variables
+    }
 
-        history
+    #[test]
+    fn add() {
+        let mut history = History::default();
+        let variables = vars(&[]);
+        history.add("ls", &variables);
+        history.add("ls", &variables);
+        history.add("", &variables);
+        assert_eq!(history.entries(), ["ls", "ls"]);
     }
 
-    pub fn save(&self) -> Result<(), ()> {
-        let home = env::var("HOME").expect("HOME variable not set.");
-        let history_path = format!("{}/.oursh_history", home);
-        let mut f = File::create(&history_path)
-            .expect("error cannot find history");
-        for (text, _) in self.1.iter() {
-            f.write_all(text.as_bytes())
-                .expect("error writing history");
-            f.write_all(b"\n")
-                .expect("error writing history");
-        }
+    #[test]
+    fn histcontrol() {
+        let mut history = History::default();
+        let variables = vars(&[("HISTCONTROL", "ignoreboth")]);
+        history.add("ls", &variables);
+        history.add("ls", &variables);
+        history.add(" secret", &variables);
+        history.add("date", &variables);
+        history.add("ls", &variables);
+        assert_eq!(history.entries(), ["ls", "date", "ls"]);
 
-        Ok(())
+        let mut history = History::default();
+        let variables = vars(&[("HISTCONTROL", "erasedups")]);
+        history.add("ls", &variables);
+        history.add("date", &variables);
+        history.add("ls", &variables);
+        assert_eq!(history.entries(), ["date", "ls"]);
+    }
+
+    #[test]
+    fn histsize() {
+        let mut history = History::default();
+        let variables = vars(&[("HISTSIZE", "2")]);
+        history.add("a", &variables);
+        history.add("b", &variables);
+        history.add("c", &variables);
+        assert_eq!(history.entries(), ["b", "c"]);
+    }
+
+    #[test]
+    fn up_and_down() {
+        let mut history = History::default();
+        let variables = vars(&[]);
+        history.add("a", &variables);
+        history.add("b", &variables);
+        assert_eq!(history.get_up(), Some("b".into()));
+        assert_eq!(history.get_up(), Some("a".into()));
+        assert_eq!(history.get_up(), Some("a".into()));
+        assert_eq!(history.get_down(), Some("b".into()));
+        assert_eq!(history.get_down(), None);
+    }
+
+    #[test]
+    fn expand() {
+        let mut history = History::default();
+        let variables = vars(&[]);
+        history.add("echo a b", &variables);
+        history.add("ls -la", &variables);
+        assert_eq!(Ok("ls -la | wc".into()), history.expand("!! | wc"));
+        assert_eq!(Ok("echo a b".into()), history.expand("!1"));
+        assert_eq!(Ok("echo a b".into()), history.expand("!-2"));
+        assert_eq!(Ok("echo a b; true".into()), history.expand("!ec; true"));
+        assert_eq!(Ok("cat -la".into()), history.expand("cat !$"));
+        assert_eq!(Ok("echo '!!' \\!! ! !=".into()), history.expand("echo '!!' \\!! ! !="));
+        assert_eq!(Ok("echo \"ls -la\"".into()), history.expand("echo \"!!\""));
+        assert_eq!(Err("!9: event not found".into()), history.expand("!9"));
+        assert_eq!(Err("!nope: event not found".into()), history.expand("!nope"));
+    }
+
+    #[test]
+    fn save_appends() {
+        let path = env::temp_dir().join(format!("oursh_history-{}", process::id()));
+        let path = path.to_string_lossy();
+        let variables = vars(&[("HISTFILE", &path), ("HISTFILESIZE", "3")]);
+
+        let mut first = History::load(&variables);
+        let mut second = History::load(&variables);
+        first.add("a", &variables);
+        second.add("b", &variables);
+        first.save(&variables).unwrap();
+        second.save(&variables).unwrap();
+        assert_eq!(fs::read_to_string(&*path).unwrap(), "a\nb\n");
+
+        first.add("c", &variables);
+        first.add("d", &variables);
+        first.save(&variables).unwrap();
+        assert_eq!(fs::read_to_string(&*path).unwrap(), "b\nc\nd\n");
+        assert_eq!(History::load(&variables).entries(), ["b", "c", "d"]);
+        fs::remove_file(&*path).unwrap();
     }
 }
@@ -3,11 +3,17 @@
 //! There will be *absolutely no* blocking STDIN/OUT/ERR on things like tab
 //! completion or other potentially slow, or user defined behavior.
 
-use std::io::{Stdin, Stdout};
-use docopt::ArgvMap;
-use nix::sys::wait::WaitStatus;
+use std::{
+    io::{Stdin, Stdout, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use nix::sys::{
+    termios::{tcgetattr, tcsetattr, SetArg},
+    wait::WaitStatus,
+};
 use nix::unistd::Pid;
-use crate::process::{Jobs, IO};
+use crate::program::{Runtime, Error, Program, parse_and_run, parse_primary};
+use crate::process::exit_code;
 
 #[cfg(feature = "raw")]
 use {
@@ -19,13 +25,7 @@ use {
 };
 
 #[cfg(not(feature = "raw"))]
-use {
-    std::io::BufRead,
-    crate::program::{parse_and_run, Runtime},
-};
-
-#[cfg(feature = "history")]
-use self::history::History;
+use std::{io::BufRead, mem};
 
 /// Start a REPL over the strings the user provides.
 ///
@@ -45,29 +45,28 @@ use self::history::History;
 /// ```
 // TODO: Partial syntax, completion.
 #[allow(unused_mut)]
-pub fn start(mut stdin: Stdin, mut stdout: Stdout, io: &mut IO, jobs: &mut Jobs, args: &mut ArgvMap)
+pub fn start(mut stdin: Stdin, mut stdout: Stdout, runtime: &mut Runtime)
     -> crate::program::Result<WaitStatus>
 {
-    // Load history from file in $HOME.
-    #[cfg(feature = "history")]
-    let mut history = History::load();
-
     #[cfg(feature = "raw")]
-    raw_loop(stdin, stdout, io, jobs, args);
+    let status = raw_loop(stdin, stdout, runtime);
     #[cfg(not(feature = "raw"))]
-    buffered_loop(stdin, stdout, io, jobs, args);
+    let status = buffered_loop(stdin, stdout, runtime);
 
-    Ok(WaitStatus::Exited(Pid::this(), 0))
+    Ok(WaitStatus::Exited(Pid::this(), status))
 }
 
+// Read and run commands until the end of input, returning the status of
+// the last.
 #[cfg(feature = "raw")]
-fn raw_loop(stdin: Stdin, stdout: Stdout, io: &mut IO, jobs: &mut Jobs, args: &mut ArgvMap) {
+fn raw_loop(stdin: Stdin, stdout: Stdout, runtime: &mut Runtime) -> i32 {
     // Convert the tty's stdout into raw mode.
     let mut stdout = stdout.into_raw_mode()
         .expect("error opening raw mode");
 
     // Display the inital prompt.
-    prompt::ps1(&mut stdout);
+    notify(runtime);
+    prompt::ps1(&mut stdout, runtime);
 
     // XXX: Hack to get the prompt length.
     let prompt_length = stdout.cursor_pos().unwrap().0;
@@ -78,13 +77,11 @@ fn raw_loop(stdin: Stdin, stdout: Stdout, io: &mut IO, jobs: &mut Jobs, args: &m
     // Create an context to pass to the actions.
     let mut context = ActionContext {
         stdout: &mut stdout,
-        io: io,
-        jobs: jobs,
-        args: args,
-        prompt_length: prompt_length,
+        runtime,
+        prompt_length,
         text: &mut text,
-        #[cfg(feature = "history")]
-        history: &mut history,
+        pending: String::new(),
+        status: 0,
     };
     // Iterate the keys as a user presses them.
     // TODO #5: Mouse?
@@ -109,15 +106,32 @@ fn raw_loop(stdin: Stdin, stdout: Stdout, io: &mut IO, jobs: &mut Jobs, args: &m
             _ => {}
         }
     }
+    context.status
 }
 
 #[cfg(not(feature = "raw"))]
-fn buffered_loop(stdin: Stdin, mut stdout: Stdout, io: &mut IO, jobs: &mut Jobs, args: &mut ArgvMap) {
+fn buffered_loop(stdin: Stdin, mut stdout: Stdout, runtime: &mut Runtime) -> i32 {
+    let mut status = 0;
     // Display the inital prompt.
-    prompt::ps1(&mut stdout);
+    notify(runtime);
+    prompt::ps1(&mut stdout, runtime);
 
+    // The earlier lines of a command continued with a trailing `\`.
+    let mut pending = String::new();
     for line in stdin.lock().lines() {
         let line = line.unwrap();  // TODO: Exit codes
+        // The terminal has already thrown away the interrupted line, but not
+        // the lines it continued.
+        if INTERRUPTED.swap(false, Ordering::SeqCst) {
+            pending.clear();
+        }
+        if continues(&line) {
+            pending += &line;
+            pending.push('\n');
+            prompt::ps2(&mut stdout, runtime);
+            continue;
+        }
+        let line = mem::take(&mut pending) + &line;
         //     let readline = runtime.rl.as_mut().unwrap().readline(&prompt);
         //     match readline {
         //         Ok(line) => {
@@ -136,27 +150,119 @@ fn buffered_loop(stdin: Stdin, mut stdout: Stdout, io: &mut IO, jobs: &mut Jobs,
         //             code = 130;
         //             break;
         //         }
-        let mut runtime = Runtime {
-            background: false,
-            io: io.clone(),
-            jobs: jobs,
-            args: args,
-            #[cfg(feature = "history")]
-            history: history,
-        };
-        if parse_and_run(&line, &mut runtime).is_ok() {
-            #[cfg(feature = "history")]
-            history.add(&line, 1);
-        }
-        #[cfg(feature = "history")]
-        history.add(&line, 1);
         #[cfg(feature = "history")]
-        history.reset_index();
+        if let Some(line) = record(&line, runtime) {
+            status = run(&line, runtime).unwrap_or(status);
+        }
+        #[cfg(not(feature = "history"))]
+        {
+            status = run(&line, runtime).unwrap_or(status);
+        }
+
+        notify(runtime);
+        prompt::ps1(&mut stdout, runtime);
+    }
+    status
+}
+
+// Is the user's text being run, rather than the shell waiting at the prompt.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+// Set when the line being typed at the prompt is interrupted.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Handle an interrupt from the terminal, `SIGINT`, which never exits the
+/// shell.
+///
+/// Commands run in the foreground share the shell's process group, so they
+/// get the interrupt too, and the shell just starts a new line once they've
+/// stopped. At the prompt the line being typed is thrown away and the prompt
+/// shown again. In raw mode the terminal doesn't send the signal at the
+/// prompt, the key is read like any other instead.
+pub fn interrupt() {
+    if RUNNING.load(Ordering::SeqCst) {
+        println!();
+    } else {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        let mut stdout = std::io::stdout();
+        let _ = write!(stdout, "\n{}", prompt::last_ps1());
+        let _ = stdout.flush();
+    }
+}
+
+// Run the user's text in the foreground, restoring the terminal's attributes
+// afterwards, in case the program (or the signal which stopped it) left them
+// changed. Returns the status of the text's last command, or nothing when
+// there aren't any, like for a blank or comment line, which leaves the status
+// as it was.
+pub(crate) fn run(text: &str, runtime: &mut Runtime) -> Option<i32> {
+    if matches!(parse_primary(text.as_bytes()), Ok(p) if p.commands().is_empty()) {
+        return None;
+    }
+    let saved = tcgetattr(0).ok();
+    RUNNING.store(true, Ordering::SeqCst);
+    let status = match parse_and_run(text, runtime) {
+        Ok(status) => exit_code(status),
+        Err(Error::Exit(code)) => code,
+        Err(Error::Parse(_)) => 2,
+        Err(_) => 1,
+    };
+    RUNNING.store(false, Ordering::SeqCst);
+    if let Some(saved) = saved {
+        let _ = tcsetattr(0, SetArg::TCSADRAIN, &saved);
+    }
+    Some(status)
+}
+
+// Does the line end with a `\` continuing it onto the next line, one which
+// isn't itself escaped or single quoted.
+pub(crate) fn continues(line: &str) -> bool {
+    let mut chars = line.chars();
+    let mut quote = None;
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {},
+            (_, '\\') => if chars.next().is_none() {
+                return true;
+            },
+            (Some(q), c) if q == c => quote = None,
+            (None, '\'') | (None, '"') => quote = Some(c),
+            _ => {},
+        }
+    }
+    false
+}
 
-        prompt::ps1(&mut stdout);
+// Announce the background jobs which finished or stopped since the last
+// prompt.
+pub(crate) fn notify(runtime: &mut Runtime) {
+    for notice in runtime.reap_jobs() {
+        eprintln!("{}", notice);
     }
 }
 
+// Expand the history references in the text and record it in the history,
+// returning the text to run. The expanded text is shown to the user, and
+// nothing is run when a reference isn't found.
+#[cfg(feature = "history")]
+fn record(text: &str, runtime: &mut Runtime) -> Option<String> {
+    let expanded = match runtime.history.expand(text) {
+        Ok(expanded) => expanded,
+        Err(e) => {
+            eprintln!("oursh: {}", e);
+            return None;
+        },
+    };
+    if expanded != text {
+        println!("{}", expanded);
+    }
+    // Continued lines are recorded as one.
+    runtime.history.add(&expanded.replace("\\\n", ""), &runtime.variables);
+    runtime.history.reset_index();
+    Some(expanded)
+}
+
 // pub mod display;
 pub mod prompt;
 #[cfg(feature = "raw")]
@@ -165,3 +271,28 @@ pub mod action;
 pub mod completion;
 #[cfg(feature = "history")]
 pub mod history;
+
+#[cfg(test)]
+mod tests {
+    use crate::program::Runtime;
+
+    #[test]
+    fn blank() {
+        let mut runtime = Runtime::builder().build().unwrap();
+        for text in &["", "   ", "\n", "# comment", "  # comment\n", ";", " ; "] {
+            assert_eq!(None, super::run(text, &mut runtime), "running {:?}", text);
+        }
+        assert_eq!(Some(1), super::run("false", &mut runtime));
+        assert_eq!(Some(0), super::run("\n\ntrue; # done", &mut runtime));
+    }
+
+    #[test]
+    fn continues() {
+        assert!(super::continues("ls \\"));
+        assert!(super::continues("echo \"a \\"));
+        assert!(!super::continues("ls"));
+        assert!(!super::continues("ls \\\\"));
+        assert!(!super::continues("echo 'a \\"));
+        assert!(super::continues("echo 'a' \\"));
+    }
+}
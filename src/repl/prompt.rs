@@ -1,16 +1,66 @@
-use std::env;
-use std::io::Write;
+use std::{
+    io::Write,
+    sync::Mutex,
+};
+use chrono::Local;
 use nix::unistd;
 use crate::{NAME, VERSION};
+use crate::program::{Runtime, Variables, posix::expand_here};
 
-/// TODO: docs
-pub fn ps1(stdout: &mut impl Write) {
-    let prompt = expand_prompt(env::var("PS1").unwrap_or_else(|_| "\\s-\\v\\$ ".into()));
+/// The prompt used when `$PS1` isn't set.
+const DEFAULT_PS1: &str = "\\s-\\v\\$ ";
+
+/// The prompt used when `$PS2` isn't set.
+const DEFAULT_PS2: &str = "> ";
+
+// The primary prompt as it was last displayed.
+static LAST_PS1: Mutex<String> = Mutex::new(String::new());
+
+/// Display the primary prompt, `$PS1`.
+pub fn ps1(stdout: &mut impl Write, runtime: &mut Runtime) {
+    let prompt = runtime.variables.get("PS1").unwrap_or(DEFAULT_PS1).to_string();
+    let prompt = render(&prompt, runtime);
     write!(stdout, "{}", prompt).unwrap();
     stdout.flush().unwrap();
+    *LAST_PS1.lock().unwrap() = prompt;
+}
+
+/// The primary prompt as it was last displayed, to show it again without
+/// the runtime.
+pub fn last_ps1() -> String {
+    LAST_PS1.lock().unwrap().clone()
+}
+
+/// Display the continuation prompt, `$PS2`, for the next line of a command
+/// which hasn't ended yet.
+pub fn ps2(stdout: &mut impl Write, runtime: &mut Runtime) {
+    let prompt = runtime.variables.get("PS2").unwrap_or(DEFAULT_PS2).to_string();
+    write!(stdout, "{}", render(&prompt, runtime)).unwrap();
+    stdout.flush().unwrap();
+}
+
+/// Render a prompt, first replacing its backslash escapes and then
+/// expanding parameters like `$USER`, as in double quotes, though any quotes
+/// are kept.
+///
+/// - `\u` the user, `\h` the hostname up to the first `.`, `\H` the full
+///   hostname.
+/// - `\w` the working directory with `$HOME` shortened to `~`, `\W` the
+///   basename of the working directory.
+/// - `\$` is `#` for root and `$` otherwise.
+/// - `\t` the time as `HH:MM:SS`, `\T` the same in 12-hour format, `\@` the
+///   12-hour time with am/pm, `\A` the time as `HH:MM`, and `\d` the date as
+///   `Tue May 26`.
+/// - `\s` the name of the shell, `\v` its version.
+/// - `\n` a newline, `\e` an escape, `\a` a bell, `\\` a backslash, `\[` and
+///   `\]` are removed, and `\0nn` is the octal character code.
+pub fn render(prompt: &str, runtime: &mut Runtime) -> String {
+    // With `set -u` a prompt naming an unset variable is left unexpanded.
+    let prompt = expand_escapes(prompt, &runtime.variables);
+    expand_here(&prompt, runtime).unwrap_or(prompt)
 }
 
-fn expand_prompt(prompt: String) -> String {
+fn expand_escapes(prompt: &str, variables: &Variables) -> String {
     let mut result = String::new();
     let mut command = false;
     let mut octal = vec![];
@@ -35,18 +85,39 @@ fn expand_prompt(prompt: String) -> String {
         if command {
             // TODO: https://ss64.com/bash/syntax-prompt.html
             result += &match c {
-                'h' => {
+                'h' | 'H' => {
                     let mut buf = [0u8; 64];
                     let cstr = unistd::gethostname(&mut buf).expect("error getting hostname");
-                    cstr.to_str().expect("error invalid UTF-8").into()
+                    let host = cstr.to_str().expect("error invalid UTF-8");
+                    match c {
+                        'h' => host.split('.').next().unwrap_or("").into(),
+                        _ => host.into(),
+                    }
                 }
                 'e' => (0x1b as char).into(),
-                'u' => env::var("USER").unwrap_or_else(|_| "".into()),
-                'w' => env::var("PWD").unwrap_or_else(|_| "".into()),
+                'a' => (0x07 as char).into(),
+                'n' => "\n".into(),
+                'u' => variables.get("USER").unwrap_or("").to_string(),
+                'w' => working_directory(variables),
+                'W' => {
+                    match working_directory(variables).as_str() {
+                        "/" => "/".into(),
+                        w => w.rsplit('/').next().unwrap_or("").into(),
+                    }
+                },
+                '$' if unistd::geteuid().is_root() => "#".into(),
+                // Escaped, so it's not expanded as a parameter.
+                '$' => "\\$".into(),
+                't' => Local::now().format("%H:%M:%S").to_string(),
+                'T' => Local::now().format("%I:%M:%S").to_string(),
+                '@' => Local::now().format("%I:%M %p").to_string(),
+                'A' => Local::now().format("%H:%M").to_string(),
+                'd' => Local::now().format("%a %b %d").to_string(),
                 's' => NAME.into(),
                 'v' => VERSION[0..(VERSION.len() - 2)].into(),
                 '0' => { octal.push(c); "".into() },
-                '\\' => "".into(),
+                '[' | ']' => "".into(),
+                '\\' => "\\\\".into(),
                 c => c.into(),
             };
             command = false;
@@ -58,3 +129,61 @@ fn expand_prompt(prompt: String) -> String {
     }
     result
 }
+
+// The working directory, with the home directory shortened to `~`.
+fn working_directory(variables: &Variables) -> String {
+    let pwd = variables.get("PWD").unwrap_or("");
+    match variables.get("HOME") {
+        Some(home) if !home.is_empty() && pwd == home => "~".into(),
+        Some(home) if !home.is_empty() && pwd.starts_with(&format!("{}/", home)) => {
+            format!("~{}", &pwd[home.len()..])
+        },
+        _ => pwd.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> Variables {
+        let mut variables = Variables::default();
+        variables.set("USER", "nixpulvis");
+        variables.set("HOME", "/home/nixpulvis");
+        variables.set("PWD", "/home/nixpulvis/src/oursh");
+        variables
+    }
+
+    #[test]
+    fn escapes() {
+        let variables = variables();
+        assert_eq!("nixpulvis ~/src/oursh",
+                   expand_escapes("\\u \\w", &variables));
+        assert_eq!("oursh\n", expand_escapes("\\W\\n", &variables));
+        assert_eq!("\\\\", expand_escapes("\\\\", &variables));
+        assert!(["\\$", "#"].contains(&expand_escapes("\\$", &variables).as_str()));
+        assert_eq!("ab", expand_escapes("\\[a\\]b", &variables));
+        assert_eq!(8, expand_escapes("\\t", &variables).len());
+    }
+
+    #[test]
+    fn quotes() {
+        let mut runtime = Runtime::builder().env("X", "/tmp").build().unwrap();
+        assert_eq!("it's> ", render("it's> ", &mut runtime));
+        assert_eq!("\"/tmp\"$ ", render("\"$X\"$ ", &mut runtime));
+        assert_eq!("'/tmp' \\ ", render("'$X' \\\\ ", &mut runtime));
+    }
+
+    #[test]
+    fn working_directory() {
+        let mut variables = variables();
+        assert_eq!("~/src/oursh", super::working_directory(&variables));
+        variables.set("PWD", "/home/nixpulvis");
+        assert_eq!("~", super::working_directory(&variables));
+        assert_eq!("~", expand_escapes("\\W", &variables));
+        variables.set("PWD", "/home/nixpulvisother");
+        assert_eq!("/home/nixpulvisother", super::working_directory(&variables));
+        variables.set("PWD", "/");
+        assert_eq!("/", expand_escapes("\\W", &variables));
+    }
+}
@@ -65,6 +65,16 @@ macro_rules! oursh {
     }};
 }
 
+/// A path in the temporary directory for the files of a test, unique to
+/// this run of the tests, so that runs at the same time don't collide.
+#[macro_export]
+macro_rules! temp {
+    ($name:expr) => {{
+        let name = format!("oursh-{}-{}", $name, std::process::id());
+        std::env::temp_dir().join(name).display().to_string()
+    }};
+}
+
 #[macro_export]
 macro_rules! assert_oursh {
     (! $text:expr) => {{
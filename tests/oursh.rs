@@ -1,5 +1,118 @@
 mod common;
 
+#[test]
+fn command_string() {
+    let output = shell!("target/debug/oursh", &["--noprofile", "-c", "echo hi; echo bye"], "");
+    assert_eq!("hi\nbye\n", String::from_utf8_lossy(&output.stdout));
+    let output = shell!("target/debug/oursh", &["--noprofile", "--command", "echo $0 $1 $#",
+                                                 "name", "a", "b"], "");
+    assert_eq!("name a 2\n", String::from_utf8_lossy(&output.stdout));
+    let output = shell!("target/debug/oursh", &["--noprofile", "-c", "exit 3"], "");
+    assert_eq!(Some(3), output.status.code());
+}
+
+#[test]
+fn posix_mode() {
+    let run = |args: &[&str], text: &str| {
+        let output = shell!("target/debug/oursh", args, text);
+        (String::from_utf8_lossy(&output.stdout).into_owned(),
+         String::from_utf8_lossy(&output.stderr).into_owned(),
+         output.status.code())
+    };
+    assert_eq!(("".into(), "oursh: arithmetic command `((...))` isn't POSIX\n".into(), Some(2)),
+               run(&["--noprofile", "--posix"], "(( 1 ))"));
+    assert_eq!(("failed\n".into(), "oursh: process substitution `<(...)` isn't POSIX\n".into(),
+                Some(0)),
+               run(&["--noprofile", "--posix"], "cat <(echo 1) || echo failed"));
+    assert_eq!(("".into(), "oursh: shebang block `{#!...}` isn't POSIX\n".into(), Some(2)),
+               run(&["--noprofile", "--posix"], "{#!/bin/sh; echo 1}"));
+    assert_eq!(("failed\n".into(), "oursh: here-string `<<<` isn't POSIX\n".into(), Some(0)),
+               run(&["--noprofile", "--posix"], "cat <<< hi || echo failed"));
+    // Braces are only braces in POSIX.
+    assert_eq!(("{a,b} x{1..2}\n".into(), "".into(), Some(0)),
+               run(&["--noprofile", "--posix"], "echo {a,b} x{1..2}"));
+    assert_eq!(("{a,b}\n".into(), "".into(), Some(0)),
+               run(&["--noprofile", "--posix"], "for x in {a,b}; do echo $x; done"));
+    // It's an option like any other.
+    assert_eq!(("1\n".into(), "".into(), Some(0)),
+               run(&["--noprofile", "--posix"], "set +o posix; (( 1 )) && echo 1"));
+    assert_eq!(("".into(), "oursh: arithmetic command `((...))` isn't POSIX\n".into(), Some(2)),
+               run(&["--noprofile"], "set -o posix; (( 1 ))"));
+}
+
+#[test]
+fn shell_level() {
+    use std::process::{Command, Stdio};
+
+    let run = |level: Option<&str>, text: &str| {
+        let mut command = Command::new("target/debug/oursh");
+        command.args(["--noprofile", "-c", text]).stdin(Stdio::null());
+        match level {
+            Some(level) => command.env("SHLVL", level),
+            None => command.env_remove("SHLVL"),
+        };
+        String::from_utf8_lossy(&command.output().unwrap().stdout).into_owned()
+    };
+    assert_eq!("1\n", run(None, "echo $SHLVL"));
+    assert_eq!("4\n", run(Some("3"), "echo $SHLVL"));
+    assert_eq!("1\n", run(Some("deep"), "echo $SHLVL"));
+    assert_eq!("4\n", run(Some("3"), "(echo $SHLVL)"));
+    assert_eq!("5\n", run(Some("3"), "target/debug/oursh --noprofile -c 'echo $SHLVL'"));
+}
+
+#[test]
+fn interactive() {
+    // Without a terminal the shell is only interactive with `-i`, which
+    // keeps it running after an error, even in a pipeline.
+    let text = "set -u; echo $UNSET; echo after; (echo $UNSET) | cat; echo still";
+    let output = shell!("target/debug/oursh", &["--noprofile", "--norc", "-i"], text);
+    assert_eq!("after\nstill\n", String::from_utf8_lossy(&output.stdout));
+    assert_eq!(Some(0), output.status.code());
+    let output = shell!("target/debug/oursh", &["--noprofile", "--norc"], text);
+    assert_eq!("", String::from_utf8_lossy(&output.stdout));
+    assert_eq!(Some(1), output.status.code());
+}
+
+#[test]
+fn interactive_rc() {
+    use std::{fs, process::{Command, Stdio}};
+
+    let rc = temp!("rc");
+    fs::write(&rc, "RC=sourced\n").unwrap();
+    let run = |args: &[&str]| {
+        let output = Command::new("target/debug/oursh")
+            .args(args)
+            // The path is expanded, like a parameter.
+            .env("ENV", rc.replace("-rc-", "-${RC_NAME}-"))
+            .env("RC_NAME", "rc")
+            .stdin(Stdio::null())
+            .output()
+            .unwrap();
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    assert_eq!("sourced\n", run(&["--noprofile", "-i", "-c", "echo $RC"]));
+    assert_eq!("\n", run(&["--noprofile", "--norc", "-i", "-c", "echo $RC"]));
+    assert_eq!("\n", run(&["--noprofile", "-c", "echo $RC"]));
+    let _ = fs::remove_file(rc);
+}
+
+#[test]
+#[cfg(feature = "history")]
+fn unwritable_history() {
+    use std::process::{Command, Stdio};
+
+    let output = Command::new("target/debug/oursh")
+        .args(["--noprofile", "--norc", "-i", "-c", "echo hi; exit 3"])
+        .env("HISTFILE", "/nonexistent/oursh_history")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert_eq!(Some(3), output.status.code());
+    assert_eq!("hi\n", String::from_utf8_lossy(&output.stdout));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .starts_with("oursh: warning: /nonexistent/oursh_history: history not saved:"));
+}
+
 #[test]
 #[cfg(feature = "shebang-block")]
 fn shebang_block_sh_command() {
@@ -12,6 +125,15 @@ fn shebang_block_sh_command() {
 }"#, "12345");
 }
 
+#[test]
+#[cfg(feature = "shebang-block")]
+fn shebang_block_native_command() {
+    assert_oursh!("{#!posix; echo 1; echo 2}", "1\n2\n");
+    assert_oursh!("{#!modern; let x = 1; echo $x}", "1\n");
+    assert_oursh!("{#modern let x = 1; echo $x}", "1\n");
+    assert_oursh!("{#!posix; false} || echo failed", "failed\n");
+}
+
 #[test]
 #[cfg(feature = "shebang-block")]
 fn shebang_block_ruby_command() {
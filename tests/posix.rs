@@ -5,23 +5,382 @@ fn hello_world() {
     assert_oursh!("echo hello world", "hello world\n");
 }
 
+#[test]
+fn blank_lines() {
+    assert_oursh!("echo a\n\n   \n# comment\n;\necho b\n", "a\nb\n");
+    // Lines without any commands leave the status as it was.
+    assert_oursh!(! "false\n\n");
+    assert_oursh!(! "false\n  # comment\n");
+    assert_oursh!(! "false\n;\n");
+}
+
 #[test]
 fn builtin_cd() {
     assert_oursh!("cd /; pwd", "/\n");
-    // assert_oursh!("cd; pwd", "$HOME\n");
-    // assert_oursh!("cd ~; pwd", "$HOME\n");
-    // assert_oursh!("cd /; cd /home; cd -", "/\n");
+    assert_oursh!("HOME=/tmp; cd; pwd", "/tmp\n");
+    assert_oursh!("HOME=/tmp; cd; HOME=/; cd; pwd", "/\n");
+    assert_oursh!("cd /; cd /tmp; cd -; echo $OLDPWD", "/\n/tmp\n");
+    assert_oursh!("cd /tmp; cd /; cd /nowhere || echo $PWD $OLDPWD", "/ /tmp\n");
+    assert_oursh!("cd /; cd /tmp /usr || pwd", "/\n");
+    assert_oursh!("CDPATH=/nowhere:/; cd /tmp; cd tmp; pwd", "/tmp\n/tmp\n");
+    assert_oursh!("CDPATH=/; cd /tmp; cd ./; pwd", "/tmp\n");
+}
+
+#[test]
+fn builtin_cd_failure() {
+    assert_oursh!("cd /tmp; cd /nowhere || echo $PWD", "/tmp\n",
+                  "oursh: cd: /nowhere: no such file or directory\n");
+    let file = temp!("cd-file");
+    assert_oursh!(format!("touch {0}; cd /tmp; cd {0} || pwd; rm {0}", file), "/tmp\n",
+                  format!("oursh: cd: {}: not a directory\n", file));
+    assert_oursh!("cd /tmp; unset HOME; cd || pwd; echo continued", "/tmp\ncontinued\n",
+                  "oursh: cd: HOME not set\n");
+    // Root can change into any directory.
+    if !String::from_utf8_lossy(&oursh!("id -u").stdout).starts_with("0\n") {
+        let denied = temp!("cd-denied");
+        assert_oursh!(format!("mkdir -p {0}; chmod 0 {0}; cd {0} || echo failed; rmdir {0}",
+                              denied),
+                      "failed\n", format!("oursh: cd: {}: permission denied\n", denied));
+    }
+}
+
+#[test]
+fn builtin_cd_symlink() {
+    let dir = temp!("cd");
+    let setup = format!("mkdir -p {0}/real; ln -sfn real {0}/link", dir);
+    assert_oursh!(format!("{}; cd {}/link; pwd; pwd -P; cd ..; pwd", setup, dir),
+                  format!("{0}/link\n{0}/real\n{0}\n", dir));
+    assert_oursh!(format!("{}; cd -L {}/link/../real; pwd", setup, dir),
+                  format!("{}/real\n", dir));
+    assert_oursh!(format!("{}; cd -P {}/link; pwd; echo $PWD", setup, dir),
+                  format!("{0}/real\n{0}/real\n", dir));
+    let _ = std::fs::remove_dir_all(dir);
 }
 
 #[test]
 fn builtin_exit() {
     assert_oursh!("exit");
+    assert_oursh!(! "false; exit");
     assert_oursh!(! "exit 1");
+    let code = |text: &str| oursh!(text).status.code();
+    assert_eq!(Some(1), code("exit 257"));
+    assert_eq!(Some(255), code("exit -1"));
+    let output = oursh!("exit foo; echo continued");
+    assert_eq!(Some(2), output.status.code());
+    assert_eq!("", String::from_utf8_lossy(&output.stdout));
+    assert_eq!("oursh: exit: foo: numeric argument required\n",
+               String::from_utf8_lossy(&output.stderr));
+    assert_oursh!("exit 1 2 || echo continued", "continued\n",
+                  "oursh: exit: too many arguments\n");
+}
+
+#[test]
+fn builtin_set() {
+    // With no arguments the variables are printed.
+    let output = oursh!("FOO=bar; set");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("FOO=bar\n"));
+    // A lone `--` clears the positional parameters.
+    assert_oursh!("set -- a b c; set --; echo $#", "0\n");
+    assert_oursh!("set -- a b c; set --; echo $1", "\n");
+    // Arguments after `--` become the positional parameters.
+    assert_oursh!("set -- a b c; echo $#", "3\n");
+    assert_oursh!("set -- a b c; echo $1 $3", "a c\n");
+    assert_oursh!("set -- a b c; echo $@", "a b c\n");
+    // Options are turned on with `-o` and off with `+o`.
+    assert_oursh!("set -o huponexit; set -o",
+                  "huponexit       on\nnoclobber       off\nnounset         off\n\
+                   pathwarn        off\npipefail        off\nposix           off\n");
+    assert_oursh!("set -o huponexit +o huponexit; set +o",
+                  "set +o huponexit\nset +o noclobber\nset +o nounset\nset +o pathwarn\n\
+                   set +o pipefail\nset +o posix\n");
+    assert_oursh!("set -o nope || echo failed", "failed\n",
+                  "oursh: set: nope: invalid option name\n");
+}
+
+#[test]
+fn nounset() {
+    let output = oursh!("set -u; echo $X; echo unreachable");
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!("", String::from_utf8_lossy(&output.stdout));
+    assert_eq!("oursh: X: parameter not set\n", String::from_utf8_lossy(&output.stderr));
+    assert_oursh!(! "set -o nounset; echo \"$1\"");
+    assert_oursh!(! "set -u; X=$Y");
+    assert_oursh!(! "set -u; echo > $X");
+    let output = oursh!("set -u; echo > $X; echo unreachable");
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!("", String::from_utf8_lossy(&output.stdout));
+    assert_oursh!(! "set -u; { echo; } > $X; echo unreachable");
+    assert_oursh!(! "set -u; (( $X + 1 ))");
+    assert_oursh!("set -u; (echo $X) || echo failed", "failed\n",
+                  "oursh: X: parameter not set\n");
+    // Set variables, even empty ones, are fine.
+    assert_oursh!("set -u; X=; echo \"[$X]\" $#", "[] 0\n");
+    // As are `$@` and `$*` without any positional parameters.
+    assert_oursh!("set -u; : \"$@\"; printf '[%s]' \"$*\" $@; echo", "[]\n");
+    // And the forms with a modifier.
+    assert_oursh!("set -u; : ${X:-default} ${X-default} ${X:+alternate}; echo ok", "ok\n");
+    // But not the length of one.
+    assert_oursh!(! "set -u; echo ${#X}");
+    assert_oursh!("set -u; set +u; echo \"[$X]\"", "[]\n");
+}
+
+#[test]
+fn builtin_shift() {
+    assert_oursh!("set -- a b c; shift; echo $# $1 $2", "2 b c\n");
+    assert_oursh!("set -- a b c; shift 2; echo $# $1", "1 c\n");
+    assert_oursh!("set -- a b c; shift 3; echo $#", "0\n");
+    // Shifting too many leaves the parameters alone.
+    assert_oursh!("set -- a b; shift 3 || echo $# $1", "2 a\n",
+                  "oursh: shift: 3: shift count out of range\n");
+}
+
+#[test]
+fn builtin_test() {
+    assert_oursh!("test a && test -n a && test -z '' && echo yes", "yes\n");
+    assert_oursh!("test '' || test a = b || echo no", "no\n");
+    assert_oursh!("[ a != b ] && [ -d / ] && [ ! -f / ] && echo yes", "yes\n");
+    assert_oursh!("[ 1 -eq 01 ] && [ 2 -gt -3 ] && [ 1 -le 1 ] && echo yes", "yes\n");
+    assert_oursh!("[ 1 -ne 1 ] || [ 2 -lt 1 ] || echo no", "no\n");
+    assert_oursh!("[ a -a '' -o b ] && [ \\( '' -o a \\) -a b ] && echo yes", "yes\n");
+    // Operators are operands where there's no room for an operator.
+    assert_oursh!("[ -n ] && [ = = = ] && echo yes", "yes\n");
+    // Integer comparisons of anything else are errors, not just false.
+    assert_oursh!("[ foo -eq 1 ] || echo error", "error\n",
+                  "oursh: [: foo: integer expression expected\n");
+    assert_oursh!("test 1 -lt 1.5 || echo error", "error\n",
+                  "oursh: test: 1.5: integer expression expected\n");
+    let output = oursh!("test foo -ne 1");
+    assert_eq!(Some(2), output.status.code());
+    let output = oursh!("[ 1 = 1");
+    assert_eq!(Some(2), output.status.code());
+    assert_eq!(b"oursh: [: missing `]'\n", &output.stderr[..]);
+}
+
+#[test]
+fn builtin_getopts() {
+    assert_oursh!("getopts ab: opt -a; echo $opt $OPTIND", "a 2\n");
+    assert_oursh!("getopts ab: opt -b 1; echo $opt $OPTARG $OPTIND", "b 1 3\n");
+    assert_oursh!("getopts ab: opt -b1; echo $opt $OPTARG", "b 1\n");
+    assert_oursh!("getopts ab: opt -ab1; getopts ab: opt -ab1; echo $opt $OPTARG",
+                  "b 1\n");
+    assert_oursh!(! "getopts ab: opt foo");
+    // Invalid options and missing arguments are reported.
+    assert_oursh!("getopts ab: opt -x; echo $opt$OPTARG", "?\n",
+                  "oursh: getopts: illegal option -- x\n");
+    assert_oursh!("getopts ab: opt -b; echo $opt$OPTARG", "?\n",
+                  "oursh: getopts: option requires an argument -- b\n");
+    // A group of options is only continued while `OPTIND` and the arguments
+    // stay the same.
+    assert_oursh!("getopts ab o -ab; OPTIND=5; getopts ab o -ab || echo finished",
+                  "finished\n");
+    assert_oursh!("set -- -abc; getopts abc o; set -- -x; getopts abc o || echo finished",
+                  "finished\n");
+}
+
+#[test]
+fn builtin_getopts_silent() {
+    assert_oursh!("getopts :ab: opt -x; echo $opt $OPTARG", "? x\n", "");
+    assert_oursh!("getopts :ab: opt -b; echo $opt $OPTARG", ": b\n", "");
 }
 
 #[test]
 fn builtin_null() {
     assert_oursh!(":");
+    assert_oursh!(": ignored arguments > /dev/null", "");
+    assert_oursh!(": ${x:=5}; echo $x", "5\n");
+    assert_oursh!("x=1; : ${x:=5} ${y=$x}; echo $x $y", "1 1\n");
+    assert_oursh!("FOO=bar :; echo $FOO", "bar\n");
+    assert_oursh!(! "readonly x; : ${x:=5}");
+}
+
+#[test]
+fn builtin_echo() {
+    assert_oursh!("echo", "\n");
+    assert_oursh!("echo -n a  b; echo c", "a bc\n");
+    assert_oursh!("echo a -n", "a -n\n");
+}
+
+#[test]
+fn builtin_printf() {
+    assert_oursh!("printf 'a\\tb\\n'", "a\tb\n");
+    assert_oursh!("printf '%s=%d%%\\n' x 42", "x=42%\n");
+    assert_oursh!("printf '%x %X %o %c %u\\n' 255 255 8 hello 7", "ff FF 10 h 7\n");
+    assert_oursh!("printf '[%5s][%-5s][%.2s]\\n' a b xyz", "[    a][b    ][xy]\n");
+    assert_oursh!("printf '[%05d][%+d][%.3d][%#x]\\n' -42 5 7 255", "[-0042][+5][007][0xff]\n");
+    // The format is reused for the rest of the arguments.
+    assert_oursh!("printf '%s-%s\\n' a b c", "a-b\nc-\n");
+    assert_oursh!("printf 'x\\n' a b", "x\n");
+    // Missing arguments are empty or zero.
+    assert_oursh!("printf '[%s][%d]\\n'", "[][0]\n");
+    assert_oursh!("printf '%d %d %d\\n' 0x10 010 \"'A\"", "16 8 65\n");
+    assert_oursh!("printf 'a\\cb'; printf '\\101\\n'", "aA\n");
+    let output = oursh!("printf '%d\\n' foo");
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(b"0\n", &output.stdout[..]);
+    assert_eq!(b"oursh: printf: foo: invalid number\n", &output.stderr[..]);
+}
+
+#[test]
+fn builtin_times() {
+    let output = oursh!("sh -c true; times");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(2, lines.len());
+    for line in lines {
+        let times = line.split(' ').collect::<Vec<_>>();
+        assert_eq!(2, times.len());
+        for time in times {
+            let (minutes, seconds) = time.split_once('m').unwrap();
+            assert!(minutes.parse::<u64>().is_ok());
+            assert!(seconds.strip_suffix('s').unwrap().parse::<f64>().is_ok());
+        }
+    }
+}
+
+#[test]
+fn builtin_umask() {
+    assert_oursh!("umask 027; umask", "0027\n");
+    assert_oursh!("umask 022; umask -S", "u=rwx,g=rx,o=rx\n");
+    assert_oursh!("umask u=rwx,g=rx,o=; umask", "0027\n");
+    assert_oursh!("umask 077; umask g+r,o+rx; umask", "0032\n");
+    assert_oursh!(format!("umask 077; rm -f {0}; echo > {0}; stat -c %a {0}; rm {0}",
+                          temp!("umask")),
+                  "600\n");
+    assert_oursh!("umask 088 || echo failed", "failed\n",
+                  "oursh: umask: 088: invalid mask\n");
+}
+
+#[test]
+fn builtin_command() {
+    assert_oursh!("command echo a", "a\n");
+    assert_oursh!("f() { :; }; command -v cd f", "cd\nf\n");
+    assert_oursh!("command -v sh > /dev/null && echo found", "found\n");
+    assert_oursh!("PATH=/nowhere command -v sh || echo missing", "missing\n");
+    assert_oursh!("command -v /bin/sh", "/bin/sh\n");
+}
+
+#[test]
+fn builtin_builtin() {
+    assert_oursh!("echo() { printf 'f:%s\\n' \"$@\"; }; echo a; builtin echo b", "f:a\nb\n");
+    assert_oursh!("builtin nope || echo failed", "failed\n",
+                  "oursh: builtin: nope: not a shell builtin\n");
+}
+
+#[test]
+fn builtin_enable() {
+    assert_oursh!("enable -n echo; PATH=/nowhere; echo hi 2> /dev/null || printf 'failed\\n'; \
+                   enable echo; echo hi",
+                  "failed\nhi\n");
+    assert_oursh!("enable -n echo; builtin echo hi || printf 'failed\\n'", "failed\n",
+                  "oursh: builtin: echo: not a shell builtin\n");
+    assert_oursh!(format!("enable -n test; enable > {0}; grep -e test -e echo {0}; rm {0}",
+                          temp!("enable")),
+                  "enable echo\nenable -n test\n");
+    assert_oursh!("enable -n nope || echo failed", "failed\n",
+                  "oursh: enable: nope: not a shell builtin\n");
+}
+
+#[test]
+fn builtin_exec() {
+    assert_oursh!("FOO=bar exec printenv FOO", "bar\n");
+    assert_oursh!("exec echo hi; echo unreachable", "hi\n");
+    assert_oursh!(format!("exec > {0}; echo hi; cat {0} > /dev/stderr; rm {0}", temp!("exec")),
+                  "", "hi\n");
+    let output = oursh!("exec nowhere-to-be-found; echo unreachable");
+    assert_eq!(Some(127), output.status.code());
+    assert_eq!("", String::from_utf8_lossy(&output.stdout));
+    assert_eq!("oursh: exec: nowhere-to-be-found: not found\n",
+               String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn builtin_pwd() {
+    assert_oursh!("cd /tmp; pwd", "/tmp\n");
+}
+
+#[test]
+fn builtin_redirect() {
+    // Without a `PATH` only the builtin `echo` can be run.
+    let file = temp!("builtin-redirect");
+    assert_oursh!(format!("PATH=; echo hi > {0}; /bin/cat {0}", file), "hi\n");
+    assert_oursh!(format!("echo hello > {0}; echo hi > {0}; cat {0}; rm {0}", file), "hi\n");
+    assert_oursh!("echo hi > /nowhere/file || echo failed", "failed\n");
+}
+
+#[test]
+fn noclobber_redirect() {
+    let file = temp!("noclobber");
+    assert_oursh!(format!("echo a > {0}; set -C; echo b > {0} || cat {0}", file),
+                  "a\n", format!("oursh: {}: cannot overwrite existing file\n", file));
+    assert_oursh!(format!("echo a > {0}; set -o noclobber; echo b >| {0}; echo c >> {0}; \
+                           cat {0}", file),
+                  "b\nc\n");
+    assert_oursh!(format!("rm -f {0}; set -C; echo a > {0}; echo b > /dev/null; \
+                           set +C; echo c > {0}; cat {0}; rm {0}", file),
+                  "c\n");
+}
+
+#[test]
+fn builtin_output_redirect() {
+    assert_oursh!(format!("trap 'true' USR1; trap > {0}; cat {0}; rm {0}",
+                          temp!("builtin-output")),
+                  "trap -- 'true' USR1\n");
+}
+
+#[test]
+fn compound_redirect() {
+    let file = temp!("compound");
+    assert_oursh!(format!("{{ echo out; echo err >&2; }} > {0} 2>&1; cat {0}", file),
+                  "out\nerr\n");
+    assert_oursh!("{ echo err >&2; } 2>&1 | tr e E", "Err\n");
+    assert_oursh!(format!("printf 'a\\nb\\n' > {0}; \
+                           while read x; do echo \"[$x]\"; done < {0}", file),
+                  "[a]\n[b]\n");
+    assert_oursh!(format!("if true; then echo yes; fi > {0}; cat {0}", file), "yes\n");
+    assert_oursh!("(echo sub; ls /nowhere) 2> /dev/null || echo failed", "sub\nfailed\n", "");
+    // A function's redirections apply to each call.
+    assert_oursh!(format!("f() {{ echo $1; }} >> {0}; rm {0}; f a; f b; cat {0}; rm {0}", file),
+                  "a\nb\n");
+    assert_oursh!("{ echo unreachable; } > /nowhere/file || echo failed", "failed\n");
+}
+
+#[test]
+fn duplicate_redirect() {
+    // The order matters, each duplicates the file descriptor as it is so far.
+    assert_oursh!("sh -c 'echo out; echo err >&2' 2>&1 1>/dev/null", "err\n");
+    assert_oursh!("sh -c 'echo out; echo err >&2' 1>/dev/null 2>&1", "");
+    let file = temp!("duplicate");
+    assert_oursh!(format!("sh -c 'echo out; echo err >&2' > {0} 2>&1; cat {0}", file),
+                  "out\nerr\n");
+    assert_oursh!("printf 'err\\n' 1>&2", "", "err\n");
+    assert_oursh!(format!("echo hi > {0}; cat 2< {0} <&2; rm {0}", file), "hi\n");
+    assert_oursh!("echo hi >&- || echo failed", "failed\n");
+    assert_oursh!("sh -c 'cat' <&- || echo failed", "failed\n");
+    // Only a file descriptor which is open can be duplicated.
+    assert_oursh!("exec 7>&-; echo hi >&7 || echo failed", "failed\n",
+                  "oursh: 7: bad file descriptor\n");
+    assert_oursh!("exec 7>&1; echo hi >&7", "hi\n");
+    assert_oursh!("echo hi >&nope || echo failed", "failed\n",
+                  "oursh: nope: ambiguous redirect\n");
+}
+
+#[test]
+fn other_redirect() {
+    // Any file descriptor can be redirected, not only the standard ones.
+    let file = temp!("other");
+    assert_oursh!(format!("exec 3> {0}; echo a >&3; echo b >&3; cat {0}; rm {0}", file),
+                  "a\nb\n");
+    assert_oursh!("echo c 4>&1", "c\n");
+    assert_oursh!(format!("echo d > {0}; cat 4< {0} <&4; rm {0}", file), "d\n");
+    assert_oursh!("sh -c 'echo out; echo err >&2' 3>&1 1>&2 2>&3", "err\n", "out\n");
+    assert_oursh!("f() { echo e >&5; }; f 5>&1", "e\n");
+    assert_oursh!("sh -c 'echo hi >&3' 3>&1 | cat", "hi\n");
+    // Closing one closes it for the command, or the shell with `exec`.
+    assert_oursh!("exec 3>&1; sh -c 'echo hi >&3' 3>&- || echo failed", "failed\n");
+    assert_oursh!("exec 3>&1; echo a >&3; exec 3>&-; echo b >&3 || echo failed",
+                  "a\nfailed\n", "oursh: 3: bad file descriptor\n");
 }
 
 #[test]
@@ -30,6 +389,58 @@ fn forkbomb() {
     assert_oursh!(":(){ :|: & };:");
 }
 
+#[test]
+fn brace_expansion() {
+    assert_oursh!("echo {a,b}{1,2}", "a1 a2 b1 b2\n");
+    assert_oursh!("echo x{1..5}", "x1 x2 x3 x4 x5\n");
+    assert_oursh!("echo {a..e} {0..10..5}", "a b c d e 0 5 10\n");
+    assert_oursh!("echo {a,{b,c}d}", "a bd cd\n");
+    assert_oursh!("X=1; echo {$X,\"b c\"}", "1 b c\n");
+    assert_oursh!("echo {a} '{a,b}' {}", "{a} {a,b} {}\n");
+    assert_oursh!("{ echo {a,b}; }", "a b\n");
+}
+
+#[test]
+fn process_substitution() {
+    assert_oursh!("cat <(echo hi)", "hi\n");
+    assert_oursh!("cat <(echo a) <(echo b)", "a\nb\n");
+    assert_oursh!("X=1; cat <(echo $X)", "1\n");
+    assert_oursh!(format!("echo hi > {0}; tee >(tr a-z A-Z) < {0} > /dev/null; rm {0}",
+                          temp!("substitution")),
+                  "HI\n");
+    assert_oursh!("diff <(echo a) <(echo a) && echo same", "same\n");
+}
+
+#[test]
+fn here_string() {
+    assert_oursh!("read x <<< \"hello\"; echo $x", "hello\n");
+    assert_oursh!("X='a  b'; cat <<< $X", "a  b\n");
+    assert_oursh!("cat <<< '$X'", "$X\n");
+    assert_oursh!("X=1; cat <<<\"[$X]\" | wc -l", "1\n");
+    // There's no limit to how long it is, like a pipe's size.
+    assert_oursh!("x=$(head -c 2000000 /dev/zero | tr '\\0' a); cat <<< $x | wc -c",
+                  "2000001\n");
+}
+
+#[test]
+fn here_document() {
+    assert_oursh!("cat <<EOF\nhello\nworld\nEOF\necho after", "hello\nworld\nafter\n");
+    assert_oursh!("X=1; cat <<EOF\n$X \"$X\" '$X' \\$X\nEOF", "1 \"1\" '1' $X\n");
+    // Nothing is expanded with a quoted delimiter.
+    assert_oursh!("X=1; cat <<'EOF'\n$X \\$X\nEOF", "$X \\$X\n");
+    assert_oursh!("X=1; cat <<\\EOF\n$X\nEOF", "$X\n");
+    // Leading tabs are removed with `<<-`, from the delimiter's line too.
+    assert_oursh!("cat <<-EOF\n\tindented\n\t\tmore\n\tEOF", "indented\nmore\n");
+    // The documents of a line follow it in order.
+    assert_oursh!("cat <<A; cat <<B\na\nA\nb\nB\necho c", "a\nb\nc\n");
+    assert_oursh!("while read x; do echo \"[$x]\"; done <<EOF | cat\n1\n2\nEOF",
+                  "[1]\n[2]\n");
+    assert_oursh!("cat <<EOF\nEOF", "");
+    assert_oursh!("x=$(head -c 2000000 /dev/zero | tr '\\0' a); cat <<EOF | wc -c\n$x\nEOF",
+                  "2000001\n");
+    assert_oursh!(! "cat <<EOF\nnever ends");
+}
+
 #[test]
 fn hello_world_quoted() {
     assert_oursh!("echo 'hello world'", "hello world\n");
@@ -41,6 +452,52 @@ fn simple_command() {
     assert_oursh!("head README.md -n 1", "# oursh\n");
 }
 
+#[test]
+fn command_not_found() {
+    assert_oursh!("nowhere-to-be-found; echo next", "next\n",
+                  "oursh: nowhere-to-be-found: command not found\n");
+    let output = oursh!("nowhere-to-be-found");
+    assert_eq!(Some(127), output.status.code());
+    let output = oursh!("./nowhere-to-be-found");
+    assert_eq!(Some(127), output.status.code());
+    assert_eq!("oursh: ./nowhere-to-be-found: no such file or directory\n",
+               String::from_utf8_lossy(&output.stderr));
+    // Found, but not executable.
+    let file = temp!("noexec");
+    let output = oursh!(format!("touch {0}; chmod -x {0}; {0}", file));
+    let _ = std::fs::remove_file(&file);
+    assert_eq!(Some(126), output.status.code());
+    assert_eq!(format!("oursh: {}: permission denied\n", file),
+               String::from_utf8_lossy(&output.stderr));
+    // Only the shell says a command wasn't found.
+    let output = oursh!("sh -c 'exit 127'");
+    assert_eq!(Some(127), output.status.code());
+    assert_eq!("", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn empty_path_entries() {
+    // The same command in the current directory, and in `bin` below it.
+    let dir = temp!("path");
+    let setup = format!("mkdir -p {0}/bin; cd {0}; \
+                         printf '#!/bin/sh\\necho cwd\\n' > oursh-here; \
+                         printf '#!/bin/sh\\necho bin\\n' > bin/oursh-here; \
+                         chmod +x oursh-here bin/oursh-here", dir);
+    assert_oursh!(format!("{}; PATH=:{}/bin; oursh-here", setup, dir), "cwd\n");
+    assert_oursh!(format!("{}; PATH={}/bin:; oursh-here", setup, dir), "bin\n");
+    assert_oursh!(format!("{}; PATH=/nowhere::{}/bin; oursh-here", setup, dir), "cwd\n");
+    assert_oursh!(format!("{}; PATH=/nowhere; oursh-here || echo missing", setup),
+                  "missing\n", "oursh: oursh-here: command not found\n");
+    let _ = std::fs::remove_dir_all(dir);
+    assert_oursh!(format!("{}; PATH=/nowhere:; command -v oursh-here", setup),
+                  "./oursh-here\n");
+    // An explicit `.` is no surprise.
+    assert_oursh!(format!("{}; set -o pathwarn; PATH=.; oursh-here", setup), "cwd\n", "");
+    assert_oursh!(format!("{}; set -o pathwarn; PATH=:/bin; oursh-here", setup), "cwd\n",
+                  "oursh: warning: oursh-here: found in the current directory, \
+                   by an empty entry in $PATH\n");
+}
+
 #[test]
 fn chained_command() {
     assert_oursh!("false; true; echo 1", "1\n");
@@ -91,19 +548,183 @@ fn cond_command() {
     assert_oursh!("if false; then echo 1; else echo 2; fi", "2\n");
     assert_oursh!("if false; then echo 1; elif false; then echo 2; else echo 3; fi", "3\n");
     assert_oursh!("if false; then echo 1; elif true; then echo 2; else echo 3; fi", "2\n");
+    // Only one branch runs, whatever its status.
+    assert_oursh!("if true; then false; else echo 2; fi || echo failed", "failed\n");
+    assert_oursh!("if false; then true; elif true; then false; else echo 3; fi || echo failed",
+                  "failed\n");
+    // Without a branch to run the status is zero.
+    assert_oursh!("if false; then echo 1; fi && echo ok", "ok\n");
+    assert_oursh!("if false; then echo 1; elif false; then echo 2; fi && echo ok", "ok\n");
+    assert_oursh!("if if false; then true; fi; then echo nested; fi", "nested\n");
+}
+
+#[test]
+fn comment_command() {
+    assert_oursh!("echo hi # there", "hi\n");
+    assert_oursh!("echo foo#bar", "foo#bar\n");
+    assert_oursh!("echo \"a # b\" 'c # d' \\#e", "a # b c # d #e\n");
+    assert_oursh!("echo 1 # one\necho 2;# echo 3\n", "1\n2\n");
+}
+
+#[test]
+fn line_continuation() {
+    assert_oursh!("echo a b \\\n  c \\\n | wc -w", "3\n");
+    assert_oursh!("ec\\\nho a\\\nb", "ab\n");
+    assert_oursh!("echo \"a\\\nb\"", "ab\n");
+    assert_oursh!("echo 'a\\\nb'", "a\\\nb\n");
+}
+
+#[test]
+fn streamed_program() {
+    // Each command runs before the next is read.
+    assert_oursh!("read x\nhello\necho $x\n", "hello\n");
+    assert_oursh!("\n# comment\nif true\nthen\n  echo 'a\nb'\nfi\n", "a\nb\n");
+    // So a syntax error only stops the program once it's reached.
+    let output = oursh!("echo before\n)\necho after\n");
+    assert_eq!("before\n", String::from_utf8_lossy(&output.stdout));
+    assert_eq!(Some(2), output.status.code());
+    let output = oursh!("echo before\necho 'unterminated\n");
+    assert_eq!("before\n", String::from_utf8_lossy(&output.stdout));
+    assert_eq!(Some(2), output.status.code());
+}
+
+#[test]
+fn compound_list_command() {
+    assert_oursh!("{ echo 1\necho 2; false; }; echo 3", "1\n2\n3\n");
+    assert_oursh!("{ false; true; } && echo ok", "ok\n");
+    assert_oursh!("{ true; false\n\n} || echo failed", "failed\n");
+    // A `;;` only ends the clause of a `case`.
+    assert_oursh!(! "echo 1;; echo 2;\n");
 }
 
 #[test]
 fn subshell_command() {
     assert_oursh!("$( true )");
-    assert_oursh!("$(echo 1)", "1\n");
-    assert_oursh!("$(false; echo 1)", "1\n");
-    // TODO: Test some actual subshell usage.
+    assert_oursh!("$(echo echo 1)", "1\n");
+    assert_oursh!("$(false; echo echo 1)", "1\n");
+    assert_oursh!("(echo 1; echo 2)", "1\n2\n");
+    assert_oursh!("(false) || echo failed", "failed\n");
+    // Nothing done in the subshell changes the shell itself.
+    assert_oursh!("X=0; (X=1; echo $X); echo $X", "1\n0\n");
+    assert_oursh!("cd /; (cd /tmp && pwd); pwd", "/tmp\n/\n");
+    assert_oursh!("(exit 3) || echo failed", "failed\n");
+    assert_oursh!("(sleep 0; echo finished) & wait", "finished\n");
+    // Only the subshell's own `EXIT` trap is run when it ends.
+    assert_oursh!("trap 'echo bye' EXIT; (exit 3); (echo in); echo out", "in\nout\nbye\n");
+    assert_oursh!("(trap 'echo sub' EXIT; echo in); echo out", "in\nsub\nout\n");
+    assert_oursh!("echo | (trap 'echo sub' EXIT; cat)", "\nsub\n");
 }
 
 #[test]
 fn single_pipeline_command() {
     assert_oursh!("echo pi | wc -c", "3\n");
+    assert_oursh!("export A=1; env | grep ^A=", "A=1\n");
+    assert_oursh!("true | false || echo failed", "failed\n");
+    assert_oursh!("yes | head -n 1", "y\n");
+}
+
+#[test]
+fn multiple_pipeline_command() {
+    assert_oursh!("seq 10 | grep 1 | sort -r | tr 0 x", "1x\n1\n");
+    assert_oursh!("yes | cat | head -n 2 | wc -l", "2\n");
+    assert_oursh!("echo a | { cat; echo b; } | (cat; echo c) | cat", "a\nb\nc\n");
+    // Every stage runs at the same time, so none waits on a full pipe.
+    assert_oursh!("seq 100000 | cat | cat | tail -n 1", "100000\n");
+    let output = oursh!("set -o pipefail; true | sh -c 'exit 3' | true | true");
+    assert_eq!(Some(3), output.status.code());
+}
+
+#[test]
+fn compound_pipeline_command() {
+    assert_oursh!("echo a | cat | cat", "a\n");
+    assert_oursh!("echo hi | { read x; echo got $x; }", "got hi\n");
+    assert_oursh!("printf 'a\\nb\\n' | while read f; do echo \"[$f]\"; done", "[a]\n[b]\n");
+    assert_oursh!("f() { tr a-z A-Z; }; echo abc | f", "ABC\n");
+    assert_oursh!("echo abc | (tr a-z A-Z) | cat", "ABC\n");
+    assert_oursh!("if true; then echo yes; fi | cat", "yes\n");
+    // The stages run apart from the shell.
+    assert_oursh!("x=1; echo 2 | read x; echo $x", "1\n");
+}
+
+#[test]
+fn while_command() {
+    assert_oursh!("x=; while [ \"$x\" != aaa ]; do x=a$x; echo $x; done",
+                  "a\naa\naaa\n");
+    assert_oursh!("x=; until [ \"$x\" = aa ]; do x=a$x; done; echo $x", "aa\n");
+    assert_oursh!("while false; do echo no; done && echo ok", "ok\n");
+}
+
+#[test]
+fn for_command() {
+    assert_oursh!("for x in a b c; do echo $x; done", "a\nb\nc\n");
+    assert_oursh!("for x in a b\ndo\n  echo $x\ndone; echo $x", "a\nb\nb\n");
+    // The words are expanded before the loop starts.
+    assert_oursh!("y='1 2'; for x in $y \"$y\" {a,b}; do echo \"[$x]\"; y=; done",
+                  "[1]\n[2]\n[1 2]\n[a]\n[b]\n");
+    // Without `in` the positional parameters are used.
+    assert_oursh!("set -- a 'b c'; for x; do echo \"[$x]\"; done", "[a]\n[b c]\n");
+    assert_oursh!("set -- a b; for x\ndo echo $x; done", "a\nb\n");
+    // The status is the body's last, or zero if it never ran.
+    assert_oursh!("for x in a b; do false; done || echo failed", "failed\n");
+    assert_oursh!("false; for x in; do echo no; done && echo ok", "ok\n");
+    assert_oursh!("for x in a b; do echo $x; done | wc -l", "2\n");
+    assert_oursh!("for x in a b; do echo $x; done > /dev/null");
+    assert_oursh!("readonly x; for x in a; do echo no; done || echo failed", "failed\n",
+                  "oursh: x: readonly variable\n");
+    assert_oursh!(! "for 1 in a; do echo no; done");
+    assert_oursh!(! "for x of a; do echo no; done");
+}
+
+#[test]
+fn case_command() {
+    assert_oursh!("case b in a) echo a;; b) echo b;; esac", "b\n");
+    assert_oursh!("case foo.rs in *.txt) echo text;; *.rs) echo rust;; esac", "rust\n");
+    // Only the first matching pattern's commands run.
+    assert_oursh!("case x in ?) echo 1;; x) echo 2;; *) echo 3;; esac", "1\n");
+    assert_oursh!("case b in a|b|c) echo abc;; esac", "abc\n");
+    assert_oursh!("case 5 in [0-9]) echo digit;; esac", "digit\n");
+    assert_oursh!("x='a b'; case $x in 'a b') echo quoted;; esac", "quoted\n");
+    assert_oursh!("case '*' in \\*) echo star;; esac", "star\n");
+    assert_oursh!("case x in '*') echo star;; *) echo other;; esac", "other\n");
+    assert_oursh!("p='[ab]'; case a in $p) echo a;; esac", "a\n");
+    assert_oursh!("p='[ab]'; case a in \"$p\") echo a;; *) echo none;; esac", "none\n");
+    assert_oursh!("case x\nin\n  (x)\n    echo one\n    echo two\n    ;;\n  y) echo y\nesac",
+                  "one\ntwo\n");
+    assert_oursh!("case x in x) echo last; esac", "last\n");
+    // The status is the commands', or zero without a match.
+    assert_oursh!("case x in x) false;; esac || echo failed", "failed\n");
+    assert_oursh!("false; case x in y) echo no;; esac && echo ok", "ok\n");
+    assert_oursh!("false; case x in x) ;; esac && echo ok", "ok\n");
+    assert_oursh!("case x in esac && echo ok", "ok\n");
+    assert_oursh!("for x in a 1 -; do case $x in [a-z]) echo letter;; [0-9]) echo digit;; \
+                   *) echo other;; esac; done", "letter\ndigit\nother\n");
+    assert_oursh!(! "case x in x) echo;; y");
+}
+
+#[test]
+fn reserved_words() {
+    // Reserved words are only recognized where a command starts, or ends.
+    assert_oursh!("echo fi then done case esac do if elif else while until for",
+                  "fi then done case esac do if elif else while until for\n");
+    assert_oursh!("if true; then echo fi; fi", "fi\n");
+    assert_oursh!("for x in do done; do echo $x; done", "do\ndone\n");
+    assert_oursh!("case esac in (if|esac) echo esac;; esac", "esac\n");
+    assert_oursh!("x=fi; echo $x done", "fi done\n");
+    assert_oursh!(! "echo a; fi");
+}
+
+#[test]
+fn pipefail() {
+    assert_oursh!("false | true && echo ok", "ok\n");
+    assert_oursh!("set -o pipefail; false | true || echo failed", "failed\n");
+    assert_oursh!("set -o pipefail; true | true && echo ok", "ok\n");
+    // The status is the last command's to fail.
+    let output = oursh!("set -o pipefail; sh -c 'exit 3' | sh -c 'exit 2'");
+    assert_eq!(Some(2), output.status.code());
+    let output = oursh!("set -o pipefail; sh -c 'exit 3' | true");
+    assert_eq!(Some(3), output.status.code());
+    let output = oursh!("sh -c 'exit 3' | true");
+    assert_eq!(Some(0), output.status.code());
 }
 
 #[test]
@@ -113,26 +734,549 @@ fn chained_pipeline_command() {
 }
 
 #[test]
-#[ignore]
 fn assignment_command() {
     assert_oursh!("PI=3.1415 printenv PI", "3.1415\n");
     assert_oursh!("X=1 Y=2 printenv X Y", "1\n2\n");
-    assert_oursh!("X=1; printenv X", "\n");
+    assert_oursh!("X=1; printenv X || echo unset", "unset\n");
+}
+
+#[test]
+fn export_command() {
+    assert_oursh!("X=1; export X; printenv X", "1\n");
+    assert_oursh!("export X=1; printenv X", "1\n");
+    assert_oursh!("export X; X=2; printenv X", "2\n");
+    assert_oursh!("X=1; export X; X=2 printenv X; printenv X", "2\n1\n");
+    let output = oursh!("export X=1; export");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("export X='1'\n"));
+    // It's a command like any other, with assignments and redirections.
+    assert_oursh!("export A=1 > /dev/null; printenv A", "1\n");
+    let file = temp!("export");
+    assert_oursh!(format!("export X=1; export -p > {0}; grep \"^export X=\" {0}; rm {0}", file),
+                  "export X='1'\n");
+    assert_oursh!("FOO=1 export BAR=2; echo $FOO; printenv BAR", "1\n2\n");
+    assert_oursh!("X=1; export X 2>&1; printenv X", "1\n");
+    // Its arguments are all expanded first, and assignments aren't split.
+    assert_oursh!("X=0; export X=1 Y=$X; printenv Y", "0\n");
+    assert_oursh!("x='a b'; export X=$x; printenv X", "a b\n");
+}
+
+#[test]
+fn assignment_only_command() {
+    assert_oursh!("FOO=bar; echo $FOO", "bar\n");
+    assert_oursh!("FOO=; echo \"[$FOO]\"", "[]\n");
+    assert_oursh!("FOO='a b'; echo \"$FOO\"", "a b\n");
+    assert_oursh!("A=1 B=$A; echo $A$B", "11\n");
+    assert_oursh!("FOO=\"$HOME\"; echo $FOO", format!("{}\n", env!("HOME")));
+    assert_oursh!("echo a=b", "a=b\n");
+}
+
+#[test]
+fn assignment_expansion() {
+    let home = env!("HOME");
+    assert_oursh!("D=~/src; echo $D", format!("{}/src\n", home));
+    assert_oursh!("D=~; echo $D", format!("{}\n", home));
+    assert_oursh!("P=$HOME/bin; echo $P", format!("{}/bin\n", home));
+    assert_oursh!("P=~/bin:~/sbin; echo $P", format!("{0}/bin:{0}/sbin\n", home));
+    assert_oursh!("P=/bin:~:~x; echo $P", format!("/bin:{}:~x\n", home));
+    assert_oursh!("P=~/bin:~/sbin printenv P", format!("{0}/bin:{0}/sbin\n", home));
+    assert_oursh!("P='~/bin:~/sbin'; echo \"$P\"", "~/bin:~/sbin\n");
+    assert_oursh!("P=\"~/bin:~/sbin\"; echo \"$P\"", "~/bin:~/sbin\n");
+    assert_oursh!("P=/bin:\\~; echo \"$P\"", "/bin:~\n");
+    assert_oursh!("P='$HOME'; echo \"$P\"", "$HOME\n");
+    assert_oursh!("P='a  b*'; echo \"$P\"", "a  b*\n");
+    assert_oursh!("echo ~/bin:~/sbin", format!("{}/bin:~/sbin\n", home));
+}
+
+#[test]
+fn assignment_prefix_command() {
+    assert_oursh!("PI=3.1415 printenv PI", "3.1415\n");
+    assert_oursh!("X=1 Y=2 printenv X Y", "1\n2\n");
+    assert_oursh!("X=1 printenv X; echo \"[$X]\"", "1\n[]\n");
+    assert_oursh!("X=0; X=1 printenv X; echo $X", "1\n0\n");
+    assert_oursh!("X=1 Y=2 sh -c 'echo $X$Y'; echo \"[$X$Y]\"", "12\n[]\n");
+    // Functions get the assignments for the call only too.
+    assert_oursh!("f() { echo $X; }; X=1 f; echo \"[$X]\"", "1\n[]\n");
+    // Without a command, or for a special builtin, they're the shell's own,
+    // and aren't exported.
+    assert_oursh!("X=1; echo $X; sh -c 'echo \"[$X]\"'", "1\n[]\n");
+    assert_oursh!("X=1 :; echo $X", "1\n");
+}
+
+#[test]
+fn special_parameters() {
+    assert_oursh!("echo $0", "oursh\n");
+    // Subshells have the shell's own process ID.
+    let output = oursh!("echo $$; (echo ${$})");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let pids = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(2, pids.len());
+    assert_eq!(pids[0], pids[1]);
+    assert!(pids[0].parse::<u32>().is_ok());
+    // `$!` is empty until there's a background job.
+    assert_oursh!("echo \"[$!]\"", "[]\n");
+    assert_oursh!("sleep 0 & [ $! -gt 0 ] && [ $! != $$ ] && wait $! && echo yes", "yes\n");
+}
+
+#[test]
+fn last_status() {
+    assert_oursh!("echo $?", "0\n");
+    assert_oursh!("false; echo $? ${?}; echo $?", "1 1\n0\n");
+    assert_oursh!("sh -c 'exit 7'; echo $?", "7\n");
+    assert_oursh!("true | false; echo $?", "1\n");
+    assert_oursh!("! true; echo $?", "1\n");
+    assert_oursh!("(exit 3); echo $?", "3\n");
+    assert_oursh!("false; (echo $?)", "1\n");
+    assert_oursh!("f() { return 4; }; f; echo $?", "4\n");
+    assert_oursh!("cat < /nonexistent; echo $?", "1\n",
+                  "oursh: /nonexistent: No such file or directory (os error 2)\n");
+    // The condition's status is seen by the branch it chose.
+    assert_oursh!("if sh -c 'exit 2'; then :; else echo $?; fi", "2\n");
+    assert_oursh!("false || echo $?", "1\n");
+    assert_oursh!("case $? in 0) echo zero;; esac", "zero\n");
+    // `return` and `exit` default to it.
+    assert_oursh!("f() { false; return; }; f || echo failed", "failed\n");
+    assert_eq!(Some(3), oursh!("sh -c 'exit 3'; exit").status.code());
+    let output = oursh!("trap 'echo exited $?' EXIT; false; exit 5");
+    assert_eq!("exited 5\n", String::from_utf8_lossy(&output.stdout));
+    assert_eq!(Some(5), output.status.code());
 }
 
 #[test]
 fn variable_command() {
     assert_oursh!("X=1; echo $X", "1\n");
-    assert_oursh!("export FOO=1 BAR=$FOO; echo $BAR", "1\n");
+    assert_oursh!("export FOO=1; export BAR=$FOO; echo $BAR", "1\n");
     assert_oursh!("echo $BAR", "\n");
     assert_oursh!("echo $", "$\n");
     assert_oursh!("echo ' $ '", " $ \n");
-    assert_oursh!("echo \" $$ $ \"", " $$ $ \n");
+    assert_oursh!("echo \" \\$\\$ $ \"", " $$ $ \n");
+    // Executables get the expanded words too, and so does the command name.
+    assert_oursh!("X=a; /bin/echo $X ${X}b \"$X\" '$X'", "a ab a $X\n");
+    assert_oursh!("C=echo; $C hi; F='printf %s-'; $F a b", "hi\na-b-");
+    assert_oursh!("f() { echo $1; }; F=f; $F called", "called\n");
+}
+
+#[test]
+fn parameter_modifiers() {
+    assert_oursh!("echo ${X:-default} \"${X-a  b}\"", "default a  b\n");
+    assert_oursh!("X=; echo \"[${X:-default}]\" \"[${X-default}]\"", "[default] []\n");
+    assert_oursh!("X=x; echo ${X:+alternate} \"[${Y:+alternate}]\"", "alternate []\n");
+    assert_oursh!("set -- a; echo ${1:-none} ${2:-none}", "a none\n");
+    // The word is split and matched as a pattern only where it's unquoted.
+    assert_oursh!("set -- ${X:-\"a b\"} ${X:-a b}; echo $#", "3\n");
+    assert_oursh!("echo ${X:-'/bi*'} ${X:-\"/\"bi*}", "/bi* /bin\n");
+    // The default isn't assigned.
+    assert_oursh!("echo ${X:-default}; echo \"[$X]\"", "default\n[]\n");
+    assert_oursh!("X=x; echo ${X:?}", "x\n");
+    // An error ends a shell which isn't interactive.
+    let output = oursh!("echo ${X:?must be set}; echo unreachable");
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!("", String::from_utf8_lossy(&output.stdout));
+    assert_eq!("oursh: X: must be set\n", String::from_utf8_lossy(&output.stderr));
+    assert_oursh!("(: ${X?}) || echo failed", "failed\n", "oursh: X: parameter not set\n");
+    assert_oursh!("X=; (: ${X:?}) || echo failed", "failed\n",
+                  "oursh: X: parameter null or not set\n");
+}
+
+#[test]
+fn length_and_removal() {
+    assert_oursh!("f=archive.tar.gz; echo ${#f} ${f%.*} ${f%%.*} ${f#*.} ${f##*.}",
+                  "14 archive.tar archive tar.gz gz\n");
+    assert_oursh!("set -- a bc; echo ${#1} ${#2} ${#@}", "1 2 2\n");
+    // The pattern is expanded first, and its quoted characters are literal.
+    assert_oursh!("f=archive.tar.gz; ext=.gz; echo ${f%$ext} ${f%\"${ext#.}\"}",
+                  "archive.tar archive.tar.\n");
+    assert_oursh!("p='*'; s='*a*b'; echo \"[${s##$p}]\" \"${s##\"$p\"}\" \"${s#?*\\*}\"",
+                  "[] a*b b\n");
+    assert_oursh!("dir=/usr/local/bin; for d in ${dir%/*} ${dir##*/}; do echo $d; done",
+                  "/usr/local\nbin\n");
+}
+
+#[test]
+fn command_substitution() {
+    assert_oursh!("x=$(echo hi); echo $x", "hi\n");
+    assert_oursh!("echo `echo back` \"`echo \\\"q\\\"`\"", "back q\n");
+    assert_oursh!("echo $(echo $(echo nested)) `echo \\`echo nested\\``",
+                  "nested nested\n");
+    assert_oursh!("f() { echo called; }; echo $(f)", "called\n");
+    // Only the trailing newlines are removed, and unquoted output is split.
+    assert_oursh!("echo \"[$(printf 'a\\n\\nb\\n\\n')]\"", "[a\n\nb]\n");
+    assert_oursh!("printf '[%s]' $(echo ' a  b ') \"$(echo ' a  b ')\"", "[a][b][ a  b ]");
+    assert_oursh!("echo \"$(echo \")\" $(echo \"(\"))\"", ") (\n");
+    assert_oursh!("cat <<EOF\n$(echo a) `echo b`\nEOF", "a b\n");
+    // Nothing done in the command changes the shell itself.
+    assert_oursh!("x=0; echo $(x=1; echo $x) $x", "1 0\n");
+    // The status of a command without a name is the last substitution's.
+    assert_oursh!("x=$(false); echo $?", "1\n");
+    assert_oursh!("$(exit 3) || echo $?", "3\n");
+    assert_oursh!("echo $(exit 4) $?", "4\n");
+    assert_oursh!("false; x=1; echo $?", "0\n");
+    // The `)` of a pattern doesn't end the command.
+    assert_oursh!("echo \"$(case x in x) echo in;; esac)\" $(case y in (y) echo out; esac)",
+                  "in out\n");
+    // A syntax error within fails the command using it.
+    let output = oursh!("echo \"$(fi)\"; echo unreachable");
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!("", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn arithmetic_expansion() {
+    assert_oursh!("echo $((1 + 2 * 3)) $(( (1 + 2) * 3 )) $((7 / 2)) $((-7 % 3))",
+                  "7 9 3 -1\n");
+    assert_oursh!("echo $((1 << 4)) $((0x10 + 010)) $((~0)) $((!5)) $((2 > 1 ? 10 : 20))",
+                  "16 24 -1 0 10\n");
+    // Variables are named with or without a `$`, and may be assigned.
+    assert_oursh!("i=5; echo $((i * 2)) $(($i + 1)) $((unset))", "10 6 0\n");
+    assert_oursh!("x=1; echo $((x += 4)) $((x++)) $x $((--x))", "5 5 6 5\n");
+    assert_oursh!("i=0; while [ $i -lt 3 ]; do i=$((i + 1)); done; echo $i", "3\n");
+    assert_oursh!("echo \"$(( $(echo 6) * 7 ))\"", "42\n");
+    assert_oursh!("cat <<EOF\n$((2 + 2))\nEOF", "4\n");
+    // Without a closing `))` it's a command substitution of a subshell.
+    assert_oursh!("echo $((echo a) | tr a b)", "b\n");
+    // An error in the expression ends the shell.
+    let output = oursh!("echo $((1 / 0)); echo unreachable");
+    assert_eq!("", String::from_utf8_lossy(&output.stdout));
+    assert_eq!("oursh: $((1 / 0)): division by zero\n",
+               String::from_utf8_lossy(&output.stderr));
+    assert_eq!(Some(1), output.status.code());
+    assert_oursh!(! "readonly r=1; echo $((r = 2))");
+}
+
+#[test]
+fn field_splitting() {
+    assert_oursh!("X=' a  b '; printf '[%s]' $X", "[a][b]");
+    assert_oursh!("X=' a  b '; printf '[%s]' \"$X\"", "[ a  b ]");
+    assert_oursh!("X=a:b::c; IFS=:; printf '[%s]' $X", "[a][b][][c]");
+    assert_oursh!("X='a b'; IFS=; printf '[%s]' $X", "[a b]");
+    assert_oursh!("X=; printf '[%s]' $X x \"$X\"", "[x][]");
+    assert_oursh!("set -- 'a b' c; printf '[%s]' $@", "[a][b][c]");
+    assert_oursh!("set -- a b; IFS=:; printf '[%s]' \"$*\"", "[a:b]");
+}
+
+#[test]
+fn quoted_positionals() {
+    // Each argument is passed through `"$@"` whole, to functions and
+    // builtins alike.
+    assert_oursh!("inner() { echo $#; printf '[%s]' \"$@\"; echo; }; \
+                   outer() { inner \"$@\"; }; outer 'a b' c",
+                  "2\n[a b][c]\n");
+    assert_oursh!("set -- 'a  b' c; set -- \"$@\"; echo $#; printf '[%s]' \"$1\"", "2\n[a  b]");
+    assert_oursh!("set -- a 'b c' d; printf '[%s]' \"x$@y\"", "[xa][b c][dy]");
+    assert_oursh!("set -- ''; printf '[%s]' \"$@\"", "[]");
+    // Without any positional parameters there's no field at all.
+    assert_oursh!("f() { echo $#; }; f \"$@\"; f \"x$@\" \"\"", "0\n2\n");
+}
+
+#[test]
+fn pathname_expansion() {
+    assert_oursh!("echo tests/*.rs", "tests/oursh.rs tests/posix.rs tests/scripts.rs\n");
+    assert_oursh!("echo tests/[op]*.r?", "tests/oursh.rs tests/posix.rs\n");
+    assert_oursh!("P='tests/s*.rs'; echo $P \"$P\"", "tests/scripts.rs tests/s*.rs\n");
+    assert_oursh!("echo 'tests/*'.rs tests/\\*.rs tests/none*", "tests/*.rs tests/*.rs tests/none*\n");
+}
+
+#[test]
+fn arithmetic_command() {
+    assert_oursh!("(( 1 + 1 )) && echo yes", "yes\n");
+    assert_oursh!("(( 1 - 1 )) || echo no", "no\n");
+    assert_oursh!("x=5; if (( x > 3 )); then echo big; fi", "big\n");
+    assert_oursh!("i=1; (( i = i + 1 )); (( i *= 3, i++ )); echo $i", "7\n");
+    assert_oursh!("x=2; (( $x == x )) && echo same", "same\n");
+    assert_oursh!("(( 1 / 0 )) || echo failed", "failed\n",
+                  "oursh: (( 1 / 0 )): division by zero\n");
+}
+
+#[test]
+fn builtin_read() {
+    let file = temp!("read");
+    assert_oursh!(format!("printf ' x  y z \\n' > {0}; read a b < {0}; \
+                           printf '[%s]' \"$a\" \"$b\"", file),
+                  "[x][y z]");
+    assert_oursh!(format!("printf 'x:y:z\\n' > {0}; IFS=:; read a b c < {0}; \
+                           printf '[%s]' \"$a\" \"$b\" \"$c\"", file),
+                  "[x][y][z]");
+    assert_oursh!(format!("printf ' x y \\n' > {0}; IFS=; read a b < {0}; \
+                           printf '[%s]' \"$a\" \"$b\"", file),
+                  "[ x y ][]");
+    assert_oursh!(format!("printf 'a\\\\ b c\\n' > {0}; read a b < {0}; read -r c d < {0}; \
+                           printf '[%s]' \"$a\" \"$b\" \"$c\" \"$d\"", file),
+                  "[a b][c][a\\][b c]");
+    assert_oursh!(format!("printf 'x' > {0}; read a < {0} || echo \"$a\"; rm {0}", file), "x\n");
+}
+
+#[test]
+fn builtin_readonly() {
+    assert_oursh!("readonly X=1 Y; readonly", "readonly X='1'\nreadonly Y\n");
+    assert_oursh!("X=1; readonly X; echo $X", "1\n");
+    assert_oursh!("readonly X=1; X=2 || echo $X", "1\n",
+                  "oursh: X: readonly variable\n");
+    assert_oursh!("readonly X=1; X=2 echo no || echo $X", "1\n",
+                  "oursh: X: readonly variable\n");
+    assert_oursh!("readonly X=1; readonly X=2 || echo $X", "1\n",
+                  "oursh: X: readonly variable\n");
+    assert_oursh!("readonly X=1; export X=2 || echo $X", "1\n",
+                  "oursh: X: readonly variable\n");
+    assert_oursh!("readonly X=1; unset X || echo $X", "1\n",
+                  "oursh: unset: X: cannot unset: readonly variable\n");
+    assert_oursh!("readonly X=1; read X < /dev/null || echo $X", "1\n",
+                  "oursh: X: readonly variable\n");
+    assert_oursh!("readonly X=1; (( X += 1 )) || echo $X", "1\n",
+                  "oursh: (( X += 1 )): X: readonly variable\n");
+    assert_oursh!("readonly 1x || echo failed", "failed\n",
+                  "oursh: readonly: `1x': not a valid identifier\n");
+}
+
+#[test]
+fn builtin_unset() {
+    assert_oursh!("X=1; unset X; echo \"[$X]\"", "[]\n");
+    assert_oursh!("export X=1; unset X; printenv X || echo unset", "unset\n");
+    assert_oursh!("f() { echo f; }; unset -f f; f 2> /dev/null || echo gone", "gone\n");
 }
 
 #[test]
 fn background_command() {
-    assert_oursh!("sleep 1 & echo 1", "1\n");
-    // TODO: How to test the output with a PID in it?
-    // assert_oursh!("sleep 1 & echo 1", "1\n", "[1]\t(\d*)\n");
+    assert_oursh!("sleep 1 & echo 1", "1\n", "");
+    // Only an interactive shell says which job was started.
+    let output = shell!("target/debug/oursh", &["--noprofile", "--norc", "-i"],
+                        "sleep 0 & (sleep 0) & echo $!; wait");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let pids = stderr.lines().map(|line| {
+        line.split_once(' ').expect("job and pid")
+    }).collect::<Vec<_>>();
+    assert_eq!(vec!["[1]", "[2]"], pids.iter().map(|(job, _)| *job).collect::<Vec<_>>());
+    assert_eq!(format!("{}\n", pids[1].1), stdout);
+    // Jobs are numbered after the highest, even when others are gone.
+    assert_oursh!("sleep 0 & sleep 1 & wait %1; sleep 0 & wait %3 && echo third", "third\n");
+    // Anything else is run as one job, by a copy of the shell.
+    assert_oursh!("{ sleep 0; echo a; } & wait; echo b", "a\nb\n");
+    assert_oursh!("false && echo no & wait %1 || echo failed", "failed\n");
+    assert_oursh!("cd /tmp; cd / & wait; pwd", "/tmp\n");
+    assert_oursh!("x=1; x=2 & wait; echo $x", "1\n");
+    assert_oursh!("f() { x=2; }; x=1; f & wait; echo $x", "1\n");
+    assert_oursh!("{ sleep 1; } & jobs | cut -f4-", "{ sleep 1; }\n");
+}
+
+#[test]
+#[cfg(feature = "modern")]
+fn last_argument() {
+    assert_oursh!("echo a b c; echo $_", "a b c\nc\n");
+    assert_oursh!("true; echo $_", "true\n");
+}
+
+#[test]
+fn function_command() {
+    assert_oursh!("f() { echo hi; }; f", "hi\n");
+    assert_oursh!("f() { echo $# $1 $2; }; f a b", "2 a b\n");
+    assert_oursh!("f() { set -- x y z; echo $#; }; set -- a b; f c; echo $# $1",
+                  "3\n2 a\n");
+    assert_oursh!("f() { echo $1; }; set -- a; f b; echo $1", "b\na\n");
+    // The body is any compound command.
+    assert_oursh!("f() (x=sub; echo $x); x=outer; f; echo $x", "sub\nouter\n");
+    assert_oursh!("f()\nif [ $1 = a ]; then echo yes; fi; f a", "yes\n");
+    assert_oursh!("f() for x; do echo $x; done; f a b", "a\nb\n");
+    // Functions are found before executables.
+    assert_oursh!("ls() { echo mine; }; ls", "mine\n");
+}
+
+#[test]
+fn builtin_return() {
+    assert_oursh!("f() { echo a; return; echo b; }; f", "a\n");
+    assert_oursh!("f() { return 3; }; f || echo failed", "failed\n");
+    assert_oursh!("f() { return 256; }; f && echo ok", "ok\n");
+    // Only the innermost function is returned from, from within any command.
+    assert_oursh!("f() { for x in a b c; do echo $x; [ $x = b ] && return; done; }; \
+                   g() { f; echo g; }; g", "a\nb\ng\n");
+    assert_oursh!("f() { while true; do if true; then return 1; fi; done; }; f || echo failed",
+                  "failed\n");
+    assert_oursh!("f() { set -- x; return; }; set -- a; f; echo $1", "a\n");
+    // In a subshell it ends only the subshell.
+    assert_oursh!("f() { (return 2) || echo failed; echo after; }; f", "failed\nafter\n");
+    assert_oursh!("f() { echo x | { read y; return 4; }; echo piped; }; f", "piped\n");
+    assert_oursh!("f() { return x; }; f; echo end", "end\n",
+                  "oursh: return: x: numeric argument required\n");
+    assert_oursh!("f() { return 1 2; echo more; }; f", "more\n",
+                  "oursh: return: too many arguments\n");
+    assert_oursh!("return 1; echo continued", "continued\n",
+                  "oursh: return: can only return from a function or sourced script\n");
+}
+
+#[test]
+fn builtin_dot() {
+    assert_oursh!(". ./scripts/sourced.sh", "sourced 0\n");
+    assert_oursh!("PATH=$PATH:scripts; . sourced.sh a b; echo $#", "sourced 2\n0\n");
+    assert_oursh!(". ./scripts/returned.sh || echo failed", "returned\nfailed\n");
+
+    let output = oursh!(". ./scripts/missing.sh; echo unreachable");
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+
+    // A syntax error ends a shell which isn't interactive, with the status 2.
+    let output = oursh!(". ./scripts/syntax_error.sh; echo unreachable");
+    assert_eq!(Some(2), output.status.code());
+    assert!(output.stdout.is_empty());
+    let output = shell!("target/debug/oursh", &["--noprofile", "--norc", "-i"],
+                        ". ./scripts/syntax_error.sh || echo failed; echo continued");
+    assert_eq!("failed\ncontinued\n", String::from_utf8_lossy(&output.stdout));
+    assert_eq!(Some(0), output.status.code());
+}
+
+#[test]
+fn builtin_trap() {
+    assert_oursh!("trap 'echo bye' EXIT; trap", "trap -- 'echo bye' EXIT\nbye\n");
+    assert_oursh!("trap 'echo bye' 0 INT; trap - EXIT; trap",
+                  "trap -- 'echo bye' INT\n");
+    assert_oursh!("trap 'echo int' SIGINT; trap INT; trap", "");
+    assert_oursh!("trap 'echo hup' HUP; kill -HUP $$; echo after", "hup\nafter\n");
+    assert_oursh!("trap '' TERM; kill -TERM $$; echo alive", "alive\n");
+    assert_oursh!("trap '' TERM; sh -c 'kill -TERM $$; echo ignored'", "ignored\n");
+    assert_oursh!(! "trap 'echo term' TERM; trap - TERM; kill -TERM $$; echo alive");
+    assert_oursh!(! "trap 'echo kill' KILL");
+}
+
+#[test]
+fn exit_trap() {
+    assert_oursh!("trap 'echo bye' EXIT; echo hi", "hi\nbye\n");
+    assert_oursh!("trap 'echo bye' EXIT; exit; echo unreachable", "bye\n");
+    let output = oursh!("trap 'echo bye' EXIT; exit 3");
+    assert_eq!(Some(3), output.status.code());
+    assert_eq!(b"bye\n", output.stdout.as_slice());
+    let output = oursh!("trap 'exit 4' EXIT; true");
+    assert_eq!(Some(4), output.status.code());
+    let output = oursh!("trap 'echo bye' EXIT; false");
+    assert_eq!(Some(1), output.status.code());
+    // A subshell doesn't run the shell's trap when it exits.
+    assert_oursh!("trap 'echo bye' EXIT; (exit 3); echo out", "out\nbye\n");
+}
+
+#[test]
+fn builtin_ulimit() {
+    assert_oursh!("ulimit -n 64; ulimit -n; ulimit -Hn", "64\n64\n");
+    assert_oursh!("ulimit -S -t 10; ulimit -t; sh -c 'ulimit -t'", "10\n10\n");
+    assert_oursh!("ulimit -f unlimited; ulimit", "unlimited\n");
+    assert_oursh!(format!("ulimit -c 0; ulimit -a > {0}; grep core {0}; rm {0}",
+                          temp!("ulimit")),
+                  "core file size       (blocks, -c)     0\n");
+    assert_oursh!("ulimit -n lots || echo failed", "failed\n",
+                  "oursh: ulimit: lots: invalid number\n");
+    assert_oursh!("ulimit -z || echo failed", "failed\n",
+                  "oursh: ulimit: -z: invalid option\n\
+                   oursh: ulimit: usage: ulimit [-H|-S] [-a|-cdflmnstuv] [limit]\n");
+}
+
+#[test]
+fn builtin_disown() {
+    assert_oursh!("sleep 0 & sleep 0 & disown; wait %2 || echo gone; wait %1 && echo kept",
+                  "gone\nkept\n");
+    assert_oursh!("sleep 0 & sleep 0 & disown -a; jobs", "");
+    assert_oursh!("disown %1 || echo failed", "failed\n", "oursh: disown: %1: no such job\n");
+}
+
+#[test]
+fn huponexit() {
+    use std::{fs, thread, time::{Duration, Instant}};
+
+    // The shell waits for the job to set its trap, reading from a FIFO the
+    // job writes to once it has, and the job says when it's done, if it
+    // isn't hung up first.
+    let (hup, ready) = (temp!("hup"), temp!("hup-ready"));
+    let text = format!("set -o huponexit; mkfifo {1}; \
+                        sh -c 'trap \"echo hup >> {0}\" HUP; echo > {1}; \
+                               sleep 2 & wait; echo done >> {0}' \
+                          > /dev/null 2> /dev/null & \
+                        cat {1} > /dev/null", hup, ready);
+    let wait_for = |line: &str| {
+        let start = Instant::now();
+        loop {
+            let text = fs::read_to_string(&hup).unwrap_or_default();
+            if text.lines().any(|l| l == line) {
+                return text;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "the job never said {}", line);
+            thread::sleep(Duration::from_millis(10));
+        }
+    };
+
+    // Only an interactive login shell hangs up its jobs.
+    let _ = fs::remove_file(&hup);
+    let _ = fs::remove_file(&ready);
+    assert_oursh!(&text);
+    assert_eq!("done\n", wait_for("done"), "the job was hung up");
+
+    let _ = fs::remove_file(&hup);
+    let _ = fs::remove_file(&ready);
+    let output = shell!("target/debug/oursh", &["--noprofile", "--norc", "-i", "--login"], &text);
+    assert!(output.status.success());
+    wait_for("hup");
+    let _ = fs::remove_file(&hup);
+    let _ = fs::remove_file(&ready);
+}
+
+#[test]
+fn builtin_jobs() {
+    assert_oursh!("sleep 1 & sleep 1 & jobs | cut -f1,3-", "[1]-\t\tsleep 1\n[2]+\t\tsleep 1\n");
+    assert_oursh!("sleep 1 & sleep 1 & sleep 1 & jobs %1 | cut -f1", "[1] \n");
+    assert_oursh!("sleep 1 & jobs -l | cut -f1 | cut -d' ' -f1,3", "[1]+ Running\n");
+    assert_oursh!("sleep 1 & jobs -p | { read p; [ $p = $! ] && echo same; }", "same\n");
+    // Finished jobs are listed once, and then removed.
+    let jobs = temp!("jobs");
+    assert_oursh!(format!("sh -c 'exit 3' & \
+                           until jobs -l > {0}; grep -q Exit {0}; do :; done; \
+                           cut -d' ' -f1,3- {0}; jobs; wait %1 2> /dev/null || echo gone", jobs),
+                  "[1]+ Exit 3\tsh -c exit 3\ngone\n");
+    let _ = std::fs::remove_file(jobs);
+    assert_oursh!("jobs %1 || echo failed", "failed\n", "oursh: jobs: %1: no such job\n");
+    assert_oursh!(! "jobs -x");
+}
+
+#[test]
+fn builtin_wait() {
+    assert_oursh!("wait; echo finished", "finished\n");
+    assert_oursh!("sh -c 'exit 3' & wait %1 || echo failed", "failed\n");
+    assert_oursh!("sh -c 'exit 3' & sleep 0 & wait %1 %2 && echo ok", "ok\n");
+    assert_oursh!("sh -c 'exit 3' & wait && echo ok", "ok\n");
+    assert_oursh!("wait %1 || echo unknown", "unknown\n");
+    // Only the first job to finish is waited for, and removed.
+    assert_oursh!("sleep 1 & sh -c 'exit 3' & wait -n || echo failed; \
+                   wait %2 2> /dev/null || echo gone; wait %1 && echo kept",
+                  "failed\ngone\nkept\n");
+    assert_oursh!("sleep 0 & wait -n && echo ok; wait -n || echo none", "ok\nnone\n");
+
+    assert_oursh!("sh -c 'exit 3' & sleep 0 & wait %- || echo failed", "failed\n");
+    assert_oursh!("sleep 0 & sh -c 'exit 3' & wait %sh || echo failed", "failed\n");
+    assert_oursh!("sleep 0 & sh -c 'exit 3' & wait %?exit %sl && echo ok", "ok\n");
+    assert_oursh!("sleep 0 & sleep 0 & wait %sl || echo ambiguous; wait", "ambiguous\n");
+}
+
+#[test]
+fn signaled_command() {
+    assert_oursh!("sh -c 'kill -SEGV $$' || echo failed", "failed\n",
+                  "Segmentation fault\n");
+    assert_oursh!("! sh -c 'kill $$' && echo negated", "negated\n", "Terminated\n");
+    let output = oursh!("sh -c 'kill $$'");
+    assert_eq!(Some(143), output.status.code());
+    let output = oursh!("sh -c 'kill $$' && echo proceeded");
+    assert_eq!(Some(143), output.status.code());
+    assert!(output.stdout.is_empty());
+    assert_oursh!("echo | sh -c 'kill -9 $$' || echo killed", "killed\n");
+    assert_oursh!("! echo | sh -c 'kill -9 $$' && echo negated", "negated\n");
+}
+
+#[test]
+#[cfg(feature = "modern")]
+fn debug_trap() {
+    assert_oursh!("trap 'echo debug' DEBUG; true; true; true",
+                  "debug\ndebug\ndebug\n");
+    assert_oursh!("trap 'echo $BASH_COMMAND' DEBUG; true a b", "true a b\n");
+    assert_oursh!("trap 'echo debug' DEBUG; trap - DEBUG; true", "debug\n");
+}
+
+#[test]
+#[cfg(feature = "modern")]
+fn err_trap() {
+    assert_oursh!("trap 'echo err' ERR; false; true", "err\n");
+    assert_oursh!("trap 'echo err' ERR; if false; then true; fi; true", "");
+    assert_oursh!("trap 'echo err' ERR; ! true; false || true; true && false; true",
+                  "err\n");
 }
@@ -11,6 +11,15 @@ fn hello_world() {
                     "hello world\n");
 }
 
+#[test]
+fn arguments() {
+    let output = shell!("target/debug/oursh",
+                        &["--noprofile", "./scripts/arguments.sh", "a", "b"], "");
+    assert_eq!("./scripts/arguments.sh 2 a b\n", String::from_utf8_lossy(&output.stdout));
+    // The status is the last command's.
+    assert_eq!(Some(1), output.status.code());
+}
+
 #[test]
 fn multiline() {
     assert_oursh!(> "./scripts/multiline.sh", "12\n");